@@ -1,27 +1,28 @@
+use intcode::{Program, Queue, VM, ExecuteStatus};
 use std::io;
 
-mod instruction;
-mod vm;
+fn run_diagnostics(program: &Program, system_id: i64) -> Vec<i64> {
+    let mut vm = VM::with_io(program, Queue::new(vec![system_id]));
+    vm.debug = true;
 
-use crate::vm::{Program, VM};
+    match vm.run().unwrap() {
+        ExecuteStatus::Halted => vm.into_io().output.into(),
+        ExecuteStatus::NeedInput => panic!("No input to read"),
+        _ => unreachable!(),
+    }
+}
 
 fn main() {
     let mut line = String::new();
     io::stdin().read_line(&mut line).unwrap();
     let program = line.parse::<Program>().unwrap();
 
-    let mut vm = VM::new(&program);
-    vm.debug(true);
-    vm.write_input(&[1]);
-    vm.execute();
+    let output = run_diagnostics(&program, 1);
     println!();
-    println!("Part 1 output: {:?}", vm.output());
+    println!("Part 1 output: {:?}", output);
     println!();
 
-    let mut vm = VM::new(&program);
-    vm.debug(true);
-    vm.write_input(&[5]);
-    vm.execute();
+    let output = run_diagnostics(&program, 5);
     println!();
-    println!("Part 2 output: {:?}", vm.output());
+    println!("Part 2 output: {:?}", output);
 }
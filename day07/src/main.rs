@@ -1,72 +1,32 @@
-use day07::{Program, VM};
+use intcode::{NetworkStatus, Program, Topology};
 use std::io;
-use std::thread;
-use std::sync::mpsc;
 
-fn run_amplifier_series(program: &Program, phases: &[i32]) -> i32 {
-    // Create N channels for N amplifiers. The amplifier with index M will take
-    // input from receivers[M] and send output to senders[M + 1].
-    let (senders, receivers): (Vec<_>, Vec<_>) =
-        (0..phases.len())
-        .map(|_| mpsc::channel())
-        .unzip();
+// In part 1 the amplifiers are in a line, and in part 2 the last one's
+// output goes back around to the first, as well as out to us.
+const SERIES: &str = "A -> B -> C -> D -> E -> out";
+const FEEDBACK_LOOP: &str = "A -> B -> C -> D -> E -> [A, out]";
 
-    // The last amplifier will send output to last_sender.
-    let (last_sender, last_receiver) = mpsc::channel();
+fn run_amplifiers(program: &Program, topology: &Topology, phases: &[i64]) -> i64 {
+    // Each amplifier gets its phase number first, and the first amplifier
+    // also gets the initial input of 0.
+    let mut inputs: Vec<Vec<i64>> = phases.iter().map(|&phase| vec![phase]).collect();
+    inputs[0].push(0);
+    let mut network = topology.network(program, &inputs);
 
-    let mut threads = Vec::with_capacity(phases.len());
-    for (idx, input_receiver) in receivers.into_iter().enumerate() {
-        let phase = phases[idx];
-        let program = program.clone();
-
-        let output_sender = if idx == phases.len() - 1 {
-            last_sender.clone()
-        } else {
-            senders[idx + 1].clone()
-        };
-
-        // Send the amplifier its phase number.
-        senders[idx].send(phase).unwrap();
-
-        let thread_builder = thread::Builder::new()
-            .name(format!("{:?}/{}", phases, phase));
-
-        threads.push(thread_builder.spawn(move || {
-            let mut vm = VM::new(input_receiver, output_sender);
-            vm.load_program(&program);
-            vm.execute();
-        }).unwrap());
-    }
-
-    // Make sure last_sender "hangs up" on the channel when its amplifier has
-    // halted, so our feedback thread knows when to quit.
-    drop(last_sender);
-
-    // Send the initial input of 0 to the first amplifier.
-    senders[0].send(0).unwrap();
-
-    // Create a "feedback" thread, which reads the output from the last
-    // amplifier, and sends it back to the first amplifier, as well as to a
-    // final output channel so we can grab the final output value at the end.
-    let (final_output_sender, final_output_receiver) = mpsc::channel();
-    let feedback_sender = senders[0].clone();
-    let thread_builder = thread::Builder::new()
-        .name(format!("{:?}/feedback", phases));
-    threads.push(thread_builder.spawn(move || {
-        for value in last_receiver.iter() {
-            let _ = feedback_sender.send(value);
-            final_output_sender.send(value).unwrap();
+    let mut last_output = None;
+    loop {
+        match network.run().unwrap() {
+            NetworkStatus::Outgoing { packet, .. } => last_output = Some(packet[0]),
+            NetworkStatus::Halted(_) => {}
+            NetworkStatus::AllHalted => break,
+            status => panic!("amplifiers stopped: {:?}", status),
         }
-    }).unwrap());
-
-    for thread in threads {
-        let _ = thread.join();
     }
 
-    final_output_receiver.try_iter().last().unwrap()
+    last_output.unwrap()
 }
 
-fn next_combination(ary: &mut [i32]) -> bool {
+fn next_combination(ary: &mut [i64]) -> bool {
     // 1. Find the least significant digit that has at least one larger digit
     // somewhere to its right.
     let mut i = ary.len() - 2;
@@ -122,12 +82,14 @@ fn main() {
     let mut line = String::new();
     io::stdin().read_line(&mut line).unwrap();
     let program = line.parse::<Program>().unwrap();
+    let series = SERIES.parse::<Topology>().unwrap();
+    let feedback_loop = FEEDBACK_LOOP.parse::<Topology>().unwrap();
 
     // Part 1
     let mut phases = [0, 1, 2, 3, 4];
     let mut highest_output = 0;
     loop {
-        let output = run_amplifier_series(&program, &phases);
+        let output = run_amplifiers(&program, &series, &phases);
         if output > highest_output {
             highest_output = output;
         }
@@ -142,7 +104,7 @@ fn main() {
     let mut phases = [5, 6, 7, 8, 9];
     let mut highest_output = 0;
     loop {
-        let output = run_amplifier_series(&program, &phases);
+        let output = run_amplifiers(&program, &feedback_loop, &phases);
         if output > highest_output {
             highest_output = output;
         }
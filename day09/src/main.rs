@@ -1,23 +1,21 @@
-use day09::{Program, VM};
+use intcode::{Program, Queue, VM, ExecuteStatus};
 use std::io;
-use std::sync::mpsc;
+
+fn run_boost(program: &Program, mode: i64) -> Vec<i64> {
+    let mut vm = VM::with_io(program, Queue::new(vec![mode]));
+
+    match vm.run().unwrap() {
+        ExecuteStatus::Halted => vm.into_io().output.into(),
+        ExecuteStatus::NeedInput => panic!("No input to read"),
+        _ => unreachable!(),
+    }
+}
 
 fn main() {
     let mut line = String::new();
     io::stdin().read_line(&mut line).unwrap();
     let program = line.parse::<Program>().unwrap();
 
-    let (input_tx, input_rx) = mpsc::channel();
-    let (output_tx, output_rx) = mpsc::channel();
-    let mut vm = VM::new(input_rx, output_tx);
-
-    vm.load_program(&program);
-    input_tx.send(1).unwrap();
-    vm.execute();
-    println!("Part 1 output: {:?}", (output_rx.try_iter().collect::<Vec<_>>()));
-
-    vm.load_program(&program);
-    input_tx.send(2).unwrap();
-    vm.execute();
-    println!("Part 2 output: {:?}", (output_rx.try_iter().collect::<Vec<_>>()));
+    println!("Part 1 output: {:?}", run_boost(&program, 1));
+    println!("Part 2 output: {:?}", run_boost(&program, 2));
 }
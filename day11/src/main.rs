@@ -1,7 +1,6 @@
-use day11::{Program, VM, ExecuteStatus};
+use intcode::{IoDevice, Program, VM, ExecuteStatus};
 use std::collections::HashMap;
 use std::io;
-use std::sync::mpsc;
 
 #[derive(Debug, PartialEq, Eq)]
 enum Color {
@@ -27,74 +26,86 @@ fn print_tiles(tiles: &HashMap<(i32, i32), Color>) {
     }
 }
 
-fn paint_the_thing(program: &Program, start_on_white_tile: bool) {
-    let (input_sender, input_receiver) = mpsc::channel();
-    let (output_sender, output_receiver) = mpsc::channel();
-    let mut vm = VM::new(input_receiver, output_sender);
-    vm.pause_after_output = true;
-
-    vm.load_program(&program);
-
-    let mut position: (i32, i32) = (0, 0);
-    let mut direction: (i32, i32) = (0, -1);
-
-    let mut painted_tiles: HashMap<(i32, i32), Color> = HashMap::new();
+/// The painting robot, as the program sees it: input is the color under
+/// it, and outputs come in pairs of a color to paint and a way to turn.
+struct Robot {
+    position: (i32, i32),
+    direction: (i32, i32),
+    painted_tiles: HashMap<(i32, i32), Color>,
+    /// The color from the first half of a pair.
+    color: Option<Color>,
+}
 
-    if start_on_white_tile {
-        painted_tiles.insert(position, Color::White);
+impl Robot {
+    fn new() -> Self {
+        Robot {
+            position: (0, 0),
+            direction: (0, -1),
+            painted_tiles: HashMap::new(),
+            color: None,
+        }
     }
 
-    loop {
-        match vm.execute() {
-            ExecuteStatus::WaitingForInput => {
-                let color_code = match painted_tiles.get(&position) {
-                    Some(Color::White) => 1,
-                    _ => 0,
-                };
-                input_sender.send(color_code).unwrap();
-            }
+    fn turn(&mut self, turn: i64) {
+        self.direction = match turn {
+            // Turn left
+            0 => match self.direction {
+                (0, -1) => (-1, 0),
+                (-1, 0) => (0, 1),
+                (0, 1)  => (1, 0),
+                (1, 0)  => (0, -1),
+                _ => unreachable!(),
+            },
+            // Turn right
+            1 => match self.direction {
+                (0, -1) => (1, 0),
+                (1, 0) => (0, 1),
+                (0, 1)  => (-1, 0),
+                (-1, 0)  => (0, -1),
+                _ => unreachable!(),
+            },
+            _ => panic!("Invalid direction input"),
+        };
+
+        self.position.0 += self.direction.0;
+        self.position.1 += self.direction.1;
+    }
+}
 
-            ExecuteStatus::PausedAfterOutput => {
-                assert_eq!(vm.execute(), ExecuteStatus::PausedAfterOutput);
+impl IoDevice for Robot {
+    fn read(&mut self) -> Option<i64> {
+        match self.painted_tiles.get(&self.position) {
+            Some(Color::White) => Some(1),
+            _ => Some(0),
+        }
+    }
 
-                let color = match output_receiver.recv().unwrap() {
+    fn write(&mut self, value: i64) {
+        match self.color.take() {
+            None => {
+                self.color = Some(match value {
                     0 => Color::Black,
                     1 => Color::White,
                     _ => panic!("Invalid color input"),
-                };
-
-                painted_tiles.insert(position, color);
-
-                match output_receiver.recv().unwrap() {
-                    0 => {
-                        // Turn left
-                        direction = match direction {
-                            (0, -1) => (-1, 0),
-                            (-1, 0) => (0, 1),
-                            (0, 1)  => (1, 0),
-                            (1, 0)  => (0, -1),
-                            _ => unreachable!(),
-                        };
-                    },
-                    1 => {
-                        // Turn right
-                        direction = match direction {
-                            (0, -1) => (1, 0),
-                            (1, 0) => (0, 1),
-                            (0, 1)  => (-1, 0),
-                            (-1, 0)  => (0, -1),
-                            _ => unreachable!(),
-                        };
-                    },
-                    _ => panic!("Invalid direction input"),
-                };
-
-                position.0 += direction.0;
-                position.1 += direction.1;
+                });
+            }
+            Some(color) => {
+                self.painted_tiles.insert(self.position, color);
+                self.turn(value);
             }
-            ExecuteStatus::Halted => break,
         }
     }
+}
+
+fn paint_the_thing(program: &Program, start_on_white_tile: bool) {
+    let mut robot = Robot::new();
+    if start_on_white_tile {
+        robot.painted_tiles.insert(robot.position, Color::White);
+    }
+
+    let mut vm = VM::with_io(program, robot);
+    assert_eq!(vm.run(), Ok(ExecuteStatus::Halted));
+    let painted_tiles = vm.into_io().painted_tiles;
 
     println!("Number of painted tiles: {}", painted_tiles.len());
     println!();
@@ -1,14 +1,11 @@
-mod instruction;
 mod utils;
 mod vm;
 
-use wasm_bindgen::prelude::*;
-
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
 #[cfg(feature = "wee_alloc")]
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
-pub use vm::{Program, VM, ExecuteStatus};
+pub use vm::{Access, Program, VM, ExecuteStatus};
 
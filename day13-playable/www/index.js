@@ -81,8 +81,24 @@ vm.set_memory(0, 2n);
 let paddle_x = 0;
 let ball_x = 0;
 
+// Hand control back to the browser at least this often, so a program that
+// never outputs anything can't freeze the tab.
+const CYCLES_PER_FRAME = 1000000;
+
+// The rest of an x, y, tile triple. It's only a few instructions away, so
+// top up the budget rather than splitting it across frames.
+const expectOutput = () => {
+    let status = vm.execute();
+    if (status === ExecuteStatus.BudgetExhausted) {
+        vm.set_cycle_budget(CYCLES_PER_FRAME);
+        status = vm.execute();
+    }
+    if (status !== ExecuteStatus.Output) throw 'expected more output!';
+};
+
 const run = () => {
     let numFrames = 0;
+    vm.set_cycle_budget(CYCLES_PER_FRAME);
     while (true) {
         statusEl.textContent = 'Running';
         switch (vm.execute()) {
@@ -100,8 +116,8 @@ const run = () => {
                 }
                 break;
             case ExecuteStatus.Output:
-                if (vm.execute() !== ExecuteStatus.Output) throw 'expected more output!';
-                if (vm.execute() !== ExecuteStatus.Output) throw 'expected more output!';
+                expectOutput();
+                expectOutput();
 
                 const x = Number(vm.recv_output());
                 const y = Number(vm.recv_output());
@@ -134,6 +150,9 @@ const run = () => {
                     window.setTimeout(run, 200);
                 }
                 return;
+            case ExecuteStatus.BudgetExhausted:
+                window.requestAnimationFrame(run);
+                return;
             case ExecuteStatus.Halted:
                 statusEl.textContent = `Halted after ${vm.cycles} cycles`;
                 return;
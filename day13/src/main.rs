@@ -1,10 +1,12 @@
-use day13::{Program, VM, ExecuteStatus};
+use intcode::{IoDevice, Profile, Program, VM, ExecuteStatus};
 use num_enum::TryFromPrimitive;
 use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::env;
 use std::fmt;
+use std::fs;
 use std::io;
-use std::sync::mpsc;
+use std::process;
 use std::thread;
 use std::time::Duration;
 
@@ -63,33 +65,80 @@ impl fmt::Display for Screen {
     }
 }
 
-fn part1(program: &Program) {
-    let (_input_sender, input_receiver) = mpsc::channel();
-    let (output_sender, output_receiver) = mpsc::channel();
-    let mut vm = VM::new(input_receiver, output_sender);
-    vm.pause_after_output = true;
+/// The arcade cabinet: outputs come in threes, drawing a tile or setting the
+/// score, and input is the joystick, which just follows the ball.
+struct Arcade {
+    screen: Screen,
+    score: i64,
+    ball_x: i64,
+    paddle_x: i64,
+    /// The outputs so far of the next three.
+    pending: Vec<i64>,
+    /// Whether to show the screen every time it changes.
+    display: bool,
+}
+
+impl Arcade {
+    fn new(display: bool) -> Self {
+        Arcade {
+            screen: Screen::new(),
+            score: 0,
+            ball_x: 0,
+            paddle_x: 0,
+            pending: Vec::new(),
+            display,
+        }
+    }
+}
 
-    vm.load_program(&program);
+impl IoDevice for Arcade {
+    fn read(&mut self) -> Option<i64> {
+        let input = if self.paddle_x < self.ball_x {
+            1
+        } else if self.paddle_x > self.ball_x {
+            -1
+        } else {
+            0
+        };
+        Some(input)
+    }
 
-    let mut screen = Screen::new();
+    fn write(&mut self, value: i64) {
+        self.pending.push(value);
+        if self.pending.len() < 3 {
+            return;
+        }
+        let (x, y, value) = (self.pending[0], self.pending[1], self.pending[2]);
+        self.pending.clear();
+
+        if x == -1 && y == 0 {
+            self.score = value;
+        } else {
+            let tile = Tile::try_from(value as u8)
+                .expect("Invalid tile");
+
+            if tile == Tile::Ball {
+                self.ball_x = x;
+            } else if tile == Tile::Paddle {
+                self.paddle_x = x;
+            }
 
-    loop {
-        match vm.execute() {
-            ExecuteStatus::PausedAfterOutput => {
-                assert_eq!(vm.execute(), ExecuteStatus::PausedAfterOutput);
-                assert_eq!(vm.execute(), ExecuteStatus::PausedAfterOutput);
+            self.screen.tiles.insert((x, y), tile);
+        }
 
-                let x = output_receiver.recv().unwrap();
-                let y = output_receiver.recv().unwrap();
-                let tile = Tile::try_from(output_receiver.recv().unwrap() as u8)
-                    .expect("Invalid tile");
+        if self.display && self.score > 0 {
+            println!("{}", self.screen);
+            println!("Score: {}", self.score);
 
-                screen.tiles.insert((x, y), tile);
-            }
-            ExecuteStatus::WaitingForInput => unreachable!(),
-            ExecuteStatus::Halted => break,
+            thread::sleep(Duration::from_millis(10));
         }
     }
+}
+
+fn part1(program: &Program) {
+    let mut vm = VM::with_io(program, Arcade::new(false));
+    assert_eq!(vm.run(), Ok(ExecuteStatus::Halted));
+    let screen = vm.into_io().screen;
 
     println!("{}", screen);
 
@@ -100,66 +149,24 @@ fn part1(program: &Program) {
     println!("Number of blocks: {}", num_blocks);
 }
 
-fn part2(program: &Program, display: bool) {
-    let (input_sender, input_receiver) = mpsc::channel();
-    let (output_sender, output_receiver) = mpsc::channel();
-    let mut vm = VM::new(input_receiver, output_sender);
-    vm.pause_after_output = true;
-
-    vm.load_program(&program);
-    vm.patch_program(0, 2);
-
-    let mut screen = Screen::new();
-    let mut score = 0;
-    let mut ball_x = 0;
-    let mut paddle_x = 0;
-
-    loop {
-        match vm.execute() {
-            ExecuteStatus::PausedAfterOutput => {
-                assert_eq!(vm.execute(), ExecuteStatus::PausedAfterOutput);
-                assert_eq!(vm.execute(), ExecuteStatus::PausedAfterOutput);
-
-                let x = output_receiver.recv().unwrap();
-                let y = output_receiver.recv().unwrap();
-
-                if x == -1 && y == 0 {
-                    score = output_receiver.recv().unwrap();
-                } else {
-                    let tile = Tile::try_from(output_receiver.recv().unwrap() as u8)
-                        .expect("Invalid tile");
-
-                    if tile == Tile::Ball {
-                        ball_x = x;
-                    } else if tile == Tile::Paddle {
-                        paddle_x = x;
-                    }
-
-                    screen.tiles.insert((x, y), tile);
-                }
-
-                if display && score > 0 {
-                    println!("{}", screen);
-                    println!("Score: {}", score);
-
-                    thread::sleep(Duration::from_millis(10));
-                }
-            }
-            ExecuteStatus::WaitingForInput => {
-                let input = if paddle_x < ball_x {
-                    1
-                } else if paddle_x > ball_x {
-                    -1
-                } else {
-                    0
-                };
-                input_sender.send(input).unwrap();
-            }
-            ExecuteStatus::Halted => break,
-        }
+fn part2(program: &Program, display: bool, profile: Option<&str>) {
+    let mut vm = VM::with_io(program, Arcade::new(display));
+    vm.set_memory(0, 2);
+    if profile.is_some() {
+        vm.profile = Some(Profile::new(program));
     }
 
-    println!("Final score: {}", score);
+    assert_eq!(vm.run(), Ok(ExecuteStatus::Halted));
+
+    println!("Final score: {}", vm.io().score);
+
+    if let (Some(profile), Some(path)) = (vm.profile, profile) {
+        eprint!("{}", profile.report(20));
+        match fs::write(path, profile.collapsed_stacks()) {
+            Ok(()) => eprintln!("Wrote collapsed stacks to {}", path),
+            Err(err) => eprintln!("Couldn't write {}: {}", path, err),
+        }
+    }
 }
 
 fn main() {
@@ -167,6 +174,20 @@ fn main() {
     io::stdin().read_line(&mut line).unwrap();
     let program = line.parse::<Program>().unwrap();
 
+    // Pass `--profile <file>` to see where part 2 spends its time. The
+    // collapsed stacks for a flamegraph go in the file.
+    let args: Vec<String> = env::args().collect();
+    let profile = match args.iter().position(|arg| arg == "--profile") {
+        Some(i) => match args.get(i + 1) {
+            Some(path) => Some(path.as_str()),
+            None => {
+                eprintln!("--profile needs a file to write the collapsed stacks to");
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+
     part1(&program);
-    part2(&program, false);
+    part2(&program, false, profile);
 }
@@ -1,4 +1,3 @@
-mod instruction;
 mod utils;
 mod vm;
 
@@ -8,5 +7,5 @@ mod vm;
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
-pub use vm::{Program, VM, ExecuteStatus};
+pub use vm::{Access, Program, VM, ExecuteStatus};
 
@@ -1,21 +1,16 @@
-use std::collections::VecDeque;
-use std::convert::TryFrom;
 use wasm_bindgen::prelude::*;
-use web_sys::console;
 
-use crate::instruction::{Opcode, Instruction, ParameterMode};
+// wasm_bindgen can't export types from another crate, so these are thin
+// wrappers around the `intcode` crate's types that JS can see.
 
 #[wasm_bindgen]
 #[derive(Debug, Clone)]
-pub struct Program {
-    code: Vec<i64>,
-}
+pub struct Program(intcode::Program);
 
 #[wasm_bindgen]
 impl Program {
-    #[allow(dead_code)]
     pub fn new(code: Vec<i64>) -> Self {
-        Program { code }
+        Program(intcode::Program::new(code))
     }
 }
 
@@ -25,351 +20,170 @@ pub enum ExecuteStatus {
     NeedInput,
     Output,
     Halted,
+    Breakpoint,
+    Watchpoint,
+    BudgetExhausted,
+}
+
+impl From<intcode::ExecuteStatus> for ExecuteStatus {
+    fn from(status: intcode::ExecuteStatus) -> Self {
+        match status {
+            intcode::ExecuteStatus::NeedInput => ExecuteStatus::NeedInput,
+            intcode::ExecuteStatus::Output => ExecuteStatus::Output,
+            intcode::ExecuteStatus::Halted => ExecuteStatus::Halted,
+            intcode::ExecuteStatus::Breakpoint => ExecuteStatus::Breakpoint,
+            intcode::ExecuteStatus::Watchpoint { .. } => ExecuteStatus::Watchpoint,
+            intcode::ExecuteStatus::BudgetExhausted => ExecuteStatus::BudgetExhausted,
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+impl From<intcode::Access> for Access {
+    fn from(access: intcode::Access) -> Self {
+        match access {
+            intcode::Access::Read => Access::Read,
+            intcode::Access::Write => Access::Write,
+        }
+    }
 }
 
 #[wasm_bindgen]
 #[derive(Debug)]
 pub struct VM {
-    memory: Vec<i64>,
-    ip: usize,
-    bp: usize,
-    input: VecDeque<i64>,
-    output: VecDeque<i64>,
-    pub cycles: usize,
-    pub debug: bool,
+    vm: intcode::VM,
+    /// The watchpoint the last `execute()` stopped at, since JS enums can't
+    /// carry it in `ExecuteStatus::Watchpoint`.
+    watchpoint: Option<(usize, Access)>,
 }
 
 #[wasm_bindgen]
 impl VM {
     pub fn new(program: &Program) -> Self {
         VM {
-            memory: program.code.clone(),
-            ip: 0,
-            bp: 0,
-            input: VecDeque::new(),
-            output: VecDeque::new(),
-            cycles: 0,
-            debug: false,
+            vm: intcode::VM::new(&program.0),
+            watchpoint: None,
         }
     }
 
-    pub fn set_memory(&mut self, address: usize, value: i64) {
-        self.memory[address] = value;
+    #[wasm_bindgen(getter)]
+    pub fn cycles(&self) -> usize {
+        self.vm.cycles
     }
 
-    pub fn send_input(&mut self, value: i64) {
-        self.input.push_back(value);
+    #[wasm_bindgen(getter)]
+    pub fn debug(&self) -> bool {
+        self.vm.debug
     }
 
-    pub fn recv_output(&mut self) -> i64 {
-        self.output.pop_front().expect("No output to receive")
+    #[wasm_bindgen(setter)]
+    pub fn set_debug(&mut self, debug: bool) {
+        self.vm.debug = debug;
     }
 
-    pub fn execute(&mut self) -> ExecuteStatus {
-        loop {
-            let inst = Instruction::try_from(self.memory[self.ip])
-                .unwrap_or_else(|_| {
-                    panic!("Invalid instruction {} at {}", self.memory[self.ip], self.ip);
-                });
-
-            if self.debug {
-                console::log_1(&format!("{:<4} | {}",
-                    self.ip,
-                    inst.disassemble(&self.memory[self.ip .. self.ip + inst.length()])
-                ).into());
-            }
-
-            self.cycles += 1;
-
-            match inst.opcode() {
-                Opcode::Add => {
-                    *self.mut_param(&inst, 2) = self.param(&inst, 0) + self.param(&inst, 1);
-                }
-                Opcode::Mul => {
-                    *self.mut_param(&inst, 2) = self.param(&inst, 0) * self.param(&inst, 1);
-                }
-                Opcode::In => {
-                    if let Some(value) = self.input.pop_front() {
-                        *self.mut_param(&inst, 0) = value;
-                    } else {
-                        return ExecuteStatus::NeedInput;
-                    }
-                }
-                Opcode::Out => {
-                    let value = self.param(&inst, 0);
-                    self.output.push_back(value);
-                    self.ip += inst.length();
-                    return ExecuteStatus::Output;
-                }
-                Opcode::JmpT => {
-                    if self.param(&inst, 0) != 0 {
-                        self.ip = self.param(&inst, 1) as usize;
-                        continue;
-                    }
-                }
-                Opcode::JmpF => {
-                    if self.param(&inst, 0) == 0 {
-                        self.ip = self.param(&inst, 1) as usize;
-                        continue;
-                    }
-                }
-                Opcode::Lt => {
-                    *self.mut_param(&inst, 2) =
-                        if self.param(&inst, 0) < self.param(&inst, 1) {
-                            1
-                        } else {
-                            0
-                        };
-                }
-                Opcode::Eql => {
-                    *self.mut_param(&inst, 2) =
-                        if self.param(&inst, 0) == self.param(&inst, 1) {
-                            1
-                        } else {
-                            0
-                        };
-                }
-                Opcode::Base => {
-                    self.bp = (self.bp as i64 + self.param(&inst, 0)) as usize;
-                }
-                Opcode::Halt => return ExecuteStatus::Halted,
-            };
-
-            self.ip += inst.length();
-        }
+    pub fn add_breakpoint(&mut self, ip: usize) {
+        self.vm.breakpoints.insert(ip);
     }
 
-    fn param(&mut self, inst: &Instruction, param: usize) -> i64 {
-        let address = self.param_address(inst, param);
-        self.memory[address]
+    pub fn remove_breakpoint(&mut self, ip: usize) {
+        self.vm.breakpoints.remove(&ip);
     }
 
-    fn mut_param(&mut self, inst: &Instruction, param: usize) -> &mut i64 {
-        let address = self.param_address(inst, param);
-        if inst.param_mode(param) == ParameterMode::Immediate {
-            panic!("Can't write to immediate mode param");
-        }
-        &mut self.memory[address]
+    pub fn watch_reads(&mut self, address: usize) {
+        self.vm.read_watchpoints.insert(address);
     }
 
-    fn param_address(&mut self, inst: &Instruction, param: usize) -> usize {
-        use ParameterMode::*;
-
-        let address = match inst.param_mode(param) {
-            Position => self.memory[self.ip + param + 1] as usize,
-            Relative => (self.bp as i64 + self.memory[self.ip + param + 1]) as usize,
-            Immediate => self.ip + param + 1,
-        };
-
-        if address >= self.memory.len() {
-            self.memory.resize(address + 1, 0);
-        }
-
-        address
+    pub fn watch_writes(&mut self, address: usize) {
+        self.vm.write_watchpoints.insert(address);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    fn test_program_memory(code: &[i64], expected: &[i64]) {
-        let mut vm = VM::new(&Program::new(code.to_vec()));
-        assert_eq!(vm.execute(), ExecuteStatus::Halted);
-        assert_eq!(vm.memory, expected);
+    pub fn unwatch(&mut self, address: usize) {
+        self.vm.read_watchpoints.remove(&address);
+        self.vm.write_watchpoints.remove(&address);
     }
 
-    fn test_program(code: &[i64], input: &[i64], output: &[i64]) {
-        let mut vm = VM::new(&Program::new(code.to_vec()));
-        for &value in input {
-            vm.send_input(value);
-        }
-        let mut actual_output = Vec::new();
-        loop {
-            match vm.execute() {
-                ExecuteStatus::Output => actual_output.push(vm.recv_output()),
-                ExecuteStatus::NeedInput => panic!("Not enough input"),
-                ExecuteStatus::Halted => break,
-            }
-        }
-        assert_eq!(actual_output, output);
+    /// Makes `execute()` return `BudgetExhausted` after `cycles` more
+    /// instructions, so a long run can be split up between frames.
+    pub fn set_cycle_budget(&mut self, cycles: usize) {
+        self.vm.budget = Some(intcode::Budget {
+            cycles: Some(cycles),
+            ..intcode::Budget::default()
+        });
     }
 
-    #[test]
-    fn pauses_execution_to_wait_for_input() {
-        let add = Program::new(vec![
-            3,100,
-            3,101,
-            1,100,101,102,
-            4,102,
-            99,
-        ]);
-
-        let mut vm = VM::new(&add);
-
-        assert_eq!(vm.execute(), ExecuteStatus::NeedInput);
-        assert_eq!(vm.ip, 0);
-        vm.send_input(5);
-
-        assert_eq!(vm.execute(), ExecuteStatus::NeedInput);
-        assert_eq!(vm.ip, 2);
-        vm.send_input(8);
-
-        assert_eq!(vm.execute(), ExecuteStatus::Output);
-        assert_eq!(vm.recv_output(), 13);
-        assert_eq!(vm.ip, 10);
-
-        assert_eq!(vm.execute(), ExecuteStatus::Halted);
-        assert_eq!(vm.ip, 10);
+    pub fn clear_budget(&mut self) {
+        self.vm.budget = None;
     }
 
-    #[test]
-    fn day2_test_cases() {
-        test_program_memory(
-            &[1,0,0,0,99],
-            &[2,0,0,0,99],
-        );
-
-        test_program_memory(
-            &[2,3,0,3,99],
-            &[2,3,0,6,99],
-        );
-
-        test_program_memory(
-            &[2,4,4,5,99,0],
-            &[2,4,4,5,99,9801],
-        );
-
-        test_program_memory(
-            &[1,1,1,4,99,5,6,0,99],
-            &[30,1,1,4,2,5,6,0,99],
-        );
+    pub fn set_memory(&mut self, address: usize, value: i64) {
+        self.vm.set_memory(address, value);
     }
 
-    #[test]
-    fn day5_test_cases() {
-        let echo = &[
-            3,0,
-            4,0,
-            99,
-        ];
-        test_program(echo, &[42], &[42]);
-        test_program(echo, &[123], &[123]);
-
-        let equal_to_8 = &[
-            3,9,
-            8,9,10,9,
-            4,9,
-            99,
-            -1,8,
-        ];
-        test_program(equal_to_8, &[8], &[1]);
-        test_program(equal_to_8, &[7], &[0]);
-        test_program(equal_to_8, &[9], &[0]);
-
-        let less_than_8 = &[
-            3,9,
-            7,9,10,9,
-            4,9,
-            99,
-            -1,8,
-        ];
-        test_program(less_than_8, &[7], &[1]);
-        test_program(less_than_8, &[8], &[0]);
-        test_program(less_than_8, &[9], &[0]);
+    /// The VM's whole state as text, e.g. for stashing in localStorage.
+    pub fn snapshot(&self) -> String {
+        self.vm.snapshot().to_string()
+    }
 
-        let equal_to_8_imm = &[
-            3,3,
-            1108,-1,8,3,
-            4,3,
-            99,
-        ];
-        test_program(equal_to_8_imm, &[8], &[1]);
-        test_program(equal_to_8_imm, &[7], &[0]);
-        test_program(equal_to_8_imm, &[9], &[0]);
+    /// Throws if `snapshot` isn't something `snapshot()` produced.
+    pub fn restore(&mut self, snapshot: &str) -> Result<(), JsValue> {
+        let snapshot = snapshot.parse::<intcode::Snapshot>()
+            .map_err(|err| JsValue::from_str(&err.to_string()))?;
+        self.vm.restore(&snapshot)
+            .map_err(|err| JsValue::from_str(&err.to_string()))?;
+        self.watchpoint = None;
+        Ok(())
+    }
 
-        let less_than_8_imm = &[
-            3,3,
-            1107,-1,8,3,
-            4,3,
-            99,
-        ];
-        test_program(less_than_8_imm, &[7], &[1]);
-        test_program(less_than_8_imm, &[8], &[0]);
-        test_program(less_than_8_imm, &[9], &[0]);
+    /// Starts recording every instruction executed, e.g. to reproduce a
+    /// session later with `intcode-replay`.
+    pub fn start_trace(&mut self) {
+        self.vm.trace = Some(intcode::Trace::new());
+    }
 
-        let is_nonzero = &[
-            3,12,
-            6,12,15,
-            1,13,14,13,
-            4,13,
-            99,
-            -1,0,1,9,
-        ];
-        test_program(is_nonzero, &[0], &[0]);
-        test_program(is_nonzero, &[8], &[1]);
+    /// Everything recorded since `start_trace()`, as text.
+    pub fn trace(&self) -> Option<String> {
+        self.vm.trace.as_ref().map(|trace| trace.to_string())
+    }
 
-        let is_nonzero_imm = &[
-            3,3,
-            1105,-1,9,
-            1101,0,0,12,
-            4,12,
-            99,
-            1,
-        ];
-        test_program(is_nonzero_imm, &[0], &[0]);
-        test_program(is_nonzero_imm, &[8], &[1]);
+    pub fn send_input(&mut self, value: i64) {
+        self.vm.send_input(value);
+    }
 
-        let spaceship_8 = &[
-            3,21,
-            1008,21,8,20,
-            1005,20,22,
-            107,8,21,20,
-            1006,20,31,
-            1106,0,36,
-            98,0,0,
-            1002,21,125,20,
-            4,20,
-            1105,1,46,
-            104,999,
-            1105,1,46,
-            1101,1000,1,20,
-            4,20,
-            1105,1,46,
-            98,
-            99,
-        ];
-        test_program(spaceship_8, &[10], &[1001]);
-        test_program(spaceship_8, &[9], &[1001]);
-        test_program(spaceship_8, &[8], &[1000]);
-        test_program(spaceship_8, &[7], &[999]);
-        test_program(spaceship_8, &[6], &[999]);
+    /// Throws if there's no output to receive.
+    pub fn recv_output(&mut self) -> Result<i64, JsValue> {
+        self.vm.recv_output().map_err(to_js_error)
     }
 
-    #[test]
-    fn day9_test_cases() {
-        let large_number = &[
-            104,1125899906842624,
-            99,
-        ];
-        test_program(large_number, &[], &[1125899906842624]);
+    /// Throws if the program crashes (invalid opcode, negative address, etc.)
+    pub fn execute(&mut self) -> Result<ExecuteStatus, JsValue> {
+        let status = self.vm.execute().map_err(to_js_error)?;
+        self.watchpoint = match status {
+            intcode::ExecuteStatus::Watchpoint { address, access } => Some((address, access.into())),
+            _ => None,
+        };
+        Ok(status.into())
+    }
 
-        let large_number = &[
-            1102,34915192,34915192,7,
-            4,7,
-            99,
-            0,
-        ];
-        test_program(large_number, &[], &[1219070632396864]);
+    /// The watched address the last `execute()` stopped at, if it returned
+    /// `Watchpoint`.
+    #[wasm_bindgen(getter)]
+    pub fn watchpoint_address(&self) -> Option<usize> {
+        self.watchpoint.map(|(address, _)| address)
+    }
 
-        let quine = &[
-            109,1,
-            204,-1,
-            1001,100,1,100,
-            1008,100,16,101,
-            1006,101,0,
-            99,
-        ];
-        test_program(quine, &[], quine);
+    /// Whether that address was read or written.
+    #[wasm_bindgen(getter)]
+    pub fn watchpoint_access(&self) -> Option<Access> {
+        self.watchpoint.map(|(_, access)| access)
     }
 }
+
+fn to_js_error(err: intcode::VmError) -> JsValue {
+    JsValue::from_str(&err.to_string())
+}
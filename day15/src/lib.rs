@@ -1,4 +0,0 @@
-mod instruction;
-mod vm;
-
-pub use vm::{Program, VM, ExecuteStatus};
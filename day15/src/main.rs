@@ -1,4 +1,4 @@
-use day15::{Program, VM, ExecuteStatus};
+use intcode::{Program, VM, ExecuteStatus};
 use num_enum::TryFromPrimitive;
 use std::collections::HashMap;
 use std::convert::TryFrom;
@@ -219,11 +219,11 @@ fn main() {
         if let Some(direction) = world.unexplored_direction() {
             let (nx, ny) = direction.move_from(world.x, world.y);
 
-            assert_eq!(vm.execute(), ExecuteStatus::NeedInput);
+            assert_eq!(vm.execute(), Ok(ExecuteStatus::NeedInput));
             vm.send_input(direction.code());
-            assert_eq!(vm.execute(), ExecuteStatus::Output);
+            assert_eq!(vm.execute(), Ok(ExecuteStatus::Output));
 
-            let tile = Tile::try_from(vm.recv_output() as u8)
+            let tile = Tile::try_from(vm.recv_output().unwrap() as u8)
                 .expect("Invalid tile code");
 
             if tile != Tile::Wall {
@@ -243,10 +243,10 @@ fn main() {
             if let Some(&(nx, ny)) = path.last() {
                 let direction = Direction::from_delta((nx - world.x, ny - world.y));
 
-                assert_eq!(vm.execute(), ExecuteStatus::NeedInput);
+                assert_eq!(vm.execute(), Ok(ExecuteStatus::NeedInput));
                 vm.send_input(direction.code());
-                assert_eq!(vm.execute(), ExecuteStatus::Output);
-                vm.recv_output();
+                assert_eq!(vm.execute(), Ok(ExecuteStatus::Output));
+                vm.recv_output().unwrap();
 
                 world.x = nx;
                 world.y = ny;
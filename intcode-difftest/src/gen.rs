@@ -0,0 +1,175 @@
+//! Random Intcode programs. They're random instructions rather than random
+//! numbers, so most of them run for a while before crashing, if they crash at
+//! all.
+
+use intcode::Program;
+
+use crate::Case;
+
+/// A tiny xorshift random number generator, so a seed always gives the same
+/// cases.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // Xorshift gets stuck on 0.
+        Rng(seed ^ 0x9e37_79b9_7f4a_7c15)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A number in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// A number in `low..=high`.
+    pub fn range(&mut self, low: i64, high: i64) -> i64 {
+        low + (self.next_u64() % (high - low + 1) as u64) as i64
+    }
+
+    /// True one time in `n`.
+    pub fn one_in(&mut self, n: usize) -> bool {
+        self.below(n) == 0
+    }
+}
+
+/// How many cells of data go after the code.
+const DATA_LEN: usize = 16;
+
+/// A parameter before the program's layout is known.
+#[derive(Clone, Copy)]
+enum Param {
+    Position(usize),
+    /// An address in the data, which starts right after the code.
+    Data(usize),
+    Immediate(i64),
+    Relative(i64),
+    /// The address of an instruction, for jumps.
+    Instruction(usize),
+}
+
+/// A value that's usually small, but sometimes big enough to overflow when
+/// it's multiplied.
+fn value(rng: &mut Rng) -> i64 {
+    match rng.below(10) {
+        0 => rng.next_u64() as i64,
+        1 => rng.range(-1_000_000_000, 1_000_000_000),
+        _ => rng.range(-10, 100),
+    }
+}
+
+/// A parameter that's read from.
+fn read_param(rng: &mut Rng, instructions: usize) -> Param {
+    match rng.below(10) {
+        0 => Param::Position(rng.below(instructions * 4)),
+        1 | 2 => Param::Relative(rng.range(-2, DATA_LEN as i64)),
+        3..=5 => Param::Immediate(value(rng)),
+        _ => Param::Data(rng.below(DATA_LEN)),
+    }
+}
+
+/// A parameter that's written to. Mostly into the data, but now and then
+/// over the code.
+fn write_param(rng: &mut Rng, instructions: usize) -> Param {
+    match rng.below(20) {
+        0 => Param::Position(rng.below(instructions * 4)),
+        1..=3 => Param::Relative(rng.range(-2, DATA_LEN as i64)),
+        _ => Param::Data(rng.below(DATA_LEN)),
+    }
+}
+
+/// A jump target: usually another instruction, or sometimes wherever a
+/// data cell says.
+fn jump_param(rng: &mut Rng, instructions: usize) -> Param {
+    if rng.one_in(8) {
+        Param::Data(rng.below(DATA_LEN))
+    } else {
+        Param::Instruction(rng.below(instructions + 1))
+    }
+}
+
+/// A program using every instruction, and some input for it. It can write
+/// over its own code, loop forever, or crash.
+pub fn random_case(rng: &mut Rng) -> Case {
+    let count = 1 + rng.below(24);
+    let mut instructions: Vec<(i64, Vec<Param>)> = Vec::new();
+    for _ in 0..count {
+        let opcode = if rng.one_in(40) { 99 } else { 1 + rng.below(9) as i64 };
+        let params = match opcode {
+            1 | 2 | 7 | 8 => vec![
+                read_param(rng, count),
+                read_param(rng, count),
+                write_param(rng, count),
+            ],
+            3 => vec![write_param(rng, count)],
+            4 | 9 => vec![read_param(rng, count)],
+            5 | 6 => vec![read_param(rng, count), jump_param(rng, count)],
+            _ => vec![],
+        };
+        instructions.push((opcode, params));
+    }
+
+    // Every instruction's address, and then the halt at the end.
+    let mut addresses = Vec::new();
+    let mut address = 0;
+    for (_, params) in &instructions {
+        addresses.push(address);
+        address += 1 + params.len();
+    }
+    addresses.push(address);
+    let data = address + 1;
+
+    let mut code = Vec::new();
+    for (opcode, params) in &instructions {
+        let mut opcode = *opcode;
+        let mut place = 100;
+        let mut values = Vec::new();
+        for param in params {
+            let (mode, value) = match *param {
+                Param::Position(address) => (0, address as i64),
+                Param::Data(offset) => (0, (data + offset) as i64),
+                Param::Immediate(value) => (1, value),
+                Param::Relative(offset) => (2, offset),
+                Param::Instruction(idx) => (1, addresses[idx] as i64),
+            };
+            opcode += mode * place;
+            place *= 10;
+            values.push(value);
+        }
+        code.push(opcode);
+        code.extend(values);
+    }
+    code.push(99);
+    code.extend((0..DATA_LEN).map(|_| value(rng)));
+
+    let input = (0..rng.below(8)).map(|_| value(rng)).collect();
+    Case { program: Program::new(code), input }
+}
+
+/// A program that only adds and multiplies, like day 2's: every parameter
+/// is a position, and it only writes to the data after its code, so it
+/// always halts.
+pub fn random_add_mul_case(rng: &mut Rng) -> Case {
+    let count = 1 + rng.below(16);
+    let data = count * 4 + 1;
+    let len = data + DATA_LEN;
+
+    let mut code = Vec::new();
+    for _ in 0..count {
+        code.push(1 + rng.below(2) as i64);
+        code.push(rng.below(len) as i64);
+        code.push(rng.below(len) as i64);
+        code.push((data + rng.below(DATA_LEN)) as i64);
+    }
+    code.push(99);
+    code.extend((0..DATA_LEN).map(|_| rng.range(0, 100)));
+
+    Case { program: Program::new(code), input: Vec::new() }
+}
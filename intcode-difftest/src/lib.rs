@@ -0,0 +1,256 @@
+//! Differential testing for the Intcode implementations. Random programs are
+//! run on `VM`, which is the reference, and on everything else that runs
+//! Intcode, and they all have to agree on the output, how it ended, and
+//! (where they can show it) the memory afterwards:
+//!
+//! - `VM` without its threaded fast path
+//! - `GenericVM` with `i64`, `i128` and `BigInt` cells (the bigger cells only
+//!   for programs that don't overflow an `i64`)
+//! - `compile_wasm`, run with wasmi
+//! - `compile_c`, built with the system C compiler, if there is one
+//! - day 2's hand-written `intcode.wat`, for programs that only add and
+//!   multiply
+//!
+//! A case that fails is shrunk. The runner in `main.rs` saves those in
+//! `regressions/`, so they get checked every time from then on.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use intcode::Program;
+
+pub mod gen;
+mod run;
+mod shrink;
+
+pub use run::{reference, Implementation, Outcome, Status, IMPLEMENTATIONS};
+pub use shrink::shrink;
+
+/// A program and its input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Case {
+    pub program: Program,
+    pub input: Vec<i64>,
+}
+
+fn join(values: &[i64]) -> String {
+    values.iter().map(i64::to_string).collect::<Vec<_>>().join(",")
+}
+
+impl fmt::Display for Case {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "program: {}", join(self.program.code()))?;
+        writeln!(f, "input: {}", join(&self.input))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseCaseError(String);
+
+impl fmt::Display for ParseCaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Can't parse case: {}", self.0)
+    }
+}
+
+impl Error for ParseCaseError {}
+
+fn split(values: &str) -> Result<Vec<i64>, ParseCaseError> {
+    values.split(',')
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(|value| value.parse().map_err(|_| ParseCaseError(format!("`{}` isn't a number", value))))
+        .collect()
+}
+
+/// The format `Display` writes: a `program:` line and an `input:` line,
+/// with `#` comments.
+impl FromStr for Case {
+    type Err = ParseCaseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut program = None;
+        let mut input = None;
+        for line in s.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            if let Some(code) = line.strip_prefix("program:") {
+                program = Some(Program::new(split(code)?));
+            } else if let Some(values) = line.strip_prefix("input:") {
+                input = Some(split(values)?);
+            } else {
+                return Err(ParseCaseError(format!("unexpected line `{}`", line)));
+            }
+        }
+        Ok(Case {
+            program: program.ok_or_else(|| ParseCaseError("no `program:` line".to_string()))?,
+            input: input.unwrap_or_default(),
+        })
+    }
+}
+
+/// An implementation that didn't do what the reference did.
+#[derive(Debug)]
+pub struct Mismatch {
+    pub implementation: &'static str,
+    pub expected: Outcome,
+    pub actual: Outcome,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} disagrees with VM", self.implementation)?;
+        writeln!(f, "expected: {:?}", self.expected)?;
+        write!(f, "actual:   {:?}", self.actual)
+    }
+}
+
+/// A case that failed, shrunk as far as it would go, and how it failed.
+#[derive(Debug)]
+pub struct Failure {
+    pub case: Case,
+    pub mismatch: Mismatch,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\n{}", self.mismatch, self.case)
+    }
+}
+
+/// Runs `case` on each of `implementations` that supports it, and checks
+/// they agree with the reference. Returns `Ok(false)` if the case was no use
+/// (see `reference()`).
+pub fn check<'a, I>(case: &Case, implementations: I) -> Result<bool, Box<Mismatch>>
+where
+    I: IntoIterator<Item = &'a Implementation>,
+{
+    let expected = match reference(case) {
+        Some(expected) => expected,
+        None => return Ok(false),
+    };
+    for implementation in implementations {
+        if !(implementation.supports)(case) {
+            continue;
+        }
+        if let Some(actual) = (implementation.run)(case) {
+            if !implementation.agrees(&expected, &actual) {
+                return Err(Box::new(Mismatch {
+                    implementation: implementation.name,
+                    expected: implementation.expect(&expected),
+                    actual,
+                }));
+            }
+        }
+    }
+    Ok(true)
+}
+
+pub fn regressions_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("regressions")
+}
+
+/// Saves `case` in `dir`, named after its contents, with `mismatch` in a
+/// comment.
+pub fn save_regression(dir: &Path, case: &Case, mismatch: &Mismatch) -> io::Result<PathBuf> {
+    // FNV-1a, which (unlike `DefaultHasher`) gives the same name every time.
+    let text = case.to_string();
+    let hash = text.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100_0000_01b3)
+    });
+
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{:016x}.txt", hash));
+    let comment = mismatch.to_string().lines().map(|line| format!("# {}\n", line)).collect::<String>();
+    fs::write(&path, comment + &text)?;
+    Ok(path)
+}
+
+/// Every case saved in `dir`, in name order. There aren't any if `dir`
+/// doesn't exist yet.
+pub fn load_regressions(dir: &Path) -> io::Result<Vec<(PathBuf, Case)>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    paths.retain(|path| path.extension().is_some_and(|extension| extension == "txt"));
+    paths.sort();
+
+    paths.into_iter()
+        .map(|path| {
+            let case = fs::read_to_string(&path)?.parse()
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            Ok((path, case))
+        })
+        .collect()
+}
+
+/// Checks `case` on every implementation (leaving out the slow ones unless
+/// `slow` is set). If one disagrees, the case is shrunk, and the error has
+/// the smallest version that still fails. Nothing is saved: that's up to
+/// the caller (see `save_regression()`).
+pub fn test_case(case: &Case, slow: bool) -> Result<bool, Box<Failure>> {
+    let implementations = || IMPLEMENTATIONS.iter().filter(|implementation| slow || !implementation.slow);
+    let failed = match check(case, implementations()) {
+        Ok(useful) => return Ok(useful),
+        Err(mismatch) => mismatch.implementation,
+    };
+
+    let only_failed = || implementations().filter(|implementation| implementation.name == failed);
+    let shrunk = shrink(case.clone(), |case| check(case, only_failed()).is_err());
+    let mismatch = check(&shrunk, only_failed()).unwrap_err();
+    Err(Box::new(Failure { case: shrunk, mismatch: *mismatch }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gen::{random_add_mul_case, random_case, Rng};
+
+    #[test]
+    fn random_cases_agree() {
+        let mut rng = Rng::new(2019);
+        let mut useful = 0;
+        for _ in 0..1000 {
+            if test_case(&random_case(&mut rng), false).unwrap_or_else(|err| panic!("{}", err)) {
+                useful += 1;
+            }
+        }
+        // Most of them should be worth running, or the generator's no good.
+        assert!(useful > 500, "only {} useful cases", useful);
+
+        for _ in 0..200 {
+            let case = random_add_mul_case(&mut rng);
+            assert!(test_case(&case, false).unwrap_or_else(|err| panic!("{}", err)));
+        }
+    }
+
+    #[test]
+    fn compiled_c_agrees() {
+        let mut rng = Rng::new(25);
+        for _ in 0..20 {
+            test_case(&random_case(&mut rng), true).unwrap_or_else(|err| panic!("{}", err));
+        }
+    }
+
+    #[test]
+    fn regressions_still_agree() {
+        for (path, case) in load_regressions(&regressions_dir()).unwrap() {
+            if let Err(mismatch) = check(&case, IMPLEMENTATIONS) {
+                panic!("{}: {}", path.display(), mismatch);
+            }
+        }
+    }
+
+    #[test]
+    fn cases_round_trip() {
+        let case = Case { program: Program::new(vec![3,0,4,0,99]), input: vec![-7, 8] };
+        assert_eq!(case.to_string().parse(), Ok(case));
+        let empty: Case = "# nothing\nprogram:\ninput:\n".parse().unwrap();
+        assert_eq!(empty, Case { program: Program::new(vec![]), input: vec![] });
+    }
+}
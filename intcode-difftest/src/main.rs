@@ -0,0 +1,48 @@
+//! Runs lots of random cases through every implementation, for longer than
+//! the tests do. Any that fail are shrunk and saved in `regressions/`.
+//!
+//! ```text
+//! $ cargo run --release -- 100000        # cases, then optionally a seed
+//! ```
+
+use intcode_difftest::gen::{random_add_mul_case, random_case, Rng};
+use intcode_difftest::{regressions_dir, save_regression, test_case};
+use std::env;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    let mut args = env::args().skip(1).map(|arg| arg.parse::<u64>().unwrap_or_else(|_| {
+        eprintln!("Usage: intcode-difftest [cases] [seed]");
+        process::exit(1);
+    }));
+    let cases = args.next().unwrap_or(10_000);
+    let seed = args.next().unwrap_or_else(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    });
+    println!("Seed {}", seed);
+
+    let mut rng = Rng::new(seed);
+    let (mut useful, mut failed) = (0, 0);
+    for idx in 0..cases {
+        // Every fourth case only adds and multiplies, so day 2 gets a go.
+        let case = if idx % 4 == 3 { random_add_mul_case(&mut rng) } else { random_case(&mut rng) };
+        match test_case(&case, true) {
+            Ok(true) => useful += 1,
+            Ok(false) => {}
+            Err(failure) => {
+                let saved = match save_regression(&regressions_dir(), &failure.case, &failure.mismatch) {
+                    Ok(path) => format!("saved as {}", path.display()),
+                    Err(err) => format!("couldn't save it: {}", err),
+                };
+                println!("{}({})\n", failure, saved);
+                failed += 1;
+            }
+        }
+    }
+
+    println!("{} cases, {} useful, {} failed", cases, useful + failed, failed);
+    if failed > 0 {
+        process::exit(1);
+    }
+}
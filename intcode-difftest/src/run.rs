@@ -0,0 +1,402 @@
+//! Running a case on each implementation, and what they have to agree on.
+
+use std::convert::TryInto;
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use intcode::{Budget, Cell, ExecuteStatus, GenericVM, Opcode, Overflow, ParameterMode, Program, VmError, VM};
+use wasmi::{Caller, Config, Engine, Extern, Linker, Module, Store};
+
+use crate::Case;
+
+/// How many instructions the reference VM gets before we give up on a case.
+const CYCLES: usize = 10_000;
+
+/// The highest address a case can use. The C and WebAssembly backends keep
+/// memory in one array, so they can't reach as far as `VM` can.
+const MAX_ADDRESS: usize = 1 << 16;
+
+/// How long a compiled C program can run, or how much fuel a WebAssembly one
+/// gets. Plenty for `CYCLES` instructions.
+const TIMEOUT: Duration = Duration::from_secs(10);
+const FUEL: u64 = 100_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Halted,
+    /// Stopped to wait for more input than the case has.
+    NeedInput,
+    /// Any error. The compiled backends can't say which.
+    Crashed,
+    TimedOut,
+}
+
+/// What running a case did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    pub status: Status,
+    pub output: Vec<i64>,
+    /// Memory at the end, without any zeros at the end, if the
+    /// implementation can show it.
+    pub memory: Option<Vec<i64>>,
+}
+
+fn trim_zeros(mut memory: Vec<i64>) -> Vec<i64> {
+    while memory.last() == Some(&0) {
+        memory.pop();
+    }
+    memory
+}
+
+pub struct Implementation {
+    pub name: &'static str,
+    /// Whether it can run this case, e.g. only add and multiply.
+    pub supports: fn(&Case) -> bool,
+    /// Runs the case, or returns `None` if it can't here (no C compiler).
+    pub run: fn(&Case) -> Option<Outcome>,
+    /// Cells are `i32`, so only the low 32 bits have to match.
+    pub narrow: bool,
+    /// It takes long enough that it should only get some of the cases.
+    pub slow: bool,
+}
+
+impl Implementation {
+    /// `expected`, as this implementation would see it.
+    pub fn expect(&self, expected: &Outcome) -> Outcome {
+        let narrow = |value: i64| if self.narrow { value as i32 as i64 } else { value };
+        Outcome {
+            status: expected.status,
+            output: expected.output.iter().copied().map(narrow).collect(),
+            memory: expected.memory.as_ref()
+                .map(|memory| trim_zeros(memory.iter().copied().map(narrow).collect())),
+        }
+    }
+
+    /// Whether `actual` agrees with `expected`. Memory is only compared if
+    /// both have it.
+    pub fn agrees(&self, expected: &Outcome, actual: &Outcome) -> bool {
+        let expected = self.expect(expected);
+        expected.status == actual.status
+            && expected.output == actual.output
+            && (expected.memory.is_none() || actual.memory.is_none() || expected.memory == actual.memory)
+    }
+}
+
+/// Runs a `VM` to the end, if it ends within `cycles` instructions, and
+/// returns the error too if it crashed.
+fn run_vm(vm: &mut VM, case: &Case, cycles: usize) -> (Outcome, Option<VmError>) {
+    for &value in &case.input {
+        vm.send_input(value);
+    }
+    vm.budget = Some(Budget { cycles: Some(cycles), ..Budget::default() });
+
+    let mut output = Vec::new();
+    let mut error = None;
+    let status = loop {
+        match vm.execute() {
+            Ok(ExecuteStatus::Output) => output.push(vm.recv_output().unwrap()),
+            Ok(ExecuteStatus::Halted) => break Status::Halted,
+            Ok(ExecuteStatus::NeedInput) => break Status::NeedInput,
+            Ok(ExecuteStatus::BudgetExhausted) => break Status::TimedOut,
+            Ok(status) => unreachable!("{:?}", status),
+            Err(err) => {
+                error = Some(err);
+                break Status::Crashed;
+            }
+        }
+    };
+    let outcome = Outcome { status, output, memory: Some(trim_zeros(vm.memory().to_vec())) };
+    (outcome, error)
+}
+
+/// What a case should do, according to `VM` with its threaded fast path.
+/// `None` if the case isn't a fair test: it runs too long, needs more input
+/// than it has, or uses addresses too far out.
+pub fn reference(case: &Case) -> Option<Outcome> {
+    let mut vm = VM::new(&case.program);
+    vm.max_address = Some(MAX_ADDRESS);
+    let (outcome, error) = run_vm(&mut vm, case, CYCLES);
+    if let Some(VmError::AddressOutOfRange(..)) = error {
+        return None;
+    }
+    match outcome.status {
+        Status::Halted | Status::Crashed => Some(outcome),
+        Status::NeedInput | Status::TimedOut => None,
+    }
+}
+
+/// Whether `case` never overflows an `i64`, in its arithmetic or its
+/// relative base, so bigger cells should do the same thing.
+fn fits_in_i64(case: &Case) -> bool {
+    let mut vm = VM::new(&case.program);
+    vm.overflow = Overflow::Trap;
+    for &value in &case.input {
+        vm.send_input(value);
+    }
+
+    let mut bp = 0_i64;
+    for _ in 0..CYCLES {
+        let event = match vm.step() {
+            Ok(Some(event)) => event,
+            Ok(None) => return true,
+            Err(err) => return !matches!(err, VmError::Overflow(_)),
+        };
+        let overflows = |offset: i64| bp.checked_add(offset).is_none();
+        if event.params.iter().any(|param| param.mode == ParameterMode::Relative && overflows(param.raw)) {
+            return false;
+        }
+        if let Some(change) = event.bp {
+            if overflows(event.params[0].value) {
+                return false;
+            }
+            bp = change.new;
+        }
+        if event.opcode == Opcode::Halt {
+            return true;
+        }
+    }
+    true
+}
+
+fn only_adds_and_multiplies(case: &Case) -> bool {
+    let code = case.program.code();
+    let halt = code.iter().step_by(4).position(|&opcode| opcode == 99);
+    case.input.is_empty()
+        && code.len() * 4 + 1024 <= 65536
+        && halt.is_some_and(|halt| {
+            let (instructions, data) = code.split_at(halt * 4);
+            let data_start = halt as i64 * 4 + 1;
+            instructions.chunks(4).all(|instruction| {
+                instruction.len() == 4
+                    && (instruction[0] == 1 || instruction[0] == 2)
+                    && instruction[1..3].iter().all(|&address| 0 <= address && address < code.len() as i64)
+                    && data_start <= instruction[3] && instruction[3] < code.len() as i64
+            }) && !data.is_empty()
+        })
+}
+
+fn everything(_case: &Case) -> bool {
+    true
+}
+
+fn vm_stepping(case: &Case) -> Option<Outcome> {
+    let mut vm = VM::new(&case.program);
+    vm.threaded = false;
+    Some(run_vm(&mut vm, case, CYCLES * 10).0)
+}
+
+fn generic<C: Cell>(case: &Case) -> Option<Outcome> {
+    let code = case.program.code().iter().map(|&value| C::from_i64(value)).collect();
+    let mut vm = GenericVM::<C>::new(&Program::new(code));
+    for &value in &case.input {
+        vm.send_input(C::from_i64(value));
+    }
+    // So a bug that makes it loop forever is a mismatch, not a hang.
+    vm.budget = Some(Budget { cycles: Some(CYCLES * 10), ..Budget::default() });
+
+    let mut output = Vec::new();
+    let status = loop {
+        match vm.execute() {
+            Ok(ExecuteStatus::Output) => output.push(vm.recv_output().unwrap().clamp_to_i64()),
+            Ok(ExecuteStatus::Halted) => break Status::Halted,
+            Ok(ExecuteStatus::NeedInput) => break Status::NeedInput,
+            Ok(ExecuteStatus::BudgetExhausted) => break Status::TimedOut,
+            Ok(status) => unreachable!("{:?}", status),
+            Err(_) => break Status::Crashed,
+        }
+    };
+    let memory = vm.memory().to_vec().iter().map(Cell::clamp_to_i64).collect();
+    Some(Outcome { status, output, memory: Some(trim_zeros(memory)) })
+}
+
+/// Output a compiled program printed, one number per line.
+fn parse_output(output: &[u8]) -> Vec<i64> {
+    String::from_utf8_lossy(output).lines().filter_map(|line| line.parse().ok()).collect()
+}
+
+fn compiled_c(case: &Case) -> Option<Outcome> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let name = format!("intcode-difftest-{}-{}", std::process::id(), COUNT.fetch_add(1, Ordering::SeqCst));
+    let source = env::temp_dir().join(format!("{}.c", name));
+    let binary = env::temp_dir().join(&name);
+    // Not a pipe, which would fill up while we wait for it to finish.
+    let stdout = env::temp_dir().join(format!("{}.out", name));
+    fs::write(&source, intcode::compile_c(&case.program)).unwrap();
+
+    let compiled = Command::new("cc").arg("-o").arg(&binary).arg(&source).status();
+    fs::remove_file(&source).unwrap();
+    if !compiled.ok()?.success() {
+        panic!("C compiler failed on {}", case.program);
+    }
+
+    let mut child = Command::new(&binary)
+        .stdin(Stdio::piped())
+        .stdout(File::create(&stdout).unwrap())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    for value in &case.input {
+        // It might have exited without reading it all.
+        let _ = writeln!(stdin, "{}", value);
+    }
+    drop(stdin);
+
+    let start = Instant::now();
+    let timed_out = loop {
+        if child.try_wait().unwrap().is_some() {
+            break false;
+        }
+        if start.elapsed() > TIMEOUT {
+            child.kill().unwrap();
+            break true;
+        }
+        thread::sleep(Duration::from_millis(1));
+    };
+    let exit = child.wait().unwrap();
+    let output = fs::read(&stdout).unwrap();
+    fs::remove_file(&binary).unwrap();
+    fs::remove_file(&stdout).unwrap();
+
+    let status = match (timed_out, exit.success()) {
+        (true, _) => Status::TimedOut,
+        (false, true) => Status::Halted,
+        (false, false) => Status::Crashed,
+    };
+    Some(Outcome { status, output: parse_output(&output), memory: None })
+}
+
+/// Standard input and output for a WebAssembly program.
+#[derive(Default)]
+struct Wasi {
+    input: Vec<u8>,
+    read: usize,
+    output: Vec<u8>,
+}
+
+/// Reads `len` iovecs at `iovs` from the caller's memory, as (address,
+/// length) pairs.
+fn iovecs(caller: &Caller<'_, Wasi>, iovs: i32, len: i32) -> Vec<(usize, usize)> {
+    let memory = caller.get_export("memory").and_then(Extern::into_memory).unwrap();
+    let data = memory.data(caller);
+    (0..len as usize)
+        .map(|idx| {
+            let at = iovs as usize + idx * 8;
+            let word = |at: usize| u32::from_le_bytes(data[at..at + 4].try_into().unwrap()) as usize;
+            (word(at), word(at + 4))
+        })
+        .collect()
+}
+
+fn linker(engine: &Engine) -> Linker<Wasi> {
+    let mut linker = Linker::new(engine);
+    linker
+        .func_wrap("wasi_unstable", "fd_read", |mut caller: Caller<'_, Wasi>, _fd: i32, iovs: i32, len: i32, nread: i32| {
+            let memory = caller.get_export("memory").and_then(Extern::into_memory).unwrap();
+            let mut count = 0;
+            for (address, len) in iovecs(&caller, iovs, len) {
+                let wasi = caller.data();
+                let bytes = wasi.input[wasi.read..].iter().take(len).copied().collect::<Vec<_>>();
+                caller.data_mut().read += bytes.len();
+                memory.write(&mut caller, address, &bytes).unwrap();
+                count += bytes.len();
+            }
+            memory.write(&mut caller, nread as usize, &(count as u32).to_le_bytes()).unwrap();
+            0
+        })
+        .unwrap();
+    linker
+        .func_wrap("wasi_unstable", "fd_write", |mut caller: Caller<'_, Wasi>, _fd: i32, iovs: i32, len: i32, nwritten: i32| {
+            let memory = caller.get_export("memory").and_then(Extern::into_memory).unwrap();
+            let mut count = 0;
+            for (address, len) in iovecs(&caller, iovs, len) {
+                let mut bytes = vec![0; len];
+                memory.read(&caller, address, &mut bytes).unwrap();
+                caller.data_mut().output.extend(&bytes);
+                count += len;
+            }
+            memory.write(&mut caller, nwritten as usize, &(count as u32).to_le_bytes()).unwrap();
+            0
+        })
+        .unwrap();
+    linker
+}
+
+fn store(engine: &Engine, input: Vec<u8>) -> Store<Wasi> {
+    let mut store = Store::new(engine, Wasi { input, ..Wasi::default() });
+    store.add_fuel(FUEL).unwrap();
+    store
+}
+
+/// An engine that counts fuel, so a program can't run forever.
+fn engine() -> Engine {
+    let mut config = Config::default();
+    config.consume_fuel(true);
+    Engine::new(&config)
+}
+
+fn compiled_wasm(case: &Case) -> Option<Outcome> {
+    let engine = engine();
+    let module = Module::new(&engine, &intcode::compile_wasm(&case.program).to_bytes()[..]).unwrap();
+    let input = case.input.iter().map(|value| format!("{}\n", value)).collect::<String>();
+    let mut store = store(&engine, input.into_bytes());
+    let instance = linker(&engine).instantiate(&mut store, &module).unwrap().start(&mut store).unwrap();
+    let run = instance.get_typed_func::<(), ()>(&store, "_start").unwrap();
+
+    let status = match run.call(&mut store, ()) {
+        Ok(()) => Status::Halted,
+        Err(_) if store.fuel_consumed() == Some(FUEL) => Status::TimedOut,
+        Err(_) => Status::Crashed,
+    };
+    Some(Outcome { status, output: parse_output(&store.data().output), memory: None })
+}
+
+/// Where day 2's interpreter puts the program it's running.
+const DAY02_PROGRAM: usize = 1024;
+
+/// Runs the case with `$executeIntcodeProgram` from day 2's hand-written
+/// WebAssembly, which only knows how to add and multiply.
+fn day02_wat(case: &Case) -> Option<Outcome> {
+    // It isn't exported, since `_start` is all a WASI runtime needs.
+    let source = include_str!("../../day02/intcode.wat");
+    let end = source.rfind(')').unwrap();
+    let source = format!("{}(export \"execute\" (func $executeIntcodeProgram)))", &source[..end]);
+
+    let engine = engine();
+    let module = Module::new(&engine, &wat::parse_str(&source).unwrap()[..]).unwrap();
+    let mut store = store(&engine, Vec::new());
+    let instance = linker(&engine).instantiate(&mut store, &module).unwrap().start(&mut store).unwrap();
+    let memory = instance.get_memory(&store, "memory").unwrap();
+    let execute = instance.get_typed_func::<i32, ()>(&store, "execute").unwrap();
+
+    let code = case.program.code();
+    let bytes = code.iter().flat_map(|&value| (value as i32).to_le_bytes()).collect::<Vec<_>>();
+    memory.write(&mut store, DAY02_PROGRAM, &bytes).unwrap();
+    let status = match execute.call(&mut store, DAY02_PROGRAM as i32) {
+        Ok(()) => Status::Halted,
+        Err(_) => Status::Crashed,
+    };
+
+    let mut bytes = vec![0; code.len() * 4];
+    memory.read(&store, DAY02_PROGRAM, &mut bytes).unwrap();
+    let memory = bytes.chunks(4)
+        .map(|word| i32::from_le_bytes(word.try_into().unwrap()) as i64)
+        .collect();
+    Some(Outcome { status, output: Vec::new(), memory: Some(trim_zeros(memory)) })
+}
+
+/// Everything that's checked against `reference()`.
+pub const IMPLEMENTATIONS: &[Implementation] = &[
+    Implementation { name: "VM (stepping)", supports: everything, run: vm_stepping, narrow: false, slow: false },
+    Implementation { name: "GenericVM<i64>", supports: everything, run: generic::<i64>, narrow: false, slow: false },
+    Implementation { name: "GenericVM<i128>", supports: fits_in_i64, run: generic::<i128>, narrow: false, slow: false },
+    Implementation { name: "GenericVM<BigInt>", supports: fits_in_i64, run: generic::<intcode::BigInt>, narrow: false, slow: false },
+    Implementation { name: "compile_wasm", supports: everything, run: compiled_wasm, narrow: false, slow: false },
+    Implementation { name: "day02/intcode.wat", supports: only_adds_and_multiplies, run: day02_wat, narrow: true, slow: false },
+    Implementation { name: "compile_c", supports: everything, run: compiled_c, narrow: false, slow: true },
+];
@@ -0,0 +1,82 @@
+//! Making a failing case as small as possible, so it's easy to see what went
+//! wrong.
+
+use intcode::Program;
+
+use crate::Case;
+
+/// Smaller numbers to try instead of `value`, simplest first. They're all
+/// closer to 0 than `value`, so shrinking always finishes.
+fn simpler(value: i64) -> Vec<i64> {
+    let mut values = vec![0, value / 2, value - value.signum()];
+    // Halting early is often the quickest way to a smaller case.
+    if value.abs() > 99 {
+        values.insert(1, 99);
+    }
+    values.dedup();
+    values.retain(|&simpler| simpler != value);
+    values
+}
+
+/// Ways to take out chunks of `values`, biggest chunks first.
+fn removals(values: &[i64]) -> Vec<Vec<i64>> {
+    let mut removals = Vec::new();
+    let mut size = values.len();
+    while size > 0 {
+        for start in (0..values.len()).step_by(size) {
+            let mut removed = values[..start].to_vec();
+            removed.extend(&values[(start + size).min(values.len())..]);
+            removals.push(removed);
+        }
+        size /= 2;
+    }
+    removals
+}
+
+/// Every case one step smaller than `case`.
+fn candidates(case: &Case) -> Vec<Case> {
+    let code = case.program.code();
+    let mut candidates = Vec::new();
+    for code in removals(code) {
+        candidates.push(Case { program: Program::new(code), input: case.input.clone() });
+    }
+    for input in removals(&case.input) {
+        candidates.push(Case { program: case.program.clone(), input });
+    }
+    for (idx, &value) in code.iter().enumerate() {
+        for simpler in simpler(value) {
+            let mut code = code.clone();
+            code[idx] = simpler;
+            candidates.push(Case { program: Program::new(code), input: case.input.clone() });
+        }
+    }
+    for (idx, &value) in case.input.iter().enumerate() {
+        for simpler in simpler(value) {
+            let mut input = case.input.clone();
+            input[idx] = simpler;
+            candidates.push(Case { program: case.program.clone(), input });
+        }
+    }
+    candidates
+}
+
+/// Shrinks `case`, which `fails`, one step at a time until no smaller case
+/// fails.
+pub fn shrink(mut case: Case, mut fails: impl FnMut(&Case) -> bool) -> Case {
+    while let Some(smaller) = candidates(&case).into_iter().find(|candidate| fails(candidate)) {
+        case = smaller;
+    }
+    case
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shrinks_to_what_matters() {
+        let case = Case { program: Program::new(vec![1,2,3,7000,5,6]), input: vec![1, 2] };
+        let shrunk = shrink(case, |case| case.program.code().iter().any(|&value| value > 100));
+        assert_eq!(shrunk, Case { program: Program::new(vec![101]), input: vec![] });
+    }
+}
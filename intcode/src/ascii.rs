@@ -0,0 +1,190 @@
+//! Talking to programs that speak ASCII: they print text a character at a
+//! time, and read commands a line at a time.
+
+use std::mem;
+
+use crate::error::VmError;
+use crate::vm::{ExecuteStatus, VM};
+
+/// Something an ASCII program printed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsciiOutput {
+    /// A line of text, without its newline.
+    Line(String),
+    /// Text that's still waiting for a newline when the program stops for
+    /// input or halts, like `> `. If it stops for anything else (a
+    /// breakpoint, say), the text is kept until the line's finished.
+    Prompt(String),
+    /// An output that isn't ASCII, i.e. anything outside 0-127. Puzzle
+    /// programs use these for their answers.
+    Number(i64),
+}
+
+/// Wraps a `VM` to send and receive text instead of numbers.
+#[derive(Debug)]
+pub struct Ascii {
+    vm: VM,
+    /// Text output since the last newline.
+    line: String,
+    status: ExecuteStatus,
+    /// Numbers that `read_line()` passed over, oldest first.
+    pub numbers: Vec<i64>,
+}
+
+impl Ascii {
+    pub fn new(vm: VM) -> Self {
+        Ascii {
+            vm,
+            line: String::new(),
+            status: ExecuteStatus::NeedInput,
+            numbers: Vec::new(),
+        }
+    }
+
+    pub fn vm(&self) -> &VM {
+        &self.vm
+    }
+
+    pub fn vm_mut(&mut self) -> &mut VM {
+        &mut self.vm
+    }
+
+    pub fn into_inner(self) -> VM {
+        self.vm
+    }
+
+    /// Why the program last stopped: `NeedInput`, `Halted`, or a breakpoint,
+    /// watchpoint or budget.
+    pub fn status(&self) -> ExecuteStatus {
+        self.status
+    }
+
+    /// Sends each character of `line` (which should be ASCII), then a
+    /// newline.
+    pub fn send_line(&mut self, line: &str) {
+        for byte in line.bytes() {
+            self.vm.send_input(byte.into());
+        }
+        self.vm.send_input(10);
+    }
+
+    /// Runs until the program prints a whole line or a number, and returns
+    /// it. Returns `None` once it stops (see `status()`) with nothing left
+    /// to return.
+    pub fn next_output(&mut self) -> Result<Option<AsciiOutput>, VmError> {
+        loop {
+            match self.vm.execute()? {
+                ExecuteStatus::Output => match self.vm.recv_output()? {
+                    10 => return Ok(Some(AsciiOutput::Line(mem::take(&mut self.line)))),
+                    value @ 0..=127 => self.line.push(value as u8 as char),
+                    value => return Ok(Some(AsciiOutput::Number(value))),
+                },
+                status => {
+                    self.status = status;
+                    let waiting = matches!(status, ExecuteStatus::NeedInput | ExecuteStatus::Halted);
+                    if !waiting || self.line.is_empty() {
+                        return Ok(None);
+                    }
+                    return Ok(Some(AsciiOutput::Prompt(mem::take(&mut self.line))));
+                }
+            }
+        }
+    }
+
+    /// The next line of text (or prompt), or `None` once the program stops.
+    /// Any numbers on the way are put in `numbers`.
+    pub fn read_line(&mut self) -> Result<Option<String>, VmError> {
+        loop {
+            match self.next_output()? {
+                Some(AsciiOutput::Line(line)) | Some(AsciiOutput::Prompt(line)) => return Ok(Some(line)),
+                Some(AsciiOutput::Number(value)) => self.numbers.push(value),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Everything the program prints until it stops, which is usually
+    /// because it's waiting at a prompt for the next line of input.
+    pub fn read_until_prompt(&mut self) -> Result<Vec<AsciiOutput>, VmError> {
+        let mut outputs = Vec::new();
+        while let Some(output) = self.next_output()? {
+            outputs.push(output);
+        }
+        Ok(outputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+    use crate::budget::Budget;
+
+    /// Prints "Name?", reads a line, then prints "Hi, " and echoes it back
+    /// with its newline, then prints 1000 and halts.
+    fn greeter() -> VM {
+        let mut source = String::new();
+        for c in "Name?\n".bytes() {
+            source.push_str(&format!("out #{}\n", c));
+        }
+        source.push_str("
+                in [char]
+                out #72
+                out #105
+                out #44
+                out #32
+        echo:   out [char]
+                eq [char], #10, [done]
+                jt [done], #end
+                in [char]
+                jt #1, #echo
+        end:    out #1000
+                hlt
+        char:   .data 0
+        done:   .data 0
+        ");
+        VM::new(&assemble(&source).unwrap())
+    }
+
+    #[test]
+    fn talks_in_lines() {
+        let mut ascii = Ascii::new(greeter());
+        assert_eq!(ascii.read_line(), Ok(Some("Name?".to_string())));
+        assert_eq!(ascii.read_line(), Ok(None));
+        assert_eq!(ascii.status(), ExecuteStatus::NeedInput);
+
+        ascii.send_line("Ada");
+        assert_eq!(ascii.read_until_prompt(), Ok(vec![
+            AsciiOutput::Line("Hi, Ada".to_string()),
+            AsciiOutput::Number(1000),
+        ]));
+        assert_eq!(ascii.status(), ExecuteStatus::Halted);
+    }
+
+    #[test]
+    fn unfinished_lines_and_skipped_numbers() {
+        let program = assemble("out #62\nout #200\nout #32\nin [0]\nhlt").unwrap();
+        let mut ascii = Ascii::new(VM::new(&program));
+        assert_eq!(ascii.read_line(), Ok(Some("> ".to_string())));
+        assert_eq!(ascii.numbers, vec![200]);
+        assert_eq!(ascii.status(), ExecuteStatus::NeedInput);
+
+        let mut ascii = Ascii::new(VM::new(&program));
+        assert_eq!(ascii.read_until_prompt(), Ok(vec![
+            AsciiOutput::Number(200),
+            AsciiOutput::Prompt("> ".to_string()),
+        ]));
+    }
+
+    #[test]
+    fn other_stops_dont_split_lines() {
+        let mut vm = greeter();
+        vm.budget = Some(Budget { cycles: Some(3), ..Budget::default() });
+        let mut ascii = Ascii::new(vm);
+        assert_eq!(ascii.next_output(), Ok(None));
+        assert_eq!(ascii.status(), ExecuteStatus::BudgetExhausted);
+
+        ascii.vm_mut().budget = None;
+        assert_eq!(ascii.next_output(), Ok(Some(AsciiOutput::Line("Name?".to_string()))));
+    }
+}
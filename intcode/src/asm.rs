@@ -0,0 +1,426 @@
+//! An assembler for Intcode, so test programs don't have to be written as raw
+//! comma separated ints. For example, here's day 5's "is the input equal to
+//! 8?" program:
+//!
+//! ```text
+//!         in [x]
+//!         eq [x], #8, [x]     ; x = x == 8 ? 1 : 0
+//!         out [x]
+//!         hlt
+//! x:      .data -1
+//! ```
+//!
+//! Each line is an optional `label:`, then an instruction or directive, then
+//! an optional `; comment`. Operands are separated by commas and come in the
+//! same order as the instruction's parameters, so the destination is last.
+//!
+//! | Operand     | Mode      | Meaning                                      |
+//! |-------------|-----------|----------------------------------------------|
+//! | `[x]`       | position  | the value at address `x`                     |
+//! | `#x`        | immediate | `x` itself (for jumps, the address to go to) |
+//! | `bp[x]`     | relative  | the value at address `bp + x`                |
+//!
+//! `x` is a number, a label, or a sum like `table+2` or `loop-1`. The
+//! mnemonics are `add`, `mul`, `in`, `out`, `jt`, `jf`, `lt`, `eq`, `arb`
+//! (adjust relative base) and `hlt`. The only directive is `.data`, which
+//! emits its comma separated values as-is.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::instruction::{Opcode, ParameterMode};
+use crate::vm::Program;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsmErrorKind {
+    UnknownMnemonic(String),
+    UnknownDirective(String),
+    WrongOperandCount { expected: usize, found: usize },
+    InvalidOperand(String),
+    ImmediateDestination,
+    InvalidLabel(String),
+    DuplicateLabel(String),
+    UndefinedLabel(String),
+    /// An expression's value doesn't fit in an `i64`.
+    Overflow,
+}
+
+/// Something wrong with the source. Lines are numbered from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub kind: AsmErrorKind,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use AsmErrorKind::*;
+
+        write!(f, "Line {}: ", self.line)?;
+        match &self.kind {
+            UnknownMnemonic(mnemonic) => write!(f, "Unknown mnemonic `{}`", mnemonic),
+            UnknownDirective(directive) => write!(f, "Unknown directive `{}`", directive),
+            WrongOperandCount { expected, found }
+                => write!(f, "Expected {} operands, found {}", expected, found),
+            InvalidOperand(operand) => write!(f, "Invalid operand `{}`", operand),
+            ImmediateDestination => write!(f, "Can't write to an immediate operand"),
+            InvalidLabel(label) => write!(f, "Invalid label `{}`", label),
+            DuplicateLabel(label) => write!(f, "Label `{}` is already defined", label),
+            UndefinedLabel(label) => write!(f, "Undefined label `{}`", label),
+            Overflow => write!(f, "Value doesn't fit in 64 bits"),
+        }
+    }
+}
+
+impl Error for AsmError {}
+
+/// A number, label, or sum of them, e.g. `loop+1`. Resolved once all the
+/// labels are known.
+#[derive(Debug)]
+struct Expr {
+    terms: Vec<Term>,
+}
+
+#[derive(Debug)]
+enum Term {
+    /// A literal, including its sign.
+    Number(i64),
+    Label { negative: bool, name: String },
+}
+
+#[derive(Debug)]
+struct Operand {
+    mode: ParameterMode,
+    value: Expr,
+}
+
+#[derive(Debug)]
+enum Item {
+    Instruction { opcode: Opcode, operands: Vec<Operand> },
+    Data(Vec<Expr>),
+}
+
+fn is_label(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_expr(s: &str) -> Option<Expr> {
+    let s: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+    if s.is_empty() {
+        return None;
+    }
+
+    // Split on + and -, keeping track of the sign of each term. A leading
+    // sign applies to the first term.
+    let mut terms = Vec::new();
+    let mut negative = false;
+    let mut start = 0;
+    for (idx, c) in s.char_indices() {
+        if c == '+' || c == '-' {
+            if idx > start {
+                terms.push((negative, &s[start..idx]));
+            } else if idx > 0 {
+                return None;
+            }
+            negative = c == '-';
+            start = idx + 1;
+        }
+    }
+    terms.push((negative, &s[start..]));
+
+    let terms = terms.into_iter()
+        .map(|(negative, term)| {
+            // Numbers are parsed with their sign, so that `-9223372036854775808`
+            // fits.
+            let number = if negative { format!("-{}", term) } else { term.to_string() };
+            if let Ok(number) = number.parse::<i64>() {
+                Some(Term::Number(number))
+            } else if is_label(term) {
+                Some(Term::Label { negative, name: term.to_string() })
+            } else {
+                None
+            }
+        })
+        .collect::<Option<_>>()?;
+
+    Some(Expr { terms })
+}
+
+fn parse_operand(s: &str) -> Option<Operand> {
+    let s = s.trim();
+
+    let (mode, value) = if let Some(value) = s.strip_prefix('#') {
+        (ParameterMode::Immediate, value)
+    } else if let Some(value) = s.strip_prefix("bp[").and_then(|s| s.strip_suffix(']')) {
+        (ParameterMode::Relative, value)
+    } else if let Some(value) = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+        (ParameterMode::Position, value)
+    } else {
+        return None;
+    };
+
+    Some(Operand { mode, value: parse_expr(value)? })
+}
+
+fn split_operands(s: &str) -> Vec<&str> {
+    if s.trim().is_empty() {
+        Vec::new()
+    } else {
+        s.split(',').collect()
+    }
+}
+
+/// Parses one line (minus its comment) into its labels and item.
+fn parse_line(line: &str) -> Result<(Vec<&str>, Option<Item>), AsmErrorKind> {
+    let mut rest = line.trim();
+
+    let mut labels = Vec::new();
+    while let Some(colon) = rest.find(':') {
+        let label = rest[..colon].trim();
+        if !is_label(label) {
+            return Err(AsmErrorKind::InvalidLabel(label.to_string()));
+        }
+        labels.push(label);
+        rest = rest[colon + 1..].trim();
+    }
+
+    if rest.is_empty() {
+        return Ok((labels, None));
+    }
+
+    let (name, operands) = match rest.find(char::is_whitespace) {
+        Some(space) => (&rest[..space], &rest[space..]),
+        None => (rest, ""),
+    };
+    let operands = split_operands(operands);
+
+    if name.starts_with('.') {
+        if name != ".data" {
+            return Err(AsmErrorKind::UnknownDirective(name.to_string()));
+        }
+        let values = operands.iter()
+            .map(|value| {
+                parse_expr(value)
+                    .ok_or_else(|| AsmErrorKind::InvalidOperand(value.trim().to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if values.is_empty() {
+            return Err(AsmErrorKind::WrongOperandCount { expected: 1, found: 0 });
+        }
+        return Ok((labels, Some(Item::Data(values))));
+    }
+
+    let opcode = Opcode::from_mnemonic(&name.to_ascii_lowercase())
+        .ok_or_else(|| AsmErrorKind::UnknownMnemonic(name.to_string()))?;
+
+    let expected = opcode.length() - 1;
+    if operands.len() != expected {
+        return Err(AsmErrorKind::WrongOperandCount { expected, found: operands.len() });
+    }
+
+    let operands = operands.iter()
+        .map(|operand| {
+            parse_operand(operand)
+                .ok_or_else(|| AsmErrorKind::InvalidOperand(operand.trim().to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if let Some(param) = opcode.write_param() {
+        if operands[param].mode == ParameterMode::Immediate {
+            return Err(AsmErrorKind::ImmediateDestination);
+        }
+    }
+
+    Ok((labels, Some(Item::Instruction { opcode, operands })))
+}
+
+fn resolve(expr: &Expr, labels: &HashMap<&str, i64>) -> Result<i64, AsmErrorKind> {
+    expr.terms.iter().try_fold(0_i64, |sum, term| {
+        let value = match term {
+            Term::Number(number) => *number,
+            Term::Label { negative, name } => {
+                let address = *labels.get(name.as_str())
+                    .ok_or_else(|| AsmErrorKind::UndefinedLabel(name.clone()))?;
+                if *negative { -address } else { address }
+            }
+        };
+        sum.checked_add(value).ok_or(AsmErrorKind::Overflow)
+    })
+}
+
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    // First pass: parse every line and work out where each label points.
+    let mut items = Vec::new();
+    let mut labels = HashMap::new();
+    let mut address = 0;
+
+    for (idx, line) in source.lines().enumerate() {
+        let line_number = idx + 1;
+        let error = |kind| AsmError { line: line_number, kind };
+
+        let line = match line.find(';') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+
+        let (line_labels, item) = parse_line(line).map_err(error)?;
+
+        for label in line_labels {
+            if labels.insert(label, address as i64).is_some() {
+                return Err(error(AsmErrorKind::DuplicateLabel(label.to_string())));
+            }
+        }
+
+        if let Some(item) = item {
+            address += match &item {
+                Item::Instruction { opcode, .. } => opcode.length(),
+                Item::Data(values) => values.len(),
+            };
+            items.push((line_number, item));
+        }
+    }
+
+    // Second pass: emit code now that every label has an address.
+    let mut code = Vec::with_capacity(address);
+    for (line_number, item) in items {
+        let error = |kind| AsmError { line: line_number, kind };
+
+        match item {
+            Item::Instruction { opcode, operands } => {
+                let mut instruction = opcode as i64;
+                let mut place = 100;
+                for operand in &operands {
+                    instruction += operand.mode as i64 * place;
+                    place *= 10;
+                }
+                code.push(instruction);
+
+                for operand in &operands {
+                    code.push(resolve(&operand.value, &labels).map_err(error)?);
+                }
+            }
+            Item::Data(values) => {
+                for value in &values {
+                    code.push(resolve(value, &labels).map_err(error)?);
+                }
+            }
+        }
+    }
+
+    Ok(Program::new(code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::{ExecuteStatus, VM};
+
+    fn run(program: &Program, input: i64) -> Vec<i64> {
+        let mut vm = VM::new(program);
+        vm.send_input(input);
+        let mut output = Vec::new();
+        loop {
+            match vm.execute().unwrap() {
+                ExecuteStatus::Output => output.push(vm.recv_output().unwrap()),
+                ExecuteStatus::Halted => break,
+                status => panic!("Unexpected {:?}", status),
+            }
+        }
+        output
+    }
+
+    #[test]
+    fn assembles_every_mnemonic_and_mode() {
+        let program = assemble("
+            add [1], #2, [3]
+            mul bp[-1], bp[2], bp[3]
+            in [0]
+            out #5
+            jt [0], #0
+            jf #1, bp[0]
+            lt [1], [2], [3]
+            eq #1, #2, bp[3]
+            arb #-7
+            hlt
+        ").unwrap();
+
+        assert_eq!(program.code(), &vec![
+            1001,1,2,3,
+            22202,-1,2,3,
+            3,0,
+            104,5,
+            1005,0,0,
+            2106,1,0,
+            7,1,2,3,
+            21108,1,2,3,
+            109,-7,
+            99,
+        ]);
+    }
+
+    #[test]
+    fn resolves_labels_and_data() {
+        // day 5's "is the input nonzero?" test program.
+        let is_nonzero = assemble("
+                    in [input]
+                    jf [input], [zero_target]     ; goto mem[zero_target] if input == 0
+                    add [result], [one], [result]
+            done:   out [result]
+                    hlt
+            input:  .data -1
+            result: .data 0
+            one:    .data 1
+            zero_target: .data done
+        ").unwrap();
+
+        assert_eq!(is_nonzero.code(), &vec![
+            3,12,
+            6,12,15,
+            1,13,14,13,
+            4,13,
+            99,
+            -1,0,1,9,
+        ]);
+        assert_eq!(run(&is_nonzero, 0), vec![0]);
+        assert_eq!(run(&is_nonzero, 8), vec![1]);
+
+        // Labels can have offsets, e.g. to patch an instruction's parameter.
+        let program = assemble("
+            start: add #0, #0, [start+3]
+            table: .data 1, 2, table-1, start-1
+        ").unwrap();
+        assert_eq!(program.code(), &vec![1101,0,0,3, 1,2,3,-1]);
+
+        // The whole range of an i64 fits, as long as the sum does.
+        let program = assemble(".data -9223372036854775808, 9223372036854775807, -9223372036854775807 - 1, -1 + 9223372036854775807 + 1").unwrap();
+        assert_eq!(program.code(), &vec![i64::MIN, i64::MAX, i64::MIN, i64::MAX]);
+    }
+
+    #[test]
+    fn reports_errors_with_line_numbers() {
+        let error = |source| assemble(source).unwrap_err();
+
+        assert_eq!(
+            error("hlt\nfrob [1]"),
+            AsmError { line: 2, kind: AsmErrorKind::UnknownMnemonic("frob".to_string()) },
+        );
+        assert_eq!(
+            error("add [1], [2]").kind,
+            AsmErrorKind::WrongOperandCount { expected: 3, found: 2 },
+        );
+        assert_eq!(error("out 5").kind, AsmErrorKind::InvalidOperand("5".to_string()));
+        assert_eq!(error("in #5").kind, AsmErrorKind::ImmediateDestination);
+        assert_eq!(error("x: hlt\nx: hlt").kind, AsmErrorKind::DuplicateLabel("x".to_string()));
+        assert_eq!(error("jt #1, #nowhere").kind, AsmErrorKind::UndefinedLabel("nowhere".to_string()));
+        assert_eq!(error(".byte 1").kind, AsmErrorKind::UnknownDirective(".byte".to_string()));
+        assert_eq!(error(".data 9223372036854775807 + 1").kind, AsmErrorKind::Overflow);
+        assert_eq!(error(".data -9223372036854775808 - 1").kind, AsmErrorKind::Overflow);
+        assert_eq!(error(".data 9223372036854775808").kind, AsmErrorKind::InvalidOperand("9223372036854775808".to_string()));
+    }
+}
@@ -0,0 +1,236 @@
+//! Running a VM as a future, with its input coming from a `Stream` and its
+//! output going to a `Sink`. Waiting for input doesn't block a thread, so any
+//! executor can run lots of VMs on one thread, feeding each other through
+//! channels, where otherwise each would need its own thread.
+
+use futures_core::Stream;
+use futures_sink::Sink;
+use std::error::Error;
+use std::fmt;
+use std::future::poll_fn;
+use std::pin::Pin;
+
+use crate::error::{Fault, VmError};
+use crate::machine::Machine;
+use crate::vm::{ExecuteStatus, VM};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AsyncError<E> {
+    Vm(VmError),
+    /// The program needed input, but the input stream had ended.
+    InputEnded(Fault),
+    /// The output sink failed.
+    Sink(E),
+}
+
+impl<E: fmt::Display> fmt::Display for AsyncError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsyncError::Vm(err) => write!(f, "{}", err),
+            AsyncError::InputEnded(fault) => write!(f, "Input ended {}", fault),
+            AsyncError::Sink(err) => write!(f, "Couldn't send output: {}", err),
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> Error for AsyncError<E> {}
+
+impl<E> From<VmError> for AsyncError<E> {
+    fn from(err: VmError) -> Self {
+        AsyncError::Vm(err)
+    }
+}
+
+impl VM {
+    /// Runs the program, awaiting `input` whenever it needs input and sending
+    /// every output to `output`. Returns when it halts, or when `execute()`
+    /// stops for some other reason (a breakpoint, watchpoint or budget), with
+    /// that status. Calling it again carries on from there.
+    ///
+    /// Output is flushed before waiting for input and before returning, so a
+    /// buffered sink can't hold back output that the input depends on.
+    /// Anything in the input queue already (from `send_input()`) is read
+    /// before `input` is.
+    ///
+    /// Between inputs and outputs this runs without yielding, so a program
+    /// that computes for a long time holds up everything else on the same
+    /// thread. A `Budget` can break that up.
+    pub async fn run_async<I, O>(&mut self, mut input: I, mut output: O) -> Result<ExecuteStatus, AsyncError<O::Error>>
+    where
+        I: Stream<Item = i64> + Unpin,
+        O: Sink<i64> + Unpin,
+    {
+        loop {
+            match self.execute()? {
+                ExecuteStatus::NeedInput => {
+                    poll_fn(|cx| Pin::new(&mut output).poll_flush(cx)).await
+                        .map_err(AsyncError::Sink)?;
+                    match poll_fn(|cx| Pin::new(&mut input).poll_next(cx)).await {
+                        Some(value) => self.send_input(value),
+                        None => return Err(AsyncError::InputEnded(self.fault())),
+                    }
+                }
+                ExecuteStatus::Output => {
+                    let value = self.recv_output()?;
+                    poll_fn(|cx| Pin::new(&mut output).poll_ready(cx)).await
+                        .map_err(AsyncError::Sink)?;
+                    Pin::new(&mut output).start_send(value)
+                        .map_err(AsyncError::Sink)?;
+                }
+                status => {
+                    poll_fn(|cx| Pin::new(&mut output).poll_flush(cx)).await
+                        .map_err(AsyncError::Sink)?;
+                    return Ok(status);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::convert::Infallible;
+    use std::future::Future;
+    use std::rc::Rc;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    use crate::vm::Program;
+
+    /// The simplest possible single-threaded channel.
+    #[derive(Clone, Default)]
+    struct Channel(Rc<RefCell<VecDeque<i64>>>);
+
+    impl Stream for Channel {
+        type Item = i64;
+
+        fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<i64>> {
+            match self.0.borrow_mut().pop_front() {
+                Some(value) => Poll::Ready(Some(value)),
+                None => Poll::Pending,
+            }
+        }
+    }
+
+    impl Sink<i64> for Channel {
+        type Error = Infallible;
+
+        fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn start_send(self: Pin<&mut Self>, value: i64) -> Result<(), Infallible> {
+            self.0.borrow_mut().push_back(value);
+            Ok(())
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    /// These tests poll by hand, so there's nothing for a waker to do.
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    fn noop_waker() -> Waker {
+        Waker::from(Arc::new(NoopWaker))
+    }
+
+    type Task = Pin<Box<dyn Future<Output = Result<ExecuteStatus, AsyncError<Infallible>>>>>;
+
+    /// Polls every task round-robin on this thread until they've all
+    /// finished.
+    fn run_all(mut tasks: Vec<Task>) {
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        while !tasks.is_empty() {
+            tasks.retain_mut(|task| match task.as_mut().poll(&mut cx) {
+                Poll::Ready(result) => {
+                    assert_eq!(result, Ok(ExecuteStatus::Halted));
+                    false
+                }
+                Poll::Pending => true,
+            });
+        }
+    }
+
+    fn spawn(program: &Program, initial: Option<i64>, input: Channel, output: Channel) -> Task {
+        let mut vm = VM::new(program);
+        if let Some(value) = initial {
+            vm.send_input(value);
+        }
+        Box::pin(async move { vm.run_async(input, output).await })
+    }
+
+    #[test]
+    fn runs_day7_feedback_loop_on_one_thread() {
+        let program = Program::new(vec![
+            3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,
+            4,27,1001,28,-1,28,1005,28,6,99,0,0,5,
+        ]);
+        let channels: Vec<Channel> = (0..5).map(|_| Channel::default()).collect();
+        channels[0].0.borrow_mut().push_back(0);
+
+        let tasks = [9, 8, 7, 6, 5].iter().enumerate()
+            .map(|(idx, &phase)| {
+                let output = channels[(idx + 1) % 5].clone();
+                spawn(&program, Some(phase), channels[idx].clone(), output)
+            })
+            .collect();
+        run_all(tasks);
+
+        assert_eq!(channels[0].0.borrow().iter().copied().collect::<Vec<_>>(), vec![139629729]);
+    }
+
+    #[test]
+    fn chains_a_thousand_vms() {
+        // Reads a number and outputs it plus one.
+        let program = Program::new(vec![3,9,1001,9,1,9,4,9,99,0]);
+        let channels: Vec<Channel> = (0..=1000).map(|_| Channel::default()).collect();
+
+        // Spawned back to front, so every VM has to wait for its input.
+        let tasks = (0..1000).rev()
+            .map(|idx| spawn(&program, None, channels[idx].clone(), channels[idx + 1].clone()))
+            .collect();
+        channels[0].0.borrow_mut().push_back(0);
+        run_all(tasks);
+
+        assert_eq!(channels[1000].0.borrow().front(), Some(&1000));
+    }
+
+    #[test]
+    fn input_ending_is_an_error() {
+        let mut vm = VM::new(&Program::new(vec![3,0,99]));
+        let mut task: Task = Box::pin(async move {
+            vm.run_async(Ended, Channel::default()).await
+        });
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert_eq!(
+            task.as_mut().poll(&mut cx),
+            Poll::Ready(Err(AsyncError::InputEnded(Fault { ip: 0, instruction: 3, cycles: 0 }))),
+        );
+    }
+
+    /// An input stream that's already ended.
+    struct Ended;
+
+    impl Stream for Ended {
+        type Item = i64;
+
+        fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<i64>> {
+            Poll::Ready(None)
+        }
+    }
+}
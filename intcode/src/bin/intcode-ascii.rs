@@ -0,0 +1,57 @@
+//! Plays an ASCII Intcode program interactively: prints what it says, and
+//! sends it each line typed at the prompt. Numbers it outputs (usually the
+//! answer) are printed on their own line, and prompts without a newline are
+//! left that way.
+//!
+//! ```text
+//! $ cargo run --bin intcode-ascii ../input/input25
+//! ```
+
+use intcode::{Ascii, AsciiOutput, ExecuteStatus, Program, VM};
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::process;
+
+fn main() {
+    let path = env::args().nth(1).unwrap_or_else(|| {
+        eprintln!("Usage: intcode-ascii <program file>");
+        process::exit(1);
+    });
+    let program = fs::read_to_string(&path)
+        .unwrap_or_else(|err| {
+            eprintln!("Can't read {}: {}", path, err);
+            process::exit(1);
+        })
+        .parse::<Program>()
+        .unwrap_or_else(|err| {
+            eprintln!("Can't parse {}: {}", path, err);
+            process::exit(1);
+        });
+
+    let mut ascii = Ascii::new(VM::new(&program));
+    let mut stdin = io::stdin().lock();
+    loop {
+        let outputs = ascii.read_until_prompt().unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        });
+        for output in outputs {
+            match output {
+                AsciiOutput::Line(line) => println!("{}", line),
+                AsciiOutput::Prompt(prompt) => print!("{}", prompt),
+                AsciiOutput::Number(value) => println!("{}", value),
+            }
+        }
+        io::stdout().flush().unwrap();
+        if ascii.status() != ExecuteStatus::NeedInput {
+            break;
+        }
+
+        let mut line = String::new();
+        if stdin.read_line(&mut line).unwrap_or(0) == 0 {
+            break;
+        }
+        ascii.send_line(line.trim_end_matches(&['\r', '\n'][..]));
+    }
+}
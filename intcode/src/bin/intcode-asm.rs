@@ -0,0 +1,41 @@
+//! Assembles an Intcode program and prints it as comma separated ints, ready
+//! to be fed to any of the days:
+//!
+//! ```text
+//! $ cargo run --bin intcode-asm program.asm > program.txt
+//! ```
+//!
+//! Reads from stdin if no file is given.
+
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::process;
+
+fn main() {
+    let (name, source) = match env::args().nth(1) {
+        Some(path) => {
+            let source = fs::read_to_string(&path).unwrap_or_else(|err| {
+                eprintln!("Can't read {}: {}", path, err);
+                process::exit(1);
+            });
+            (path, source)
+        }
+        None => {
+            let mut source = String::new();
+            io::stdin().read_to_string(&mut source).unwrap_or_else(|err| {
+                eprintln!("Can't read stdin: {}", err);
+                process::exit(1);
+            });
+            ("<stdin>".to_string(), source)
+        }
+    };
+
+    match intcode::assemble(&source) {
+        Ok(program) => println!("{}", program),
+        Err(err) => {
+            eprintln!("{}: {}", name, err);
+            process::exit(1);
+        }
+    }
+}
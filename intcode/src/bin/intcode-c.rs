@@ -0,0 +1,32 @@
+//! Transpiles an Intcode program to C, which reads its input from stdin and
+//! prints its output, one number per line:
+//!
+//! ```text
+//! $ cargo run --bin intcode-c ../input/input09 > boost.c
+//! $ cc -O2 -o boost boost.c
+//! $ echo 2 | ./boost
+//! ```
+
+use intcode::Program;
+use std::env;
+use std::fs;
+use std::process;
+
+fn main() {
+    let path = env::args().nth(1).unwrap_or_else(|| {
+        eprintln!("Usage: intcode-c <program file>");
+        process::exit(1);
+    });
+    let program = fs::read_to_string(&path)
+        .unwrap_or_else(|err| {
+            eprintln!("Can't read {}: {}", path, err);
+            process::exit(1);
+        })
+        .parse::<Program>()
+        .unwrap_or_else(|err| {
+            eprintln!("Can't parse {}: {}", path, err);
+            process::exit(1);
+        });
+
+    print!("{}", intcode::compile_c(&program));
+}
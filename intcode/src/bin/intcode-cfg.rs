@@ -0,0 +1,54 @@
+//! Prints an Intcode program's control-flow graph in Graphviz DOT format,
+//! with one cluster per function:
+//!
+//! ```text
+//! $ cargo run --bin intcode-cfg ../input/input13 | dot -Tsvg > arcade.svg
+//! ```
+//!
+//! Pass `--functions` to list the functions instead.
+
+use intcode::{ControlFlowGraph, Program};
+use std::env;
+use std::fs;
+use std::process;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let list_functions = args.iter().any(|arg| arg == "--functions");
+    let path = args.iter().find(|arg| !arg.starts_with("--")).unwrap_or_else(|| {
+        eprintln!("Usage: intcode-cfg [--functions] <program file>");
+        process::exit(1);
+    });
+    let program = fs::read_to_string(path)
+        .unwrap_or_else(|err| {
+            eprintln!("Can't read {}: {}", path, err);
+            process::exit(1);
+        })
+        .parse::<Program>()
+        .unwrap_or_else(|err| {
+            eprintln!("Can't parse {}: {}", path, err);
+            process::exit(1);
+        });
+
+    let cfg = ControlFlowGraph::new(&program);
+
+    if !list_functions {
+        print!("{}", cfg.to_dot());
+        return;
+    }
+
+    for function in cfg.functions.values() {
+        let calls: Vec<String> = function.calls.iter()
+            .map(|&callee| cfg.function_name(callee))
+            .collect();
+        let frame = match function.frame_size {
+            Some(size) => format!("frame {}", size),
+            None => "no frame".to_string(),
+        };
+        println!("{:<8} {} blocks, {}, calls [{}]",
+                 cfg.function_name(function.entry),
+                 function.blocks.len(),
+                 frame,
+                 calls.join(", "));
+    }
+}
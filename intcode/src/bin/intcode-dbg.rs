@@ -0,0 +1,327 @@
+//! A gdb-style debugger for Intcode programs:
+//!
+//! ```text
+//! $ cargo run --bin intcode-dbg ../input/input13
+//! ```
+//!
+//! Type `help` at the prompt for a list of commands. An empty line repeats the
+//! last command, so you can hit enter to keep stepping.
+
+use intcode::{Access, Budget, ExecuteStatus, History, Instruction, Program, VM};
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::process;
+
+/// How many instructions `back` can undo, so a long `continue` doesn't eat
+/// all the memory.
+const HISTORY_LIMIT: usize = 1_000_000;
+
+/// How many instructions `continue` runs before checking back in, so a
+/// program stuck in a loop doesn't hang the debugger.
+const CONT_BUDGET: usize = 10_000_000;
+
+const HELP: &str = "\
+step [n]              execute n instructions (default 1)
+continue              run until a breakpoint, watchpoint, halt, missing input
+                      or 10 million instructions
+back [n]              undo n instructions (default 1)
+backto <ip>           run backwards until about to execute <ip>
+whowrote <addr>       run backwards to the instruction that last wrote <addr>
+break <ip>            stop before executing the instruction at <ip>
+delete <ip>           remove a breakpoint
+watch <addr>          stop after <addr> is written to
+rwatch <addr>         stop after <addr> is read from
+awatch <addr>         stop after <addr> is read from or written to
+unwatch <addr>        remove all watchpoints on <addr>
+info                  list breakpoints and watchpoints
+regs                  show ip, bp and the cycle count
+list [addr] [n]       disassemble n instructions from addr (default ip, 10)
+print <addr> [n]      show n memory cells starting at addr (default 1)
+set <addr> <value>    change a memory cell
+input <value>...      queue input values
+output                show (and clear) queued output
+reset                 reload the program and start over
+quit";
+
+struct Debugger {
+    program: Program,
+    vm: VM,
+}
+
+impl Debugger {
+    fn new(program: Program) -> Self {
+        let mut vm = VM::new(&program);
+        vm.history = Some(History::with_limit(HISTORY_LIMIT));
+        Debugger { program, vm }
+    }
+
+    fn read(&self, address: usize) -> i64 {
+        *self.vm.memory().get(address)
+    }
+
+    /// Disassembles the instruction at `address`. Returns the listing and the
+    /// address of the next instruction. Anything that doesn't decode is shown
+    /// as a single int of data.
+    fn disassemble_at(&self, address: usize) -> (String, usize) {
+        let marker = if address == self.vm.ip() {
+            "=>"
+        } else if self.vm.breakpoints.contains(&address) {
+            " *"
+        } else {
+            "  "
+        };
+
+        match Instruction::try_from(self.read(address)) {
+            Ok(inst) => {
+                let code: Vec<i64> = (address .. address + inst.length())
+                    .map(|address| self.read(address))
+                    .collect();
+                let listing = format!("{} {:<4} | {}", marker, address, inst.disassemble(&code));
+                (listing, address + inst.length())
+            }
+            Err(_) => {
+                let listing = format!("{} {:<4} | {:<20}(data)", marker, address, self.read(address));
+                (listing, address + 1)
+            }
+        }
+    }
+
+    fn print_current_instruction(&self) {
+        println!("{}", self.disassemble_at(self.vm.ip()).0);
+    }
+
+    fn step(&mut self, count: usize) {
+        for _ in 0..count {
+            self.print_current_instruction();
+            match self.vm.step() {
+                Ok(Some(event)) => {
+                    if let Some(write) = event.write {
+                        println!("     mem[{}]: {} -> {}", write.address, write.old, write.new);
+                    }
+                    if let Some(value) = event.output {
+                        println!("     output: {}", value);
+                    }
+                    if let Some(bp) = event.bp {
+                        println!("     bp: {} -> {}", bp.old, bp.new);
+                    }
+                }
+                Ok(None) => {
+                    println!("Waiting for input");
+                    return;
+                }
+                Err(err) => {
+                    println!("{}", err);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn cont(&mut self) {
+        self.vm.budget = Some(Budget { cycles: Some(CONT_BUDGET), ..Budget::default() });
+        loop {
+            match self.vm.execute() {
+                // Output just piles up in the queue until the `output` command.
+                Ok(ExecuteStatus::Output) => continue,
+                Ok(ExecuteStatus::NeedInput) => println!("Waiting for input"),
+                Ok(ExecuteStatus::Halted) => {
+                    println!("Halted after {} cycles", self.vm.cycles);
+                }
+                Ok(ExecuteStatus::Breakpoint) => {
+                    println!("Breakpoint at {}", self.vm.ip());
+                    self.print_current_instruction();
+                }
+                Ok(ExecuteStatus::Watchpoint { address, access }) => {
+                    let access = match access {
+                        Access::Read => "read from",
+                        Access::Write => "written to",
+                    };
+                    println!("mem[{}] {} (now {})", address, access, self.read(address));
+                    self.print_current_instruction();
+                }
+                Ok(ExecuteStatus::BudgetExhausted) => {
+                    println!("Still running after {} instructions; `continue` to keep going",
+                        CONT_BUDGET);
+                    self.print_current_instruction();
+                }
+                Err(err) => println!("{}", err),
+            }
+            break;
+        }
+        self.vm.budget = None;
+
+        let queued = self.vm.queued_output().len();
+        if queued > 0 {
+            println!("{} value(s) in the output queue", queued);
+        }
+    }
+
+    fn back(&mut self, count: usize) {
+        for _ in 0..count {
+            if !self.vm.step_back() {
+                println!("Reached the start of history");
+                break;
+            }
+        }
+        self.print_current_instruction();
+    }
+
+    fn report_rewind(&self, found: bool, what: &str) {
+        if !found {
+            println!("No {} in history", what);
+        }
+        self.print_current_instruction();
+    }
+
+    fn info(&self) {
+        let breakpoints: BTreeSet<_> = self.vm.breakpoints.iter().collect();
+        let read_watchpoints: BTreeSet<_> = self.vm.read_watchpoints.iter().collect();
+        let write_watchpoints: BTreeSet<_> = self.vm.write_watchpoints.iter().collect();
+
+        println!("Breakpoints:       {:?}", breakpoints);
+        println!("Read watchpoints:  {:?}", read_watchpoints);
+        println!("Write watchpoints: {:?}", write_watchpoints);
+    }
+
+    fn list(&self, start: usize, count: usize) {
+        let mut address = start;
+        for _ in 0..count {
+            let (listing, next) = self.disassemble_at(address);
+            println!("{}", listing);
+            address = next;
+        }
+    }
+
+    fn print(&self, start: usize, count: usize) {
+        for address in start .. start + count {
+            println!("mem[{}] = {}", address, self.read(address));
+        }
+    }
+
+    /// Runs a single command. Returns false when it's time to quit.
+    fn run_command(&mut self, line: &str) -> Result<bool, String> {
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => return Ok(true),
+        };
+        let args = words
+            .map(|word| word.parse::<i64>().map_err(|_| format!("Not a number: {}", word)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let address = |idx: usize| -> Result<usize, String> {
+            let value = *args.get(idx).ok_or("Missing address")?;
+            usize::try_from(value).map_err(|_| format!("Invalid address: {}", value))
+        };
+        let count = |idx: usize, default: usize| -> Result<usize, String> {
+            if args.len() > idx { address(idx) } else { Ok(default) }
+        };
+
+        match command {
+            "s" | "step" => self.step(count(0, 1)?),
+            "c" | "continue" => self.cont(),
+            "back" => self.back(count(0, 1)?),
+            "backto" => {
+                let found = self.vm.run_back_to(address(0)?);
+                self.report_rewind(found, "such instruction");
+            }
+            "whowrote" => {
+                let found = self.vm.run_back_to_write(address(0)?);
+                self.report_rewind(found, "write to that address");
+            }
+            "b" | "break" => { self.vm.breakpoints.insert(address(0)?); }
+            "d" | "delete" => { self.vm.breakpoints.remove(&address(0)?); }
+            "w" | "watch" => { self.vm.write_watchpoints.insert(address(0)?); }
+            "rwatch" => { self.vm.read_watchpoints.insert(address(0)?); }
+            "awatch" => {
+                self.vm.read_watchpoints.insert(address(0)?);
+                self.vm.write_watchpoints.insert(address(0)?);
+            }
+            "unwatch" => {
+                self.vm.read_watchpoints.remove(&address(0)?);
+                self.vm.write_watchpoints.remove(&address(0)?);
+            }
+            "i" | "info" => self.info(),
+            "r" | "regs" => {
+                println!("ip = {}, bp = {}, cycles = {}", self.vm.ip(), self.vm.bp(), self.vm.cycles);
+            }
+            "l" | "list" => self.list(count(0, self.vm.ip())?, count(1, 10)?),
+            "p" | "print" => self.print(address(0)?, count(1, 1)?),
+            "set" => {
+                let value = *args.get(1).ok_or("Missing value")?;
+                self.vm.set_memory(address(0)?, value);
+            }
+            "in" | "input" => {
+                for &value in &args {
+                    self.vm.send_input(value);
+                }
+            }
+            "out" | "output" => {
+                let output: Vec<i64> = self.vm.queued_output().iter().copied().collect();
+                for _ in &output {
+                    self.vm.recv_output().unwrap();
+                }
+                println!("{:?}", output);
+            }
+            "reset" => {
+                let mut vm = VM::new(&self.program);
+                vm.history = Some(History::with_limit(HISTORY_LIMIT));
+                vm.breakpoints = self.vm.breakpoints.clone();
+                vm.read_watchpoints = self.vm.read_watchpoints.clone();
+                vm.write_watchpoints = self.vm.write_watchpoints.clone();
+                self.vm = vm;
+            }
+            "h" | "help" => println!("{}", HELP),
+            "q" | "quit" => return Ok(false),
+            _ => return Err(format!("Unknown command: {} (try `help`)", command)),
+        }
+
+        Ok(true)
+    }
+}
+
+fn main() {
+    let path = env::args().nth(1).unwrap_or_else(|| {
+        eprintln!("Usage: intcode-dbg <program file>");
+        process::exit(1);
+    });
+    let program = fs::read_to_string(&path)
+        .unwrap_or_else(|err| {
+            eprintln!("Can't read {}: {}", path, err);
+            process::exit(1);
+        })
+        .parse::<Program>()
+        .unwrap_or_else(|err| {
+            eprintln!("Can't parse {}: {}", path, err);
+            process::exit(1);
+        });
+
+    let mut debugger = Debugger::new(program);
+    debugger.print_current_instruction();
+
+    let stdin = io::stdin();
+    let mut last_command = String::new();
+    loop {
+        print!("(intcode) ");
+        io::stdout().flush().unwrap();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+
+        let line = line.trim();
+        if !line.is_empty() {
+            last_command = line.to_string();
+        }
+
+        match debugger.run_command(&last_command) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(message) => println!("{}", message),
+        }
+    }
+}
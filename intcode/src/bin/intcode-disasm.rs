@@ -0,0 +1,32 @@
+//! Prints a full listing of an Intcode program, with code and data separated
+//! and jump targets labelled:
+//!
+//! ```text
+//! $ cargo run --bin intcode-disasm ../input/input13 > arcade.asm
+//! ```
+//!
+//! The listing can be fed straight back to `intcode-asm`.
+
+use intcode::Program;
+use std::env;
+use std::fs;
+use std::process;
+
+fn main() {
+    let path = env::args().nth(1).unwrap_or_else(|| {
+        eprintln!("Usage: intcode-disasm <program file>");
+        process::exit(1);
+    });
+    let program = fs::read_to_string(&path)
+        .unwrap_or_else(|err| {
+            eprintln!("Can't read {}: {}", path, err);
+            process::exit(1);
+        })
+        .parse::<Program>()
+        .unwrap_or_else(|err| {
+            eprintln!("Can't parse {}: {}", path, err);
+            process::exit(1);
+        });
+
+    print!("{}", intcode::disassemble(&program));
+}
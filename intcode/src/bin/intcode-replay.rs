@@ -0,0 +1,44 @@
+//! Checks that a program still does exactly what a recorded trace says it
+//! did:
+//!
+//! ```text
+//! $ cargo run --bin intcode-replay ../input/input15 session.trace
+//! ```
+//!
+//! Exits with status 1 and says where, if they diverge.
+
+use intcode::{Program, Trace};
+use std::env;
+use std::fs;
+use std::process;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() != 2 {
+        eprintln!("Usage: intcode-replay <program file> <trace file>");
+        process::exit(1);
+    }
+
+    let program = fs::read_to_string(&args[0])
+        .unwrap_or_else(|err| {
+            eprintln!("Can't read {}: {}", args[0], err);
+            process::exit(1);
+        })
+        .parse::<Program>()
+        .unwrap_or_else(|err| {
+            eprintln!("Can't parse {}: {}", args[0], err);
+            process::exit(1);
+        });
+    let trace = Trace::load(&args[1]).unwrap_or_else(|err| {
+        eprintln!("Can't load {}: {}", args[1], err);
+        process::exit(1);
+    });
+
+    match intcode::replay(&program, &trace) {
+        None => println!("Replayed {} instructions, no divergence", trace.entries.len()),
+        Some(divergence) => {
+            println!("{}", divergence);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! Compiles an Intcode program to a standalone WebAssembly module, which
+//! reads its input from stdin and prints its output, one number per line:
+//!
+//! ```text
+//! $ cargo run --bin intcode-wasm ../input/input09 > boost.wat
+//! $ echo 1 | wasmtime boost.wat
+//! ```
+//!
+//! Pass `--binary` for a `.wasm` file instead of text.
+
+use intcode::Program;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let binary = args.iter().any(|arg| arg == "--binary");
+    let path = args.iter().find(|arg| !arg.starts_with("--")).unwrap_or_else(|| {
+        eprintln!("Usage: intcode-wasm [--binary] <program file>");
+        process::exit(1);
+    });
+    let program = fs::read_to_string(path)
+        .unwrap_or_else(|err| {
+            eprintln!("Can't read {}: {}", path, err);
+            process::exit(1);
+        })
+        .parse::<Program>()
+        .unwrap_or_else(|err| {
+            eprintln!("Can't parse {}: {}", path, err);
+            process::exit(1);
+        });
+
+    let module = intcode::compile_wasm(&program);
+
+    if binary {
+        io::stdout().write_all(&module.to_bytes()).unwrap_or_else(|err| {
+            eprintln!("Can't write module: {}", err);
+            process::exit(1);
+        });
+    } else {
+        print!("{}", module);
+    }
+}
@@ -0,0 +1,42 @@
+use std::time::Instant;
+
+/// How often `execute()` looks at the clock when there's a deadline, in
+/// instructions. Checking on every one would slow things down a lot.
+const DEADLINE_INTERVAL: usize = 1024;
+
+/// Limits on how much a VM can do before `execute()` stops with
+/// `ExecuteStatus::BudgetExhausted`. Any limit left as `None` doesn't apply.
+///
+/// Running out doesn't break anything: top the budget back up (or take it
+/// away) and call `execute()` again to carry on where it stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Budget {
+    /// How many more instructions can be executed. Counts down as they are.
+    pub cycles: Option<usize>,
+    /// The most memory cells the VM can have allocated. This is checked
+    /// before each instruction, so the one that goes over still finishes.
+    pub memory: Option<usize>,
+    /// When to stop. The clock is only checked every 1024 instructions, so
+    /// this can be overshot a little. Don't use it in the browser, where
+    /// `Instant::now()` panics.
+    pub deadline: Option<Instant>,
+}
+
+impl Budget {
+    /// Whether there's nothing left for the next instruction, given how many
+    /// cycles the VM has run and how much memory it has allocated.
+    pub(crate) fn exhausted(&self, cycles: usize, allocated: usize) -> bool {
+        self.cycles == Some(0)
+            || self.memory.is_some_and(|memory| allocated > memory)
+            || self.deadline.is_some_and(|deadline| {
+                cycles % DEADLINE_INTERVAL == 0 && Instant::now() >= deadline
+            })
+    }
+
+    /// Uses up one instruction's worth of cycles.
+    pub(crate) fn spend(&mut self) {
+        if let Some(cycles) = &mut self.cycles {
+            *cycles = cycles.saturating_sub(1);
+        }
+    }
+}
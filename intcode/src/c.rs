@@ -0,0 +1,580 @@
+//! Transpiles an Intcode program to C, so it can be compiled natively with
+//! the system C compiler:
+//!
+//! ```text
+//! $ cargo run --bin intcode-c ../input/input09 > boost.c
+//! $ cc -O2 -o boost boost.c
+//! $ echo 2 | ./boost
+//! ```
+//!
+//! Every instruction the disassembler finds becomes a `case` of a `switch`
+//! on `ip`, in address order, so running on to the next instruction is just
+//! falling through to the next case. Jumps to a known instruction are a
+//! `goto`, and anything else (jumps through memory, like returns) sets `ip`
+//! and goes back round to the `switch`.
+//!
+//! Addresses without a case run one instruction at a time in `step()`, a
+//! plain interpreter. It also handles self-modifying code: once the program
+//! writes over a compiled instruction, the rest of the run is interpreted.
+//!
+//! Memory is a `realloc`ed array that grows to fit, with no sparse pages.
+//! Input is read as numbers from stdin (anything in between is skipped), and
+//! each output is printed on its own line. Arithmetic wraps, like the VM's
+//! default `Overflow::Wrap`. Errors are printed to stderr with the `ip` where
+//! they happened, and exit with status 1.
+
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+use crate::disasm::{disassemble, Disassembly};
+use crate::instruction::{Instruction, Opcode, ParameterMode};
+use crate::vm::Program;
+
+/// Everything that doesn't depend on the program, apart from `main()`.
+const RUNTIME: &str = r#"/* Every instruction falls through to the next one. */
+#pragma GCC diagnostic ignored "-Wimplicit-fallthrough"
+
+static int64_t *memory;
+static size_t memory_len;
+static int modified;
+
+static void fail(const char *message, int64_t ip)
+{
+    fflush(stdout);
+    fprintf(stderr, "%s at ip %" PRId64 "\n", message, ip);
+    exit(1);
+}
+
+/* Negative addresses are an error, and memory grows to fit. */
+static int64_t *cell(int64_t address, int64_t ip)
+{
+    if (address < 0)
+        fail("Negative address", ip);
+    /* Any further and the size in bytes wouldn't fit in a size_t. */
+    if ((uint64_t)address >= SIZE_MAX / sizeof *memory)
+        fail("Out of memory", ip);
+    if ((uint64_t)address >= memory_len) {
+        size_t len = memory_len * 2 > (size_t)address ? memory_len * 2 : (size_t)address + 1;
+        if (len >= SIZE_MAX / sizeof *memory)
+            len = (size_t)address + 1;
+        memory = realloc(memory, len * sizeof *memory);
+        if (!memory)
+            fail("Out of memory", ip);
+        memset(memory + memory_len, 0, (len - memory_len) * sizeof *memory);
+        memory_len = len;
+    }
+    return &memory[address];
+}
+
+static int64_t load(int64_t address, int64_t ip)
+{
+    return *cell(address, ip);
+}
+
+static void store(int64_t address, int64_t value, int64_t ip)
+{
+    *cell(address, ip) = value;
+    if (address < PROGRAM_LEN && compiled[address])
+        modified = 1;
+}
+
+static int64_t target(int64_t address, int64_t ip)
+{
+    if (address < 0)
+        fail("Negative address", ip);
+    return address;
+}
+
+static int64_t add(int64_t a, int64_t b)
+{
+    return (int64_t)((uint64_t)a + (uint64_t)b);
+}
+
+static int64_t mul(int64_t a, int64_t b)
+{
+    return (int64_t)((uint64_t)a * (uint64_t)b);
+}
+
+static int64_t input(int64_t ip)
+{
+    int64_t value;
+    int c;
+
+    while ((c = getchar()) != EOF && c != '-' && !isdigit(c))
+        ;
+    if (c == EOF)
+        fail("Out of input", ip);
+    ungetc(c, stdin);
+    if (scanf("%" SCNd64, &value) != 1)
+        fail("Out of input", ip);
+    return value;
+}
+
+static void output(int64_t value)
+{
+    printf("%" PRId64 "\n", value);
+    fflush(stdout);
+}
+
+/* The address of parameter n of the instruction at ip. */
+static int64_t param(int64_t ip, int64_t bp, int n, int write)
+{
+    static const int64_t divisors[] = { 0, 100, 1000, 10000 };
+    int64_t address = ip + n;
+
+    switch (load(ip, ip) / divisors[n] % 10) {
+    case 0:
+        return load(address, ip);
+    case 1:
+        if (write)
+            fail("Immediate write", ip);
+        return address;
+    case 2:
+        return add(bp, load(address, ip));
+    }
+    fail("Invalid parameter mode", ip);
+    return 0;
+}
+
+#define READ(n) load(param(at, *bp, n, 0), at)
+#define WRITE(n) param(at, *bp, n, 1)
+
+/* Runs the instruction at *ip. Returns 0 if it halts. */
+static int step(int64_t *ip, int64_t *bp)
+{
+    int64_t at = *ip;
+    int64_t instruction = load(at, at);
+
+    if (instruction < 0)
+        fail("Invalid opcode", at);
+    switch (instruction % 100) {
+    case 1:
+        store(WRITE(3), add(READ(1), READ(2)), at);
+        *ip += 4;
+        return 1;
+    case 2:
+        store(WRITE(3), mul(READ(1), READ(2)), at);
+        *ip += 4;
+        return 1;
+    case 3:
+        store(WRITE(1), input(at), at);
+        *ip += 2;
+        return 1;
+    case 4:
+        output(READ(1));
+        *ip += 2;
+        return 1;
+    case 5:
+        *ip = READ(1) != 0 ? target(READ(2), at) : *ip + 3;
+        return 1;
+    case 6:
+        *ip = READ(1) == 0 ? target(READ(2), at) : *ip + 3;
+        return 1;
+    case 7:
+        store(WRITE(3), READ(1) < READ(2), at);
+        *ip += 4;
+        return 1;
+    case 8:
+        store(WRITE(3), READ(1) == READ(2), at);
+        *ip += 4;
+        return 1;
+    case 9:
+        *bp = add(*bp, READ(1));
+        *ip += 2;
+        return 1;
+    case 99:
+        return 0;
+    }
+    fail("Invalid opcode", at);
+    return 0;
+}
+"#;
+
+pub fn compile_c(program: &Program) -> String {
+    let disassembly = disassemble(program);
+    let code = program.code();
+
+    let instructions: Vec<usize> = disassembly.instructions().collect();
+    let mut compiled = vec![0; code.len()];
+    for &address in &instructions {
+        let length = Instruction::try_from(code[address]).unwrap().length();
+        for cell in &mut compiled[address .. (address + length).min(code.len())] {
+            *cell = 1;
+        }
+    }
+
+    let mut source = String::new();
+    writeln!(source, "/* Compiled from Intcode by intcode-c. */").unwrap();
+    writeln!(source, "#include <ctype.h>").unwrap();
+    writeln!(source, "#include <inttypes.h>").unwrap();
+    writeln!(source, "#include <stdio.h>").unwrap();
+    writeln!(source, "#include <stdlib.h>").unwrap();
+    writeln!(source, "#include <string.h>").unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "#define PROGRAM_LEN {}", code.len().max(1)).unwrap();
+    writeln!(source).unwrap();
+    let values: Vec<String> = code.iter().map(|&value| literal(value)).collect();
+    write_array(&mut source, "static const int64_t program[PROGRAM_LEN]", &values);
+    writeln!(source).unwrap();
+    writeln!(source, "/* 1 for every cell that's part of a compiled instruction. */").unwrap();
+    let values: Vec<String> = compiled.iter().map(u8::to_string).collect();
+    write_array(&mut source, "static const unsigned char compiled[PROGRAM_LEN]", &values);
+    writeln!(source).unwrap();
+    source.push_str(RUNTIME);
+    writeln!(source).unwrap();
+
+    let mut compiler = Compiler {
+        disassembly: &disassembly,
+        len: code.len(),
+        compiled: &compiled,
+        instructions: instructions.iter().copied().collect(),
+        body: String::new(),
+        labels: HashSet::new(),
+    };
+    for (index, &address) in instructions.iter().enumerate() {
+        let next = compiler.instruction(address);
+        if let Some(next) = next {
+            if instructions.get(index + 1) != Some(&next) {
+                compiler.line(&format!("ip = {};", next));
+                compiler.line("continue;");
+            }
+        }
+    }
+
+    writeln!(source, "int main(void)").unwrap();
+    writeln!(source, "{{").unwrap();
+    writeln!(source, "    int64_t ip = 0, bp = 0;").unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "    memory_len = PROGRAM_LEN;").unwrap();
+    writeln!(source, "    memory = malloc(sizeof program);").unwrap();
+    writeln!(source, "    memcpy(memory, program, sizeof program);").unwrap();
+    writeln!(source).unwrap();
+    writeln!(source, "    for (;;) {{").unwrap();
+    writeln!(source, "        if (!modified) {{").unwrap();
+    writeln!(source, "            switch (ip) {{").unwrap();
+    for line in compiler.body.lines() {
+        // Labels are only written where something jumps to them, so that
+        // unused ones don't cause warnings.
+        match line.trim().strip_prefix("case ") {
+            Some(case) => {
+                let address: usize = case.trim_end_matches(':').parse().unwrap();
+                if compiler.labels.contains(&address) {
+                    writeln!(source, "{} L{}:", line, address).unwrap();
+                } else {
+                    writeln!(source, "{}", line).unwrap();
+                }
+            }
+            None => writeln!(source, "{}", line).unwrap(),
+        }
+    }
+    writeln!(source, "            }}").unwrap();
+    writeln!(source, "        }}").unwrap();
+    writeln!(source, "        if (!step(&ip, &bp))").unwrap();
+    writeln!(source, "            return 0;").unwrap();
+    writeln!(source, "    }}").unwrap();
+    writeln!(source, "}}").unwrap();
+
+    source
+}
+
+/// Writes `values` as a C array initializer, ten to a line.
+fn write_array(source: &mut String, declaration: &str, values: &[String]) {
+    writeln!(source, "{} = {{", declaration).unwrap();
+    for chunk in values.chunks(10) {
+        writeln!(source, "    {},", chunk.join(", ")).unwrap();
+    }
+    writeln!(source, "}};").unwrap();
+}
+
+struct Compiler<'a> {
+    disassembly: &'a Disassembly,
+    len: usize,
+    compiled: &'a [u8],
+    instructions: HashSet<usize>,
+    body: String,
+    /// Instructions something `goto`s.
+    labels: HashSet<usize>,
+}
+
+impl Compiler<'_> {
+    /// Writes the case for the instruction at `address`. Returns the address
+    /// it goes on to, if it can run on to the next instruction.
+    fn instruction(&mut self, address: usize) -> Option<usize> {
+        let code = self.disassembly.code();
+        let inst = Instruction::try_from(code[address]).unwrap();
+        let next = address + inst.length();
+        let arg = |param: usize| (inst.param_mode(param), code[address + param + 1]);
+
+        writeln!(self.body, "            case {}:", address).unwrap();
+        writeln!(self.body, "                /* {} */", self.disassembly.instruction(address)).unwrap();
+
+        match inst.opcode() {
+            Opcode::Add | Opcode::Mul | Opcode::Lt | Opcode::Eql => {
+                let a = self.value(address, arg(0));
+                let b = self.value(address, arg(1));
+                let value = match inst.opcode() {
+                    Opcode::Add => format!("add({}, {})", a, b),
+                    Opcode::Mul => format!("mul({}, {})", a, b),
+                    Opcode::Lt => format!("{} < {}", a, b),
+                    _ => format!("{} == {}", a, b),
+                };
+                self.store(address, arg(2), &value, next)
+            }
+            Opcode::In => self.store(address, arg(0), &format!("input({})", address), next),
+            Opcode::Out => {
+                let value = self.value(address, arg(0));
+                self.line(&format!("output({});", value));
+                Some(next)
+            }
+            Opcode::JmpT | Opcode::JmpF => {
+                let jump_if_true = inst.opcode() == Opcode::JmpT;
+                match arg(0) {
+                    (ParameterMode::Immediate, value) if (value != 0) == jump_if_true => {
+                        self.jump(address, arg(1), "");
+                        None
+                    }
+                    (ParameterMode::Immediate, _) => Some(next),
+                    condition => {
+                        let value = self.value(address, condition);
+                        let test = match jump_if_true {
+                            true => format!("if ({} != 0)", value),
+                            false => format!("if ({} == 0)", value),
+                        };
+                        self.line(&format!("{} {{", test));
+                        self.jump(address, arg(1), "    ");
+                        self.line("}");
+                        Some(next)
+                    }
+                }
+            }
+            Opcode::Base => {
+                let value = self.value(address, arg(0));
+                self.line(&format!("bp = add(bp, {});", value));
+                Some(next)
+            }
+            Opcode::Halt => {
+                self.line("return 0;");
+                None
+            }
+        }
+    }
+
+    fn line(&mut self, line: &str) {
+        writeln!(self.body, "                {}", line).unwrap();
+    }
+
+    /// A parameter's value, as a C expression.
+    fn value(&self, address: usize, (mode, raw): (ParameterMode, i64)) -> String {
+        match mode {
+            ParameterMode::Immediate => literal(raw),
+            ParameterMode::Position => match usize::try_from(raw) {
+                Ok(cell) if cell < self.len => format!("memory[{}]", cell),
+                _ => format!("load({}, {})", literal(raw), address),
+            },
+            ParameterMode::Relative => format!("load(add(bp, {}), {})", literal(raw), address),
+        }
+    }
+
+    /// Writes `value` to a parameter. If that might have changed compiled
+    /// code, carries on at `next` in the interpreter.
+    fn store(&mut self, address: usize, (mode, raw): (ParameterMode, i64), value: &str, next: usize) -> Option<usize> {
+        match mode {
+            ParameterMode::Immediate => {
+                self.line(&format!("fail(\"Immediate write\", {});", address));
+                None
+            }
+            ParameterMode::Position => match usize::try_from(raw) {
+                Ok(cell) if cell < self.len && self.compiled[cell] == 0 => {
+                    if value.contains("load(") {
+                        // That might grow memory, so it has to be worked
+                        // out before indexing it.
+                        self.line("{");
+                        self.line(&format!("    int64_t value = {};", value));
+                        self.line(&format!("    memory[{}] = value;", cell));
+                        self.line("}");
+                    } else {
+                        self.line(&format!("memory[{}] = {};", cell, value));
+                    }
+                    Some(next)
+                }
+                Ok(cell) if cell < self.len => {
+                    self.line(&format!("store({}, {}, {});", cell, value, address));
+                    self.line(&format!("ip = {};", next));
+                    self.line("continue;");
+                    None
+                }
+                _ => {
+                    self.line(&format!("store({}, {}, {});", literal(raw), value, address));
+                    Some(next)
+                }
+            },
+            ParameterMode::Relative => {
+                self.line(&format!("store(add(bp, {}), {}, {});", literal(raw), value, address));
+                self.line("if (modified) {");
+                self.line(&format!("    ip = {};", next));
+                self.line("    continue;");
+                self.line("}");
+                Some(next)
+            }
+        }
+    }
+
+    /// Jumps to the target in a jump instruction's second parameter.
+    fn jump(&mut self, address: usize, target: (ParameterMode, i64), indent: &str) {
+        match target {
+            (ParameterMode::Immediate, target) if target < 0 => {
+                self.line(&format!("{}fail(\"Negative address\", {});", indent, address));
+            }
+            (ParameterMode::Immediate, target) if self.instructions.contains(&(target as usize)) => {
+                self.labels.insert(target as usize);
+                self.line(&format!("{}goto L{};", indent, target));
+            }
+            (ParameterMode::Immediate, target) => {
+                self.line(&format!("{}ip = {};", indent, target));
+                self.line(&format!("{}continue;", indent));
+            }
+            target => {
+                let value = self.value(address, target);
+                self.line(&format!("{}ip = target({}, {});", indent, value, address));
+                self.line(&format!("{}continue;", indent));
+            }
+        }
+    }
+}
+
+/// `i64::MIN` can't be written as a literal in C.
+fn literal(value: i64) -> String {
+    match value {
+        i64::MIN => "INT64_MIN".to_string(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+    use crate::vm::{ExecuteStatus, VM};
+    use std::io::Write as _;
+    use std::process::{Command, Output, Stdio};
+
+    const COUNTDOWN: &str = "
+                in [n]
+        loop:   jf [n], #done
+                out [n]
+                add [n], #-1, [n]
+                jt #1, #loop
+        done:   hlt
+        n:      .data 0
+    ";
+
+    #[test]
+    fn compiles_to_a_switch_with_gotos() {
+        let source = compile_c(&assemble(COUNTDOWN).unwrap());
+
+        assert!(source.contains("
+            switch (ip) {
+            case 0:
+                /* in [D15] */
+                memory[15] = input(0);
+            case 2: L2:
+                /* jf [D15], #L14 */
+                if (memory[15] == 0) {
+                    goto L14;
+                }
+            case 5:
+                /* out [D15] */
+                output(memory[15]);
+            case 7:
+                /* add [D15], #-1, [D15] */
+                memory[15] = add(memory[15], -1);
+            case 11:
+                /* jt #1, #L2 */
+                goto L2;
+            case 14: L14:
+                /* hlt */
+                return 0;
+            }
+"));
+    }
+
+    fn run_vm(program: &Program, input: i64) -> Vec<i64> {
+        let mut vm = VM::new(program);
+        vm.send_input(input);
+        let mut output = Vec::new();
+        loop {
+            match vm.execute().unwrap() {
+                ExecuteStatus::Output => output.push(vm.recv_output().unwrap()),
+                ExecuteStatus::Halted => return output,
+                status => panic!("Unexpected {:?}", status),
+            }
+        }
+    }
+
+    /// Compiles `program` with the system C compiler and runs it, or returns
+    /// `None` if there isn't a C compiler.
+    fn run_c(program: &Program, input: i64, name: &str) -> Option<Output> {
+        let dir = std::env::temp_dir();
+        let source = dir.join(format!("intcode-c-{}-{}.c", std::process::id(), name));
+        let binary = dir.join(format!("intcode-c-{}-{}", std::process::id(), name));
+        std::fs::write(&source, compile_c(program)).unwrap();
+
+        let compiled = Command::new("cc").arg("-o").arg(&binary).arg(&source).status();
+        std::fs::remove_file(&source).unwrap();
+        if compiled.is_err() {
+            eprintln!("No C compiler, so the C test for {} was skipped", name);
+            return None;
+        }
+        assert!(compiled.unwrap().success());
+
+        let mut child = Command::new(&binary)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        // Programs that never read input can exit before we've written it.
+        let _ = writeln!(child.stdin.take().unwrap(), "{}", input);
+        let output = child.wait_with_output().unwrap();
+        std::fs::remove_file(&binary).unwrap();
+        Some(output)
+    }
+
+    #[test]
+    fn matches_the_vm_when_compiled() {
+        let programs = vec![
+            ("countdown", assemble(COUNTDOWN).unwrap(), 3),
+            // Day 9's quine, which grows memory through relative writes.
+            ("quine", Program::new(vec![109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99]), 0),
+            // Overwrites the operand of the `out` after it.
+            ("patch", Program::new(vec![1101,7,0,5,104,1,99]), 0),
+            ("large", Program::new(vec![104,1125899906842624,104,i64::MIN,99]), 0),
+        ];
+
+        for (name, program, input) in programs {
+            let output = match run_c(&program, input, name) {
+                Some(output) => output,
+                None => return,
+            };
+            assert!(output.status.success(), "{}", name);
+            let output: Vec<i64> = String::from_utf8(output.stdout).unwrap()
+                .lines()
+                .map(|line| line.parse().unwrap())
+                .collect();
+            assert_eq!(output, run_vm(&program, input), "{}", name);
+        }
+    }
+
+    #[test]
+    fn addresses_too_big_to_allocate_run_out_of_memory() {
+        // 2^61 cells is 2^64 bytes, which used to wrap to nothing.
+        let program = Program::new(vec![1101,1,0,2305843009213693952,4,2305843009213693952,99]);
+        assert_eq!(run_vm(&program, 0), vec![1]);
+
+        if let Some(output) = run_c(&program, 0, "huge") {
+            assert_eq!(output.status.code(), Some(1));
+            assert_eq!(String::from_utf8(output.stderr).unwrap(), "Out of memory at ip 0\n");
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! The types a `GenericVM` can use for its memory cells.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::vm::Overflow;
+
+mod bignum;
+
+pub use bignum::{BigInt, ParseBigIntError};
+
+/// A memory cell: an integer that `Add` and `Mul` work on. Implemented for
+/// `i64`, `i128` and `BigInt`.
+pub trait Cell: Clone + Ord + fmt::Debug + fmt::Display + FromStr {
+    fn from_i64(value: i64) -> Self;
+
+    /// The value as an `i64`, or `None` if it doesn't fit.
+    fn to_i64(&self) -> Option<i64>;
+
+    /// `self + other`, or `None` to trap. Fixed-size cells follow the
+    /// overflow policy; `BigInt` ignores it since it never overflows.
+    fn add(&self, other: &Self, overflow: Overflow) -> Option<Self>;
+
+    /// `self * other`, or `None` to trap.
+    fn mul(&self, other: &Self, overflow: Overflow) -> Option<Self>;
+
+    fn is_zero(&self) -> bool {
+        *self == Self::from_i64(0)
+    }
+
+    /// The value as an `i64`, clamped to `i64::MIN`/`i64::MAX` if it doesn't
+    /// fit. Good enough for error messages.
+    fn clamp_to_i64(&self) -> i64 {
+        match self.to_i64() {
+            Some(value) => value,
+            None if *self < Self::from_i64(0) => i64::MIN,
+            None => i64::MAX,
+        }
+    }
+}
+
+impl Cell for i64 {
+    fn from_i64(value: i64) -> Self {
+        value
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        Some(*self)
+    }
+
+    fn add(&self, other: &Self, overflow: Overflow) -> Option<Self> {
+        overflow.add(*self, *other)
+    }
+
+    fn mul(&self, other: &Self, overflow: Overflow) -> Option<Self> {
+        overflow.mul(*self, *other)
+    }
+}
+
+impl Cell for i128 {
+    fn from_i64(value: i64) -> Self {
+        value.into()
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        i64::try_from(*self).ok()
+    }
+
+    fn add(&self, other: &Self, overflow: Overflow) -> Option<Self> {
+        match overflow {
+            Overflow::Wrap => Some(self.wrapping_add(*other)),
+            Overflow::Trap => self.checked_add(*other),
+            Overflow::Saturate => Some(self.saturating_add(*other)),
+        }
+    }
+
+    fn mul(&self, other: &Self, overflow: Overflow) -> Option<Self> {
+        match overflow {
+            Overflow::Wrap => Some(self.wrapping_mul(*other)),
+            Overflow::Trap => self.checked_mul(*other),
+            Overflow::Saturate => Some(self.saturating_mul(*other)),
+        }
+    }
+}
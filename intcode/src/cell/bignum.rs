@@ -0,0 +1,271 @@
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::cell::Cell;
+use crate::vm::Overflow;
+
+/// An arbitrary-precision integer, just big enough to be an Intcode cell:
+/// it can add, multiply, compare, parse and print, and nothing else.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BigInt {
+    negative: bool,
+    /// Base 2^32 digits, least significant first, with no trailing zeros.
+    /// Zero is an empty vector and is never negative.
+    magnitude: Vec<u32>,
+}
+
+impl BigInt {
+    fn new(negative: bool, mut magnitude: Vec<u32>) -> Self {
+        while magnitude.last() == Some(&0) {
+            magnitude.pop();
+        }
+        let negative = negative && !magnitude.is_empty();
+        BigInt { negative, magnitude }
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(value: i64) -> Self {
+        let abs = value.unsigned_abs();
+        BigInt::new(value < 0, vec![abs as u32, (abs >> 32) as u32])
+    }
+}
+
+impl Cell for BigInt {
+    fn from_i64(value: i64) -> Self {
+        value.into()
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        if self.magnitude.len() > 2 {
+            return None;
+        }
+        let abs = self.magnitude.iter().rev()
+            .fold(0u64, |abs, &digit| abs << 32 | u64::from(digit));
+        let value = if self.negative { -i128::from(abs) } else { i128::from(abs) };
+        if value < i128::from(i64::MIN) || value > i128::from(i64::MAX) {
+            None
+        } else {
+            Some(value as i64)
+        }
+    }
+
+    fn add(&self, other: &Self, _overflow: Overflow) -> Option<Self> {
+        if self.negative == other.negative {
+            return Some(BigInt::new(self.negative, add_magnitudes(&self.magnitude, &other.magnitude)));
+        }
+        // Different signs, so it's really a subtraction, and the bigger
+        // magnitude decides the sign.
+        Some(match compare_magnitudes(&self.magnitude, &other.magnitude) {
+            Ordering::Less
+                => BigInt::new(other.negative, sub_magnitudes(&other.magnitude, &self.magnitude)),
+            _   => BigInt::new(self.negative, sub_magnitudes(&self.magnitude, &other.magnitude)),
+        })
+    }
+
+    fn mul(&self, other: &Self, _overflow: Overflow) -> Option<Self> {
+        let mut product = vec![0u32; self.magnitude.len() + other.magnitude.len()];
+        for (i, &a) in self.magnitude.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in other.magnitude.iter().enumerate() {
+                let sum = u64::from(product[i + j]) + u64::from(a) * u64::from(b) + carry;
+                product[i + j] = sum as u32;
+                carry = sum >> 32;
+            }
+            product[i + other.magnitude.len()] = carry as u32;
+        }
+        Some(BigInt::new(self.negative != other.negative, product))
+    }
+
+    fn is_zero(&self) -> bool {
+        self.magnitude.is_empty()
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare_magnitudes(&self.magnitude, &other.magnitude),
+            (true, true) => compare_magnitudes(&other.magnitude, &self.magnitude),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn compare_magnitudes(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut sum = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0u64;
+    for i in 0 .. a.len().max(b.len()) {
+        let digit = u64::from(a.get(i).copied().unwrap_or(0))
+            + u64::from(b.get(i).copied().unwrap_or(0))
+            + carry;
+        sum.push(digit as u32);
+        carry = digit >> 32;
+    }
+    sum.push(carry as u32);
+    sum
+}
+
+/// `a - b`, where `a` is at least as big as `b`.
+fn sub_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut difference = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &digit) in a.iter().enumerate() {
+        let mut digit = i64::from(digit) - i64::from(b.get(i).copied().unwrap_or(0)) - borrow;
+        borrow = 0;
+        if digit < 0 {
+            digit += 1 << 32;
+            borrow = 1;
+        }
+        difference.push(digit as u32);
+    }
+    difference
+}
+
+/// Divides a magnitude by a small number in place, returning the remainder.
+fn div_rem_small(magnitude: &mut Vec<u32>, divisor: u32) -> u32 {
+    let mut remainder = 0u64;
+    for digit in magnitude.iter_mut().rev() {
+        let value = remainder << 32 | u64::from(*digit);
+        *digit = (value / u64::from(divisor)) as u32;
+        remainder = value % u64::from(divisor);
+    }
+    while magnitude.last() == Some(&0) {
+        magnitude.pop();
+    }
+    remainder as u32
+}
+
+/// Multiplies a magnitude by a small number and adds another, in place.
+fn mul_add_small(magnitude: &mut Vec<u32>, factor: u32, addend: u32) {
+    let mut carry = u64::from(addend);
+    for digit in magnitude.iter_mut() {
+        let value = u64::from(*digit) * u64::from(factor) + carry;
+        *digit = value as u32;
+        carry = value >> 32;
+    }
+    if carry > 0 {
+        magnitude.push(carry as u32);
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.magnitude.is_empty() {
+            return write!(f, "0");
+        }
+
+        // Peel off nine decimal digits at a time, least significant first.
+        let mut magnitude = self.magnitude.clone();
+        let mut chunks = Vec::new();
+        while !magnitude.is_empty() {
+            chunks.push(div_rem_small(&mut magnitude, 1_000_000_000));
+        }
+
+        if self.negative {
+            write!(f, "-")?;
+        }
+        let mut chunks = chunks.iter().rev();
+        write!(f, "{}", chunks.next().unwrap())?;
+        for chunk in chunks {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+/// A string that isn't an optionally signed run of decimal digits.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ParseBigIntError;
+
+impl fmt::Display for ParseBigIntError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid digit found in string")
+    }
+}
+
+impl Error for ParseBigIntError {}
+
+impl FromStr for BigInt {
+    type Err = ParseBigIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match s.as_bytes().first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        if digits.is_empty() {
+            return Err(ParseBigIntError);
+        }
+
+        let mut magnitude = Vec::new();
+        for c in digits.chars() {
+            let digit = c.to_digit(10).ok_or(ParseBigIntError)?;
+            mul_add_small(&mut magnitude, 10, digit);
+        }
+        Ok(BigInt::new(negative, magnitude))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(s: &str) -> BigInt {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn arithmetic() {
+        let cases = &[
+            ("0", "0", "0", "0"),
+            ("-5", "5", "0", "-25"),
+            ("9223372036854775807", "1", "9223372036854775808", "9223372036854775807"),
+            ("-9223372036854775808", "-1", "-9223372036854775809", "9223372036854775808"),
+            ("4294967296", "-4294967297", "-1", "-18446744078004518912"),
+            ("123456789012345678901234567890", "-987654321098765432109876543210",
+             "-864197532086419753208641975320",
+             "-121932631137021795226185032733622923332237463801111263526900"),
+        ];
+        for &(a, b, sum, product) in cases {
+            assert_eq!(big(a).add(&big(b), Overflow::Trap), Some(big(sum)), "{} + {}", a, b);
+            assert_eq!(big(a).mul(&big(b), Overflow::Trap), Some(big(product)), "{} * {}", a, b);
+            assert_eq!(big(sum).to_string(), sum);
+            assert_eq!(big(product).to_string(), product);
+        }
+    }
+
+    #[test]
+    fn conversions_and_ordering() {
+        for &value in &[0, 1, -1, 4294967296, i64::MAX, i64::MIN] {
+            assert_eq!(BigInt::from(value).to_i64(), Some(value));
+            assert_eq!(BigInt::from(value).to_string(), value.to_string());
+        }
+        assert_eq!(big("9223372036854775808").to_i64(), None);
+        assert_eq!(big("-9223372036854775809").clamp_to_i64(), i64::MIN);
+        assert_eq!(big("-0"), BigInt::from(0));
+
+        assert!(big("-10000000000000000000000") < big("-1"));
+        assert!(big("-1") < big("0"));
+        assert!(big("4294967296") > big("4294967295"));
+
+        assert_eq!("".parse::<BigInt>(), Err(ParseBigIntError));
+        assert_eq!("-".parse::<BigInt>(), Err(ParseBigIntError));
+        assert_eq!("12a".parse::<BigInt>(), Err(ParseBigIntError));
+    }
+}
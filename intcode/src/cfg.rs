@@ -0,0 +1,383 @@
+//! Recovers basic blocks and functions from a program, on top of what the
+//! disassembler found.
+//!
+//! The puzzle programs are compiled, and use a consistent calling convention:
+//!
+//! ```text
+//!         add #0, #L37, bp[0]     ; store the return address at bp[0]
+//!         jt #1, #L578            ; call
+//! L37:    ...
+//!
+//! L578:   arb #3                  ; push a frame (arguments are at bp[-3]...)
+//!         ...
+//!         arb #-3                 ; pop it
+//!         jt #1, bp[0]            ; return
+//! ```
+//!
+//! so every call target is treated as the entry of a function, plus address
+//! 0 as `main`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+use crate::disasm::{disassemble, Disassembly};
+use crate::instruction::{Instruction, Opcode, ParameterMode};
+use crate::vm::Program;
+
+/// How control leaves a basic block.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlockExit {
+    /// Runs straight into the next block (or off the end of the code).
+    FallThrough,
+    Halt,
+    /// An unconditional jump.
+    Jump,
+    /// A conditional jump: either to the target or the next block.
+    Branch,
+    /// Calls a function, which comes back to the next block.
+    Call { callee: usize },
+    /// `jt #1, bp[0]` or similar.
+    Return,
+    /// A jump through memory that isn't a return, so the target is unknown.
+    Indirect,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: usize,
+    /// Address just past the block's last instruction.
+    pub end: usize,
+    /// Addresses of the instructions in the block.
+    pub instructions: Vec<usize>,
+    pub exit: BlockExit,
+    /// Blocks control can go to next, not counting calls.
+    pub successors: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub entry: usize,
+    /// `N` if the function starts with `arb #N`.
+    pub frame_size: Option<i64>,
+    /// Start addresses of the function's blocks, in order.
+    pub blocks: Vec<usize>,
+    /// Entries of the functions this one calls.
+    pub calls: BTreeSet<usize>,
+}
+
+#[derive(Debug)]
+pub struct ControlFlowGraph {
+    disassembly: Disassembly,
+    pub blocks: BTreeMap<usize, BasicBlock>,
+    pub functions: BTreeMap<usize, Function>,
+}
+
+impl ControlFlowGraph {
+    pub fn new(program: &Program) -> Self {
+        let disassembly = disassemble(program);
+        let code = disassembly.code();
+        let instructions: Vec<(usize, Instruction)> = disassembly.instructions()
+            .map(|address| (address, Instruction::try_from(code[address]).unwrap()))
+            .collect();
+        let is_instruction = |address: usize| {
+            instructions.binary_search_by_key(&address, |&(address, _)| address).is_ok()
+        };
+
+        // A block starts at address 0, at every jump target, and after every
+        // jump or halt.
+        let mut leaders = BTreeSet::new();
+        leaders.insert(0);
+        for (address, inst) in &instructions {
+            let next = address + inst.length();
+            match inst.opcode() {
+                Opcode::JmpT | Opcode::JmpF => {
+                    if inst.param_mode(1) == ParameterMode::Immediate {
+                        if let Ok(target) = usize::try_from(code[address + 2]) {
+                            leaders.insert(target);
+                        }
+                    }
+                    leaders.insert(next);
+                }
+                Opcode::Halt => { leaders.insert(next); }
+                _ => {}
+            }
+        }
+        leaders.retain(|&address| is_instruction(address));
+
+        let mut blocks = BTreeMap::new();
+        let mut idx = 0;
+        while idx < instructions.len() {
+            let start = instructions[idx].0;
+            let mut block_instructions = Vec::new();
+
+            let exit = loop {
+                let (address, inst) = &instructions[idx];
+                block_instructions.push(*address);
+                idx += 1;
+
+                let end = address + inst.length();
+                match inst.opcode() {
+                    Opcode::JmpT | Opcode::JmpF => break jump_exit(&disassembly, *address, inst),
+                    Opcode::Halt => break BlockExit::Halt,
+                    _ => {}
+                }
+                if idx == instructions.len()
+                    || instructions[idx].0 != end
+                    || leaders.contains(&end)
+                {
+                    break BlockExit::FallThrough;
+                }
+            };
+
+            let last = *block_instructions.last().unwrap();
+            let end = last + Instruction::try_from(code[last]).unwrap().length();
+            let target = usize::try_from(code.get(last + 2).copied().unwrap_or(-1))
+                .ok()
+                .filter(|&target| is_instruction(target));
+            let successors = match exit {
+                BlockExit::FallThrough | BlockExit::Call { .. } => vec![end],
+                BlockExit::Jump => target.into_iter().collect(),
+                BlockExit::Branch => target.into_iter().chain(Some(end)).collect(),
+                BlockExit::Indirect => {
+                    if is_unconditional(code, last) { vec![] } else { vec![end] }
+                }
+                BlockExit::Halt | BlockExit::Return => vec![],
+            };
+            let successors = successors.into_iter().filter(|&address| is_instruction(address)).collect();
+
+            blocks.insert(start, BasicBlock {
+                start,
+                end,
+                instructions: block_instructions,
+                exit,
+                successors,
+            });
+        }
+
+        let mut entries: BTreeSet<usize> = blocks.values()
+            .filter_map(|block| match block.exit {
+                BlockExit::Call { callee } => Some(callee),
+                _ => None,
+            })
+            .filter(|callee| blocks.contains_key(callee))
+            .collect();
+        if blocks.contains_key(&0) {
+            entries.insert(0);
+        }
+
+        let functions = entries.iter()
+            .map(|&entry| (entry, function(&blocks, &entries, entry, code)))
+            .collect();
+
+        ControlFlowGraph { disassembly, blocks, functions }
+    }
+
+    pub fn disassembly(&self) -> &Disassembly {
+        &self.disassembly
+    }
+
+    /// `main` for address 0, otherwise `fn_<entry>`.
+    pub fn function_name(&self, entry: usize) -> String {
+        if entry == 0 {
+            "main".to_string()
+        } else {
+            format!("fn_{}", entry)
+        }
+    }
+
+    /// The graph in Graphviz DOT format, with each function in its own
+    /// cluster. Calls are dashed edges. Try `dot -Tsvg`.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph intcode {{").unwrap();
+        writeln!(dot, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+
+        let mut placed = BTreeSet::new();
+        for function in self.functions.values() {
+            writeln!(dot).unwrap();
+            writeln!(dot, "    subgraph cluster_{} {{", function.entry).unwrap();
+            writeln!(dot, "        label=\"{}\";", self.function_name(function.entry)).unwrap();
+            for start in &function.blocks {
+                if placed.insert(*start) {
+                    writeln!(dot, "        {}", self.dot_node(&self.blocks[start])).unwrap();
+                }
+            }
+            writeln!(dot, "    }}").unwrap();
+        }
+
+        // Anything no function reaches, e.g. code only reachable through an
+        // indirect jump.
+        let orphans: Vec<_> = self.blocks.values()
+            .filter(|block| !placed.contains(&block.start))
+            .collect();
+        if !orphans.is_empty() {
+            writeln!(dot).unwrap();
+        }
+        for block in orphans {
+            writeln!(dot, "    {}", self.dot_node(block)).unwrap();
+        }
+
+        writeln!(dot).unwrap();
+        for block in self.blocks.values() {
+            for successor in &block.successors {
+                writeln!(dot, "    b{} -> b{};", block.start, successor).unwrap();
+            }
+            if let BlockExit::Call { callee } = block.exit {
+                if self.blocks.contains_key(&callee) {
+                    writeln!(dot, "    b{} -> b{} [style=dashed];", block.start, callee).unwrap();
+                }
+            }
+        }
+
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+
+    fn dot_node(&self, block: &BasicBlock) -> String {
+        let listing: String = block.instructions.iter()
+            .map(|&address| format!("{}: {}\\l", address, self.disassembly.instruction(address)))
+            .collect();
+        format!("b{} [label=\"{}\"];", block.start, listing.replace('"', "\\\""))
+    }
+}
+
+/// `jt #<nonzero>, ...` or `jf #0, ...`.
+fn is_unconditional(code: &[i64], address: usize) -> bool {
+    let inst = Instruction::try_from(code[address]).unwrap();
+    inst.param_mode(0) == ParameterMode::Immediate && match inst.opcode() {
+        Opcode::JmpT => code[address + 1] != 0,
+        Opcode::JmpF => code[address + 1] == 0,
+        _ => false,
+    }
+}
+
+fn jump_exit(disassembly: &Disassembly, address: usize, inst: &Instruction) -> BlockExit {
+    let code = disassembly.code();
+    let unconditional = is_unconditional(code, address);
+    let never_taken = inst.param_mode(0) == ParameterMode::Immediate && !unconditional;
+
+    if let Some(callee) = disassembly.callee(address) {
+        BlockExit::Call { callee }
+    } else if never_taken {
+        BlockExit::FallThrough
+    } else if inst.param_mode(1) == ParameterMode::Immediate {
+        if unconditional { BlockExit::Jump } else { BlockExit::Branch }
+    } else if unconditional
+        && inst.param_mode(1) == ParameterMode::Relative
+        && code[address + 2] == 0
+    {
+        BlockExit::Return
+    } else {
+        BlockExit::Indirect
+    }
+}
+
+/// Collects the blocks reachable from `entry` without going into another
+/// function.
+fn function(
+    blocks: &BTreeMap<usize, BasicBlock>,
+    entries: &BTreeSet<usize>,
+    entry: usize,
+    code: &[i64],
+) -> Function {
+    let mut reached = BTreeSet::new();
+    let mut queue = vec![entry];
+    while let Some(start) = queue.pop() {
+        if !reached.insert(start) {
+            continue;
+        }
+        for &successor in &blocks[&start].successors {
+            if !entries.contains(&successor) {
+                queue.push(successor);
+            }
+        }
+    }
+
+    let calls = reached.iter()
+        .filter_map(|start| match blocks[start].exit {
+            BlockExit::Call { callee } if entries.contains(&callee) => Some(callee),
+            _ => None,
+        })
+        .collect();
+
+    let frame_size = match Instruction::try_from(code[entry]) {
+        Ok(inst) if inst.opcode() == Opcode::Base
+                 && inst.param_mode(0) == ParameterMode::Immediate
+            => Some(code[entry + 1]),
+        _ => None,
+    };
+
+    Function { entry, frame_size, blocks: reached.into_iter().collect(), calls }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    fn program() -> Program {
+        assemble("
+                    in [x]
+                    jf [x], #done
+            loop:   add #0, #back, bp[0]    ; call double
+                    jt #1, #double
+            back:   lt [x], #100, [t]
+                    jt [t], #loop
+            done:   out [x]
+                    hlt
+            double: arb #1
+                    mul [x], #2, [x]
+                    arb #-1
+                    jt #1, bp[0]
+            x:      .data 0
+            t:      .data 0
+        ").unwrap()
+    }
+
+    #[test]
+    fn finds_blocks_and_functions() {
+        let cfg = ControlFlowGraph::new(&program());
+
+        let exits: Vec<_> = cfg.blocks.values()
+            .map(|block| (block.start, block.exit, block.successors.clone()))
+            .collect();
+        assert_eq!(exits, vec![
+            (0, BlockExit::Branch, vec![19, 5]),
+            (5, BlockExit::Call { callee: 22 }, vec![12]),
+            (12, BlockExit::Branch, vec![5, 19]),
+            (19, BlockExit::Halt, vec![]),
+            (22, BlockExit::Return, vec![]),
+        ]);
+        assert_eq!(cfg.blocks[&5].instructions, vec![5, 9]);
+        assert_eq!(cfg.blocks[&19].end, 22);
+
+        let functions: Vec<_> = cfg.functions.values().cloned().collect();
+        assert_eq!(functions, vec![
+            Function {
+                entry: 0,
+                frame_size: None,
+                blocks: vec![0, 5, 12, 19],
+                calls: vec![22].into_iter().collect(),
+            },
+            Function {
+                entry: 22,
+                frame_size: Some(1),
+                blocks: vec![22],
+                calls: BTreeSet::new(),
+            },
+        ]);
+    }
+
+    #[test]
+    fn exports_dot() {
+        let dot = ControlFlowGraph::new(&program()).to_dot();
+
+        assert!(dot.starts_with("digraph intcode {\n"));
+        assert!(dot.contains("    subgraph cluster_22 {\n        label=\"fn_22\";\n"));
+        assert!(dot.contains("        b19 [label=\"19: out [D33]\\l21: hlt\\l\"];\n"));
+        assert!(dot.contains("    b12 -> b5;\n"));
+        assert!(dot.contains("    b5 -> b22 [style=dashed];\n"));
+        assert!(dot.ends_with("}\n"));
+    }
+}
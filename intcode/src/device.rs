@@ -0,0 +1,265 @@
+//! Pluggable input and output. `VM` is generic over an `IoDevice`: every `In`
+//! reads from it and every `Out` writes to it. `VM::new()` gives a VM with a
+//! `Queue`, which is what `send_input()` and `recv_output()` use, and
+//! `VM::with_io()` takes any other device:
+//!
+//! ```text
+//! let mut vm = VM::with_io(&program, Lines::stdio());
+//! vm.run()?;
+//! ```
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::mpsc::{Receiver, Sender};
+
+pub trait IoDevice {
+    /// The next input, or `None` if there isn't any (yet).
+    fn read(&mut self) -> Option<i64>;
+
+    fn write(&mut self, value: i64);
+}
+
+impl<D: IoDevice + ?Sized> IoDevice for &mut D {
+    fn read(&mut self) -> Option<i64> {
+        (**self).read()
+    }
+
+    fn write(&mut self, value: i64) {
+        (**self).write(value)
+    }
+}
+
+/// Input from a queue, output collected in another one. It's the only device
+/// that can put input back and take output back, which stepping backwards
+/// and snapshots need.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Queue {
+    pub input: VecDeque<i64>,
+    pub output: VecDeque<i64>,
+}
+
+impl Queue {
+    pub fn new<I: IntoIterator<Item = i64>>(input: I) -> Self {
+        Queue {
+            input: input.into_iter().collect(),
+            output: VecDeque::new(),
+        }
+    }
+}
+
+impl IoDevice for Queue {
+    fn read(&mut self) -> Option<i64> {
+        self.input.pop_front()
+    }
+
+    fn write(&mut self, value: i64) {
+        self.output.push_back(value);
+    }
+}
+
+/// Input and output over `mpsc` channels, e.g. to another VM on another
+/// thread. Reading blocks until there's a value, and only gives `None` once
+/// the sender is gone. Output sent after the receiver is gone is dropped.
+#[derive(Debug)]
+pub struct Channels {
+    pub input: Receiver<i64>,
+    pub output: Sender<i64>,
+}
+
+impl IoDevice for Channels {
+    fn read(&mut self) -> Option<i64> {
+        self.input.recv().ok()
+    }
+
+    fn write(&mut self, value: i64) {
+        let _ = self.output.send(value);
+    }
+}
+
+/// Input and output through a pair of closures: `read` is called for each
+/// input and `write` with each output.
+pub struct FnDevice<R, W> {
+    read: R,
+    write: W,
+}
+
+impl<R, W> FnDevice<R, W>
+where
+    R: FnMut() -> Option<i64>,
+    W: FnMut(i64),
+{
+    pub fn new(read: R, write: W) -> Self {
+        FnDevice { read, write }
+    }
+}
+
+impl<R, W> IoDevice for FnDevice<R, W>
+where
+    R: FnMut() -> Option<i64>,
+    W: FnMut(i64),
+{
+    fn read(&mut self) -> Option<i64> {
+        (self.read)()
+    }
+
+    fn write(&mut self, value: i64) {
+        (self.write)(value)
+    }
+}
+
+/// One number per line, in and out: stdin and stdout, files, or any other
+/// reader and writer. Blank lines are skipped. The end of the input is
+/// `None`, and so is a line that isn't a number or an error reading it,
+/// after which `error()` says what went wrong and nothing more is read.
+/// Failing to write output panics, like `println!` does.
+#[derive(Debug)]
+pub struct Lines<R, W> {
+    input: R,
+    output: W,
+    /// How many lines have been read, for error messages.
+    line: usize,
+    error: Option<io::Error>,
+}
+
+impl<R: BufRead, W: Write> Lines<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Lines { input, output, line: 0, error: None }
+    }
+
+    /// Why the input ended early, if it did.
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+}
+
+impl Lines<io::StdinLock<'static>, io::Stdout> {
+    pub fn stdio() -> Self {
+        Lines::new(io::stdin().lock(), io::stdout())
+    }
+}
+
+impl Lines<BufReader<File>, File> {
+    /// Reads input from the file at `input`, and writes output to a new file
+    /// at `output`.
+    pub fn open<P: AsRef<Path>, Q: AsRef<Path>>(input: P, output: Q) -> io::Result<Self> {
+        Ok(Lines::new(BufReader::new(File::open(input)?), File::create(output)?))
+    }
+}
+
+impl<R: BufRead, W: Write> IoDevice for Lines<R, W> {
+    fn read(&mut self) -> Option<i64> {
+        if self.error.is_some() {
+            return None;
+        }
+
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match self.input.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) => self.line += 1,
+                Err(err) => {
+                    self.error = Some(err);
+                    return None;
+                }
+            }
+
+            let text = line.trim();
+            if text.is_empty() {
+                continue;
+            }
+            match text.parse() {
+                Ok(value) => return Some(value),
+                Err(_) => {
+                    self.error = Some(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Line {} isn't a number: {:?}", self.line, text),
+                    ));
+                    return None;
+                }
+            }
+        }
+    }
+
+    fn write(&mut self, value: i64) {
+        writeln!(self.output, "{}", value)
+            .and_then(|_| self.output.flush())
+            .expect("couldn't write output");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+    use std::thread;
+
+    use crate::vm::{ExecuteStatus, Program, VM};
+
+    /// Outputs each input doubled until it reads a 0.
+    fn doubler() -> Program {
+        Program::new(vec![3,15, 1006,15,14, 1002,15,2,16, 4,16, 1105,1,0, 99, 0,0])
+    }
+
+    #[test]
+    fn queue() {
+        let mut vm = VM::with_io(&doubler(), Queue::new(vec![1, 2]));
+        assert_eq!(vm.run(), Ok(ExecuteStatus::NeedInput));
+        vm.io_mut().input.extend(&[3, 0]);
+        assert_eq!(vm.run(), Ok(ExecuteStatus::Halted));
+        assert_eq!(vm.into_io().output, vec![2, 4, 6]);
+    }
+
+    #[test]
+    fn closures() {
+        let mut input = vec![0, 5, 4];
+        let mut output = Vec::new();
+        let mut vm = VM::with_io(&doubler(), FnDevice::new(|| input.pop(), |value| output.push(value)));
+        assert_eq!(vm.run(), Ok(ExecuteStatus::Halted));
+        drop(vm);
+        assert_eq!(output, vec![8, 10]);
+    }
+
+    #[test]
+    fn channels() {
+        let (to_vm, input) = channel();
+        let (output, from_vm) = channel();
+        let vm = thread::spawn(move || {
+            VM::with_io(&doubler(), Channels { input, output }).run()
+        });
+
+        to_vm.send(21).unwrap();
+        assert_eq!(from_vm.recv(), Ok(42));
+        to_vm.send(0).unwrap();
+        assert_eq!(vm.join().unwrap(), Ok(ExecuteStatus::Halted));
+    }
+
+    #[test]
+    fn lines() {
+        let mut output = Vec::new();
+        let lines = Lines::new(&b"7\n\n-3\n"[..], &mut output);
+        let mut vm = VM::with_io(&doubler(), lines);
+        assert_eq!(vm.run(), Ok(ExecuteStatus::NeedInput));
+        assert!(vm.io().error().is_none());
+        drop(vm);
+        assert_eq!(String::from_utf8(output).unwrap(), "14\n-6\n");
+    }
+
+    #[test]
+    fn lines_stop_at_one_that_isnt_a_number() {
+        let mut output = Vec::new();
+        let lines = Lines::new(&b"7\nnot a number\n-3\n"[..], &mut output);
+        let mut vm = VM::with_io(&doubler(), lines);
+        assert_eq!(vm.run(), Ok(ExecuteStatus::NeedInput));
+        assert_eq!(
+            vm.io().error().map(|err| err.to_string()),
+            Some("Line 2 isn't a number: \"not a number\"".to_string()),
+        );
+        // It doesn't carry on past the bad line.
+        assert_eq!(vm.run(), Ok(ExecuteStatus::NeedInput));
+        drop(vm);
+        assert_eq!(String::from_utf8(output).unwrap(), "14\n");
+    }
+}
@@ -0,0 +1,359 @@
+//! A static disassembler. Unlike `Instruction::disassemble()`, which decodes
+//! whatever it's pointed at, this walks the whole program from address 0 to
+//! work out which ints are actually code, and prints everything else as
+//! `.data`. The listing is valid input for `assemble()`:
+//!
+//! ```text
+//!         add [D380], [D379], [D385]          ; 0
+//!         eq [D2639], #310356, [D381]         ; 4
+//!         jt [D381], #L12                     ; 8
+//!         hlt                                 ; 11
+//! L12:    arb #2640                           ; 12
+//! ```
+//!
+//! Jump targets get `L<address>` labels and data that's referred to by
+//! position get `D<address>` labels. Only immediate jump targets can be
+//! followed statically, plus the usual calling convention of storing a
+//! constant return address right before an unconditional jump.
+
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::instruction::{Instruction, Opcode, ParameterMode};
+use crate::vm::Program;
+
+/// How many values go on each `.data` line.
+const DATA_PER_LINE: usize = 8;
+
+/// One line of the listing.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Line {
+    Instruction { address: usize, length: usize },
+    Data { address: usize, length: usize },
+}
+
+#[derive(Debug)]
+pub struct Disassembly {
+    code: Vec<i64>,
+    lines: Vec<Line>,
+    /// Addresses that get a label, and the label's name.
+    labels: HashMap<usize, String>,
+    /// Operands holding a code address (keyed by the operand's own address).
+    code_pointers: HashMap<usize, usize>,
+    /// Jumps that are really function calls, and what they call.
+    calls: HashMap<usize, usize>,
+}
+
+/// Is this an unconditional jump, i.e. `jt #<nonzero>, ...` or `jf #0, ...`?
+fn is_unconditional_jump(code: &[i64], address: usize) -> bool {
+    match Instruction::try_from(code[address]) {
+        Ok(inst) if address + inst.length() <= code.len() => match inst.opcode() {
+            Opcode::JmpT | Opcode::JmpF if inst.param_mode(0) != ParameterMode::Immediate => false,
+            Opcode::JmpT => code[address + 1] != 0,
+            Opcode::JmpF => code[address + 1] == 0,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// If the instruction at `address` just stores a constant (`add #x, #0, ...`
+/// or `mul #x, #1, ...`), returns the address of the operand holding it.
+fn constant_store(code: &[i64], address: usize, inst: &Instruction) -> Option<usize> {
+    use ParameterMode::Immediate;
+
+    if inst.param_mode(0) != Immediate || inst.param_mode(1) != Immediate {
+        return None;
+    }
+    let identity = match inst.opcode() {
+        Opcode::Add => 0,
+        Opcode::Mul => 1,
+        _ => return None,
+    };
+    if code[address + 2] == identity {
+        Some(address + 1)
+    } else if code[address + 1] == identity {
+        Some(address + 2)
+    } else {
+        None
+    }
+}
+
+/// Finds every instruction reachable from address 0. Returns the addresses
+/// of the instructions, of operands that hold code addresses, and of jumps
+/// that are calls.
+fn trace(code: &[i64]) -> (BTreeSet<usize>, HashMap<usize, usize>, HashMap<usize, usize>) {
+    let mut instructions = BTreeSet::new();
+    let mut code_pointers = HashMap::new();
+    let mut calls = HashMap::new();
+    let mut queue = vec![0];
+
+    while let Some(mut address) = queue.pop() {
+        while address < code.len() && !instructions.contains(&address) {
+            let inst = match Instruction::try_from(code[address]) {
+                Ok(inst) if address + inst.length() <= code.len() => inst,
+                _ => break,
+            };
+            instructions.insert(address);
+            let next = address + inst.length();
+
+            match inst.opcode() {
+                Opcode::Halt => break,
+                Opcode::JmpT | Opcode::JmpF => {
+                    let never_taken = inst.param_mode(0) == ParameterMode::Immediate
+                        && (code[address + 1] != 0) == (inst.opcode() == Opcode::JmpF);
+
+                    if inst.param_mode(1) == ParameterMode::Immediate && !never_taken {
+                        if let Ok(target) = usize::try_from(code[address + 2]) {
+                            code_pointers.insert(address + 2, target);
+                            queue.push(target);
+                        }
+                    }
+                    if is_unconditional_jump(code, address) {
+                        break;
+                    }
+                }
+                Opcode::Add | Opcode::Mul => {
+                    // A call: store the return address, then jump.
+                    if let Some(operand) = constant_store(code, address, &inst) {
+                        let return_address = next + 3;
+                        if next + 3 <= code.len()
+                            && is_unconditional_jump(code, next)
+                            && code[operand] == return_address as i64
+                        {
+                            code_pointers.insert(operand, return_address);
+                            queue.push(return_address);
+                            if let Ok(callee) = usize::try_from(code[next + 2]) {
+                                calls.insert(next, callee);
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+
+            address = next;
+        }
+    }
+
+    (instructions, code_pointers, calls)
+}
+
+pub fn disassemble(program: &Program) -> Disassembly {
+    let code = program.code().clone();
+    let (instructions, code_pointers, calls) = trace(&code);
+
+    // Data addresses referred to in position mode by reachable code. Each of
+    // these starts a new `.data` line so it can have a label.
+    let mut data_refs = BTreeSet::new();
+    for &address in &instructions {
+        let inst = Instruction::try_from(code[address]).unwrap();
+        for param in 0 .. inst.length() - 1 {
+            if inst.param_mode(param) == ParameterMode::Position {
+                if let Ok(target) = usize::try_from(code[address + param + 1]) {
+                    if target < code.len() && !instructions.contains(&target) {
+                        data_refs.insert(target);
+                    }
+                }
+            }
+        }
+    }
+
+    // Lay out the listing. A jump into the middle of an instruction that's
+    // already been listed can't get a label, so it stays a plain number.
+    let mut lines = Vec::new();
+    let mut address = 0;
+    while address < code.len() {
+        if instructions.contains(&address) {
+            let length = Instruction::try_from(code[address]).unwrap().length();
+            lines.push(Line::Instruction { address, length });
+            address += length;
+        } else {
+            let start = address;
+            address += 1;
+            while address < code.len()
+                && address - start < DATA_PER_LINE
+                && !instructions.contains(&address)
+                && !data_refs.contains(&address)
+            {
+                address += 1;
+            }
+            lines.push(Line::Data { address: start, length: address - start });
+        }
+    }
+
+    let mut labels = HashMap::new();
+    for line in &lines {
+        match *line {
+            Line::Instruction { address, .. } => {
+                if code_pointers.values().any(|&target| target == address) {
+                    labels.insert(address, format!("L{}", address));
+                }
+            }
+            Line::Data { address, .. } => {
+                if data_refs.contains(&address) {
+                    labels.insert(address, format!("D{}", address));
+                }
+            }
+        }
+    }
+
+    Disassembly { code, lines, labels, code_pointers, calls }
+}
+
+impl Disassembly {
+    pub fn code(&self) -> &[i64] {
+        &self.code
+    }
+
+    /// Addresses of every instruction in the listing, in order.
+    pub fn instructions(&self) -> impl Iterator<Item = usize> + '_ {
+        self.lines.iter().filter_map(|line| match *line {
+            Line::Instruction { address, .. } => Some(address),
+            Line::Data { .. } => None,
+        })
+    }
+
+    pub fn is_code(&self, address: usize) -> bool {
+        self.lines.iter().any(|line| match *line {
+            Line::Instruction { address: start, length } => (start .. start + length).contains(&address),
+            Line::Data { .. } => false,
+        })
+    }
+
+    /// If the jump at `address` is a function call, the address it calls.
+    pub fn callee(&self, address: usize) -> Option<usize> {
+        self.calls.get(&address).copied()
+    }
+
+    /// The instruction at `address` in assembler syntax, e.g. `jt [D381], #L12`.
+    pub fn instruction(&self, address: usize) -> String {
+        let inst = Instruction::try_from(self.code[address]).unwrap();
+        let operands = (0 .. inst.length() - 1)
+            .map(|param| self.operand(&inst, address, param))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("{} {}", inst.opcode().mnemonic(), operands).trim_end().to_string()
+    }
+
+    /// The label at `address`, if anything refers to it.
+    pub fn label(&self, address: usize) -> Option<&str> {
+        self.labels.get(&address).map(String::as_str)
+    }
+
+    fn operand(&self, inst: &Instruction, address: usize, param: usize) -> String {
+        use ParameterMode::*;
+
+        let operand = address + param + 1;
+        let value = self.code[operand];
+        let target = usize::try_from(value).ok();
+
+        match inst.param_mode(param) {
+            Immediate => {
+                match self.code_pointers.get(&operand).and_then(|&target| self.label(target)) {
+                    Some(label) => format!("#{}", label),
+                    None => format!("#{}", value),
+                }
+            }
+            Position => match target.and_then(|target| self.label(target)) {
+                Some(label) if label.starts_with('D') => format!("[{}]", label),
+                _ => format!("[{}]", value),
+            },
+            Relative => format!("bp[{}]", value),
+        }
+    }
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            let (address, text) = match *line {
+                Line::Instruction { address, .. } => (address, self.instruction(address)),
+                Line::Data { address, length } => {
+                    let values = self.code[address .. address + length]
+                        .iter()
+                        .map(i64::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    (address, format!(".data {}", values))
+                }
+            };
+
+            let label = match self.label(address) {
+                Some(label) => format!("{}:", label),
+                None => String::new(),
+            };
+            writeln!(f, "{:<8}{:<36}; {}", label, text, address)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    #[test]
+    fn separates_code_from_data() {
+        let program = assemble("
+                    in [x]
+                    jf [x], #zero
+                    add #0, #back, bp[0]    ; call double
+                    jt #1, #double
+            back:   out [x]
+            zero:   hlt
+                    .data 12345             ; never reached
+            double: mul [x], #2, [x]
+                    jf #0, bp[0]            ; return
+            x:      .data 0, 7, 7, 7
+        ").unwrap();
+
+        let listing = disassemble(&program);
+        assert_eq!(listing.instructions().collect::<Vec<_>>(), vec![0, 2, 5, 9, 12, 14, 16, 20]);
+        assert!(listing.is_code(3));
+        assert!(!listing.is_code(15));
+        assert!(!listing.is_code(23));
+
+        // Leading newline so the first line lines up with the rest.
+        assert_eq!(format!("\n{}", listing), "
+        in [D23]                            ; 0
+        jf [D23], #L14                      ; 2
+        add #0, #L12, bp[0]                 ; 5
+        jt #1, #L16                         ; 9
+L12:    out [D23]                           ; 12
+L14:    hlt                                 ; 14
+        .data 12345                         ; 15
+L16:    mul [D23], #2, [D23]                ; 16
+        jf #0, bp[0]                        ; 20
+D23:    .data 0, 7, 7, 7                    ; 23
+");
+    }
+
+    #[test]
+    fn store_before_a_halt_isnt_a_call() {
+        // Looks like the start of a call, until the halt where the jump
+        // would be.
+        let program = Program::new(vec![1101,0,0,6, 99, 0,0]);
+        assert_eq!(disassemble(&program).to_string().matches("hlt").count(), 1);
+    }
+
+    #[test]
+    fn listing_reassembles_to_the_same_program() {
+        let program = "1,380,379,385,1008,2639,310356,381,1005,381,12,99,109,2640,\
+                       1101,0,0,383,21101,0,25,0,1105,1,27,99,-5,4,383,2105,1,0"
+            .parse::<Program>()
+            .unwrap();
+
+        let listing = disassemble(&program).to_string();
+        assert_eq!(assemble(&listing).unwrap().code(), program.code());
+
+        // Including the one number that can't be negated.
+        for code in &[vec![99, i64::MIN], vec![104, i64::MIN, 204, i64::MIN, 99]] {
+            let program = Program::new(code.clone());
+            let listing = disassemble(&program).to_string();
+            assert_eq!(assemble(&listing).unwrap().code(), program.code(), "{}", listing);
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use std::error::Error;
+use std::fmt;
+
+/// Where the VM was when something went wrong: the instruction pointer, the
+/// raw instruction at that address, and how many instructions it had
+/// successfully executed before that.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct Fault {
+    pub ip: usize,
+    pub instruction: i64,
+    pub cycles: usize,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at ip {} (instruction {}, after {} cycles)",
+               self.ip, self.instruction, self.cycles)
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum VmError {
+    /// The last two digits of the instruction aren't a known opcode.
+    InvalidOpcode(Fault),
+    /// One of the parameter mode digits isn't 0, 1 or 2.
+    InvalidParameterMode(Fault),
+    /// A parameter or jump resolved to an address below zero. The bad address
+    /// is included.
+    NegativeAddress(Fault, i64),
+    /// A parameter or jump resolved to an address above `vm.max_address`.
+    /// The bad address is included.
+    AddressOutOfRange(Fault, i64),
+    /// The instruction tried to write to an immediate mode parameter.
+    ImmediateWrite(Fault),
+    /// `recv_output` was called with nothing in the output queue.
+    OutputUnderflow(Fault),
+    /// An `Add` or `Mul` overflowed with `vm.overflow` set to
+    /// `Overflow::Trap`.
+    Overflow(Fault),
+}
+
+impl VmError {
+    pub fn fault(&self) -> Fault {
+        use VmError::*;
+
+        match *self {
+            InvalidOpcode(fault)
+            | InvalidParameterMode(fault)
+            | NegativeAddress(fault, _)
+            | AddressOutOfRange(fault, _)
+            | ImmediateWrite(fault)
+            | OutputUnderflow(fault)
+            | Overflow(fault)
+                => fault,
+        }
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use VmError::*;
+
+        match self {
+            InvalidOpcode(fault)
+                => write!(f, "Invalid opcode {}", fault),
+            InvalidParameterMode(fault)
+                => write!(f, "Invalid parameter mode {}", fault),
+            NegativeAddress(fault, address)
+                => write!(f, "Negative address {} {}", address, fault),
+            AddressOutOfRange(fault, address)
+                => write!(f, "Address {} out of range {}", address, fault),
+            ImmediateWrite(fault)
+                => write!(f, "Can't write to immediate mode param {}", fault),
+            OutputUnderflow(fault)
+                => write!(f, "No output to receive {}", fault),
+            Overflow(fault)
+                => write!(f, "Arithmetic overflow {}", fault),
+        }
+    }
+}
+
+impl Error for VmError {}
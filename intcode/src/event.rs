@@ -0,0 +1,56 @@
+use smallvec::SmallVec;
+
+use crate::instruction::{Opcode, ParameterMode};
+
+/// Everything that happened when the VM executed a single instruction, as
+/// returned by `VM::step()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepEvent {
+    /// Address of the instruction that was executed.
+    pub ip: usize,
+    /// The raw instruction int, parameter modes and all.
+    pub instruction: i64,
+    pub opcode: Opcode,
+    /// The parameters the instruction actually looked at, in order. A jump
+    /// that isn't taken never resolves its target, so it won't be in here.
+    pub params: SmallVec<[Param; 3]>,
+    pub write: Option<MemoryWrite>,
+    pub jump: Option<Jump>,
+    pub bp: Option<BaseChange>,
+    /// The value consumed from the input queue, for `In`.
+    pub input: Option<i64>,
+    /// The value pushed onto the output queue, for `Out`.
+    pub output: Option<i64>,
+}
+
+/// A parameter after its mode has been applied.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Param {
+    pub mode: ParameterMode,
+    /// The int stored in the instruction itself.
+    pub raw: i64,
+    /// The address the parameter refers to. For immediate mode that's the
+    /// address of the parameter itself.
+    pub address: usize,
+    /// What was stored at `address` before the instruction ran.
+    pub value: i64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MemoryWrite {
+    pub address: usize,
+    pub old: i64,
+    pub new: i64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Jump {
+    Taken { target: usize },
+    NotTaken,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BaseChange {
+    pub old: i64,
+    pub new: i64,
+}
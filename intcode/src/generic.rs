@@ -0,0 +1,278 @@
+//! A VM that's generic over its cell type, for programs whose values don't
+//! fit in an `i64`. It's a plain interpreter: none of `VM`'s debugging,
+//! profiling, tracing or undo support, and no threaded fast path. It does
+//! have budgets, though.
+
+use std::collections::VecDeque;
+
+use crate::budget::Budget;
+use crate::cell::Cell;
+use crate::error::VmError;
+use crate::instruction::{Instruction, Opcode};
+use crate::machine::Machine;
+use crate::memory::Memory;
+use crate::vm::{ExecuteStatus, Overflow, Program};
+
+#[derive(Debug)]
+pub struct GenericVM<C: Cell> {
+    memory: Memory<C>,
+    ip: usize,
+    bp: C,
+    input: VecDeque<C>,
+    output: VecDeque<C>,
+    pub cycles: usize,
+    /// What to do when `Add` or `Mul` overflows. Doesn't matter for
+    /// `BigInt`, which can't.
+    pub overflow: Overflow,
+    /// The highest address the program may use, like `VM::max_address`.
+    pub max_address: Option<usize>,
+    /// Set this to limit how long `execute()` can run for, like
+    /// `VM::budget`.
+    pub budget: Option<Budget>,
+}
+
+impl<C: Cell> GenericVM<C> {
+    pub fn new(program: &Program<C>) -> Self {
+        GenericVM {
+            memory: Memory::new(program.code().clone()),
+            ip: 0,
+            bp: C::from_i64(0),
+            input: VecDeque::new(),
+            output: VecDeque::new(),
+            cycles: 0,
+            overflow: Overflow::default(),
+            max_address: None,
+            budget: None,
+        }
+    }
+
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    pub fn bp(&self) -> &C {
+        &self.bp
+    }
+
+    pub fn memory(&self) -> &Memory<C> {
+        &self.memory
+    }
+
+    pub fn send_input(&mut self, value: C) {
+        self.input.push_back(value);
+    }
+
+    pub fn recv_output(&mut self) -> Result<C, VmError> {
+        self.output.pop_front()
+            .ok_or_else(|| VmError::OutputUnderflow(self.fault()))
+    }
+
+    /// Runs until the program needs input that isn't there yet, produces an
+    /// output, or halts, or until the budget runs out.
+    pub fn execute(&mut self) -> Result<ExecuteStatus, VmError> {
+        loop {
+            let out_of_budget = self.budget.as_ref()
+                .is_some_and(|budget| budget.exhausted(self.cycles, self.memory.allocated()));
+            if out_of_budget {
+                return Ok(ExecuteStatus::BudgetExhausted);
+            }
+
+            match self.step()? {
+                None => return Ok(ExecuteStatus::NeedInput),
+                Some(Opcode::Out) => return Ok(ExecuteStatus::Output),
+                Some(Opcode::Halt) => return Ok(ExecuteStatus::Halted),
+                Some(_) => {}
+            }
+        }
+    }
+
+    /// Executes one instruction and returns its opcode, or returns `None`
+    /// without doing anything if it's an `In` with no input waiting.
+    fn step(&mut self) -> Result<Option<Opcode>, VmError> {
+        let inst = self.decode()?;
+
+        // Check where input's going before looking for it, like `VM` does.
+        if inst.opcode() == Opcode::In {
+            self.write_address(&inst, 0)?;
+            if self.input.is_empty() {
+                return Ok(None);
+            }
+        }
+
+        let mut next_ip = self.ip + inst.length();
+
+        match inst.opcode() {
+            Opcode::Add => {
+                let a = self.param(&inst, 0)?;
+                let b = self.param(&inst, 1)?;
+                let sum = a.add(&b, self.overflow)
+                    .ok_or_else(|| VmError::Overflow(self.fault()))?;
+                self.write_param(&inst, 2, sum)?;
+            }
+            Opcode::Mul => {
+                let a = self.param(&inst, 0)?;
+                let b = self.param(&inst, 1)?;
+                let product = a.mul(&b, self.overflow)
+                    .ok_or_else(|| VmError::Overflow(self.fault()))?;
+                self.write_param(&inst, 2, product)?;
+            }
+            Opcode::In => {
+                let address = self.write_address(&inst, 0)?;
+                *self.memory.get_mut(address) = self.input.pop_front().unwrap();
+            }
+            Opcode::Out => {
+                let value = self.param(&inst, 0)?;
+                self.output.push_back(value);
+            }
+            Opcode::JmpT | Opcode::JmpF => {
+                let value = self.param(&inst, 0)?;
+                if value.is_zero() == (inst.opcode() == Opcode::JmpF) {
+                    let target = self.param(&inst, 1)?;
+                    next_ip = self.address(&target)?;
+                }
+            }
+            Opcode::Lt => {
+                let a = self.param(&inst, 0)?;
+                let b = self.param(&inst, 1)?;
+                self.write_param(&inst, 2, C::from_i64(if a < b { 1 } else { 0 }))?;
+            }
+            Opcode::Eql => {
+                let a = self.param(&inst, 0)?;
+                let b = self.param(&inst, 1)?;
+                self.write_param(&inst, 2, C::from_i64(if a == b { 1 } else { 0 }))?;
+            }
+            Opcode::Base => {
+                let offset = self.param(&inst, 0)?;
+                self.bp = self.bp.add(&offset, Overflow::Wrap).unwrap();
+            }
+            Opcode::Halt => {
+                next_ip = self.ip;
+            }
+        };
+
+        self.ip = next_ip;
+        self.cycles += 1;
+        if let Some(budget) = &mut self.budget {
+            budget.spend();
+        }
+        Ok(Some(inst.opcode()))
+    }
+
+    fn param(&self, inst: &Instruction, param: usize) -> Result<C, VmError> {
+        let address = self.param_address(inst, param)?;
+        Ok(self.read(address))
+    }
+
+    fn write_param(&mut self, inst: &Instruction, param: usize, value: C) -> Result<(), VmError> {
+        let address = self.write_address(inst, param)?;
+        *self.memory.get_mut(address) = value;
+        Ok(())
+    }
+}
+
+impl<C: Cell> Machine<C> for GenericVM<C> {
+    fn ip(&self) -> usize {
+        self.ip
+    }
+
+    fn bp(&self) -> &C {
+        &self.bp
+    }
+
+    fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    fn memory(&self) -> &Memory<C> {
+        &self.memory
+    }
+
+    fn max_address(&self) -> Option<usize> {
+        self.max_address
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cell::BigInt;
+    use crate::error::Fault;
+
+    fn run<C: Cell>(code: &str, input: &[i64]) -> Result<Vec<String>, VmError> {
+        let program: Program<C> = code.parse().ok().unwrap();
+        let mut vm = GenericVM::new(&program);
+        for &value in input {
+            vm.send_input(C::from_i64(value));
+        }
+        let mut output = Vec::new();
+        loop {
+            match vm.execute()? {
+                ExecuteStatus::Output => output.push(vm.recv_output()?.to_string()),
+                ExecuteStatus::Halted => return Ok(output),
+                status => panic!("unexpected {:?}", status),
+            }
+        }
+    }
+
+    // Reads n and prints n!, using relative mode for its two variables.
+    const FACTORIAL: &str = "109,100,203,0,21101,1,0,1,1206,0,29,22202,0,1,1,21201,0,-1,0,1105,1,8,0,0,0,0,0,0,0,204,1,99";
+
+    #[test]
+    fn runs_the_day9_quine_with_every_cell_type() {
+        let quine = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+        let expected: Vec<_> = quine.split(',').map(str::to_string).collect();
+        assert_eq!(run::<i64>(quine, &[]), Ok(expected.clone()));
+        assert_eq!(run::<i128>(quine, &[]), Ok(expected.clone()));
+        assert_eq!(run::<BigInt>(quine, &[]), Ok(expected));
+    }
+
+    #[test]
+    fn factorials_are_exact_with_big_enough_cells() {
+        assert_eq!(run::<i64>(FACTORIAL, &[20]), Ok(vec!["2432902008176640000".to_string()]));
+        assert_eq!(run::<i128>(FACTORIAL, &[30]),
+                   Ok(vec!["265252859812191058636308480000000".to_string()]));
+        assert_eq!(run::<BigInt>(FACTORIAL, &[40]),
+                   Ok(vec!["815915283247897734345611269596115894272000000000".to_string()]));
+
+        let mut vm = GenericVM::<i64>::new(&FACTORIAL.parse().unwrap());
+        vm.overflow = Overflow::Trap;
+        vm.send_input(21);
+        assert!(matches!(vm.execute(), Err(VmError::Overflow(Fault { ip: 11, .. }))));
+    }
+
+    #[test]
+    fn literals_too_big_for_i64() {
+        let code = "1101,100000000000000000000,-1,7,4,7,99,0";
+        assert_eq!(run::<BigInt>(code, &[]), Ok(vec!["99999999999999999999".to_string()]));
+        assert!("1101,100000000000000000000".parse::<Program>().is_err());
+
+        // An address too big for an i64 is clamped, and out of range.
+        let mut vm = GenericVM::<BigInt>::new(&"1,100000000000000000000,0,0,99".parse().unwrap());
+        vm.max_address = Some(1 << 20);
+        assert_eq!(vm.execute(), Err(VmError::AddressOutOfRange(
+            Fault { ip: 0, instruction: 1, cycles: 0 }, i64::MAX)));
+    }
+
+    #[test]
+    fn input_isnt_lost_when_it_cant_be_stored() {
+        let mut vm = GenericVM::<BigInt>::new(&"103,0,99".parse().unwrap());
+        vm.send_input(BigInt::from_i64(5));
+        assert_eq!(vm.execute(), Err(VmError::ImmediateWrite(
+            Fault { ip: 0, instruction: 103, cycles: 0 })));
+        assert_eq!(vm.input.len(), 1);
+
+        // The address is checked even when there's no input to store yet.
+        let mut vm = GenericVM::<BigInt>::new(&"3,-1,99".parse().unwrap());
+        assert_eq!(vm.execute(), Err(VmError::NegativeAddress(
+            Fault { ip: 0, instruction: 3, cycles: 0 }, -1)));
+    }
+
+    #[test]
+    fn stops_when_the_budget_runs_out() {
+        // Jumps to itself forever.
+        let mut vm = GenericVM::<BigInt>::new(&"1105,1,0".parse().unwrap());
+        vm.budget = Some(Budget { cycles: Some(100), ..Budget::default() });
+        assert_eq!(vm.execute(), Ok(ExecuteStatus::BudgetExhausted));
+        assert_eq!(vm.cycles, 100);
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::VecDeque;
+
+use crate::event::StepEvent;
+
+/// Everything needed to undo one instruction.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct Undo {
+    pub ip: usize,
+    /// `bp` before the instruction, if it changed it.
+    pub bp: Option<i64>,
+    /// The address written to, and what was there before.
+    pub write: Option<(usize, i64)>,
+    /// Input consumed, to be put back at the front of the queue.
+    pub input: Option<i64>,
+    /// Output produced, numbered by how many outputs came before it, so we
+    /// can tell whether it's still in the queue.
+    pub output: Option<usize>,
+}
+
+impl Undo {
+    pub fn new(event: &StepEvent, output: Option<usize>) -> Self {
+        Undo {
+            ip: event.ip,
+            bp: event.bp.map(|change| change.old),
+            write: event.write.map(|write| (write.address, write.old)),
+            input: event.input,
+            output,
+        }
+    }
+}
+
+/// An undo log of executed instructions, which is what lets a VM run
+/// backwards. Turn it on with `vm.history = Some(History::new())`, then use
+/// `VM::step_back()` and friends.
+///
+/// Only the program's own effects are undone: changes made with
+/// `set_memory()` stay put, and output that's already been received can't be
+/// taken back.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    undo: VecDeque<Undo>,
+    limit: Option<usize>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        History { undo: VecDeque::new(), limit: None }
+    }
+
+    /// Only remembers the last `limit` instructions, for long runs.
+    pub fn with_limit(limit: usize) -> Self {
+        History { undo: VecDeque::new(), limit: Some(limit) }
+    }
+
+    /// How many instructions we can step back.
+    pub fn len(&self) -> usize {
+        self.undo.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.undo.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+    }
+
+    /// The address of the most recent instruction that wrote to `address`,
+    /// as far back as the history goes.
+    pub fn last_write(&self, address: usize) -> Option<usize> {
+        self.undo.iter()
+            .rev()
+            .find(|undo| matches!(undo.write, Some((written, _)) if written == address))
+            .map(|undo| undo.ip)
+    }
+
+    /// Whether the instruction at `ip` was executed, as far back as the
+    /// history goes.
+    pub fn executed(&self, ip: usize) -> bool {
+        self.undo.iter().any(|undo| undo.ip == ip)
+    }
+
+    pub(crate) fn push(&mut self, undo: Undo) {
+        if let Some(limit) = self.limit {
+            if limit == 0 {
+                return;
+            }
+            if self.undo.len() == limit {
+                self.undo.pop_front();
+            }
+        }
+        self.undo.push_back(undo);
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<Undo> {
+        self.undo.pop_back()
+    }
+}
@@ -3,7 +3,7 @@ use smallvec::SmallVec;
 use std::convert::TryFrom;
 use std::fmt;
 
-#[derive(Debug, Eq, PartialEq, TryFromPrimitive, Copy, Clone)]
+#[derive(Debug, Eq, PartialEq, Hash, TryFromPrimitive, Copy, Clone)]
 #[repr(u8)]
 pub enum Opcode {
     Add  = 1,
@@ -35,6 +35,45 @@ impl Opcode {
                 => 4,
         }
     }
+
+    /// The name the assembler uses for this opcode.
+    pub fn mnemonic(self) -> &'static str {
+        use Opcode::*;
+
+        match self {
+            Add  => "add",
+            Mul  => "mul",
+            In   => "in",
+            Out  => "out",
+            JmpT => "jt",
+            JmpF => "jf",
+            Lt   => "lt",
+            Eql  => "eq",
+            Base => "arb",
+            Halt => "hlt",
+        }
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Self> {
+        use Opcode::*;
+
+        [Add, Mul, In, Out, JmpT, JmpF, Lt, Eql, Base, Halt]
+            .iter()
+            .copied()
+            .find(|opcode| opcode.mnemonic() == mnemonic)
+    }
+
+    /// Which parameter (if any) the instruction writes to. Writes can't use
+    /// immediate mode.
+    pub fn write_param(self) -> Option<usize> {
+        use Opcode::*;
+
+        match self {
+            Add | Mul | Lt | Eql => Some(2),
+            In => Some(0),
+            Out | JmpT | JmpF | Base | Halt => None,
+        }
+    }
 }
 
 impl fmt::Display for Opcode {
@@ -64,6 +103,13 @@ pub enum ParameterMode {
     Relative  = 2,
 }
 
+/// Why an int couldn't be decoded into an `Instruction`.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum DecodeError {
+    InvalidOpcode,
+    InvalidParameterMode,
+}
+
 #[derive(Debug)]
 pub struct Instruction {
     opcode: Opcode,
@@ -71,11 +117,11 @@ pub struct Instruction {
 }
 
 impl TryFrom<i64> for Instruction {
-    type Error = ();
+    type Error = DecodeError;
 
     fn try_from(value: i64) -> Result<Self, Self::Error> {
         let opcode = Opcode::try_from((value % 100) as u8)
-            .map_err(|_| ())?;
+            .map_err(|_| DecodeError::InvalidOpcode)?;
         let param_modes = (1..opcode.length())
             .map(|i| {
                 let place = 10_i64.pow(i as u32 + 1);
@@ -83,7 +129,7 @@ impl TryFrom<i64> for Instruction {
                 ParameterMode::try_from(digit)
             })
             .collect::<Result<_, _>>()
-            .map_err(|_| ())?;
+            .map_err(|_| DecodeError::InvalidParameterMode)?;
 
         Ok(Instruction { opcode, param_modes })
     }
@@ -0,0 +1,46 @@
+mod ascii;
+mod asm;
+mod async_io;
+mod budget;
+mod c;
+mod cell;
+mod cfg;
+mod device;
+mod disasm;
+mod error;
+mod event;
+mod generic;
+mod history;
+mod instruction;
+mod machine;
+mod memory;
+mod network;
+mod profile;
+mod snapshot;
+mod topology;
+mod trace;
+mod vm;
+mod wasm;
+
+pub use ascii::{Ascii, AsciiOutput};
+pub use asm::{assemble, AsmError, AsmErrorKind};
+pub use async_io::AsyncError;
+pub use budget::Budget;
+pub use c::compile_c;
+pub use cell::{BigInt, Cell, ParseBigIntError};
+pub use cfg::{BasicBlock, BlockExit, ControlFlowGraph, Function};
+pub use device::{Channels, FnDevice, IoDevice, Lines, Queue};
+pub use disasm::{disassemble, Disassembly};
+pub use error::{Fault, VmError};
+pub use event::{BaseChange, Jump, MemoryWrite, Param, StepEvent};
+pub use generic::GenericVM;
+pub use history::History;
+pub use instruction::{DecodeError, Instruction, Opcode, ParameterMode};
+pub use memory::Memory;
+pub use network::{Network, NetworkError, NetworkStatus, Route, Router};
+pub use profile::Profile;
+pub use snapshot::{Snapshot, SnapshotError, SNAPSHOT_VERSION};
+pub use topology::{Topology, TopologyError, TopologyErrorKind, TopologyRouter};
+pub use trace::{replay, Divergence, ParseTraceEntryError, Trace, TraceEntry, TraceError, TRACE_VERSION};
+pub use vm::{Access, Overflow, Program, VM, ExecuteStatus};
+pub use wasm::{compile_wasm, WasmModule};
@@ -0,0 +1,82 @@
+//! The parts of running an instruction that `VM` and `GenericVM` share:
+//! decoding it, finding the addresses its parameters refer to, and checking
+//! that writes go somewhere writable. Each VM just says where its registers
+//! and memory are.
+
+use std::convert::TryFrom;
+
+use crate::cell::Cell;
+use crate::error::{Fault, VmError};
+use crate::instruction::{DecodeError, Instruction, ParameterMode};
+use crate::memory::Memory;
+use crate::vm::Overflow;
+
+pub(crate) trait Machine<C: Cell> {
+    fn ip(&self) -> usize;
+    fn bp(&self) -> &C;
+    fn cycles(&self) -> usize;
+    fn memory(&self) -> &Memory<C>;
+    fn max_address(&self) -> Option<usize>;
+
+    /// Where we are right now, for error reporting. Instructions too big for
+    /// an `i64` are clamped.
+    fn fault(&self) -> Fault {
+        Fault {
+            ip: self.ip(),
+            instruction: self.read(self.ip()).clamp_to_i64(),
+            cycles: self.cycles(),
+        }
+    }
+
+    fn read(&self, address: usize) -> C {
+        self.memory().get(address).clone()
+    }
+
+    /// Decodes the instruction at `ip`.
+    fn decode(&self) -> Result<Instruction, VmError> {
+        self.read(self.ip()).to_i64()
+            .ok_or(DecodeError::InvalidOpcode)
+            .and_then(Instruction::try_from)
+            .map_err(|err| match err {
+                DecodeError::InvalidOpcode
+                    => VmError::InvalidOpcode(self.fault()),
+                DecodeError::InvalidParameterMode
+                    => VmError::InvalidParameterMode(self.fault()),
+            })
+    }
+
+    /// Turns a parameter or jump target into an address, if it's in range.
+    /// Addresses too big for an `i64` are clamped, which is plenty far out of
+    /// range already.
+    fn address(&self, value: &C) -> Result<usize, VmError> {
+        let address = value.clamp_to_i64();
+        if address < 0 {
+            return Err(VmError::NegativeAddress(self.fault(), address));
+        }
+        match self.max_address() {
+            Some(max) if address as u64 > max as u64
+                => Err(VmError::AddressOutOfRange(self.fault(), address)),
+            _ => Ok(address as usize),
+        }
+    }
+
+    /// The address a parameter refers to.
+    fn param_address(&self, inst: &Instruction, param: usize) -> Result<usize, VmError> {
+        use ParameterMode::*;
+
+        let raw = self.read(self.ip() + param + 1);
+        match inst.param_mode(param) {
+            Position => self.address(&raw),
+            Relative => self.address(&self.bp().add(&raw, Overflow::Wrap).unwrap()),
+            Immediate => Ok(self.ip() + param + 1),
+        }
+    }
+
+    /// The address a parameter that gets written to refers to.
+    fn write_address(&self, inst: &Instruction, param: usize) -> Result<usize, VmError> {
+        if inst.param_mode(param) == ParameterMode::Immediate {
+            return Err(VmError::ImmediateWrite(self.fault()));
+        }
+        self.param_address(inst, param)
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use crate::cell::Cell;
+
+/// How many cells are allocated at a time past the end of the program.
+pub(crate) const PAGE_SIZE: usize = 1024;
+
+/// A VM's memory. The program, and anything written just past the end of it,
+/// lives in a plain vector. Anything further out goes in fixed-size pages
+/// that are only allocated when something's written there, so a program can
+/// use address 10^12 without needing terabytes of memory.
+///
+/// Reading an address that's never been written is zero, and doesn't
+/// allocate anything.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Memory<C: Cell = i64> {
+    dense: Vec<C>,
+    /// Pages by page number (address / `PAGE_SIZE`). Every page starts at or
+    /// after the end of `dense`.
+    pages: HashMap<usize, Box<[C]>>,
+    zero: C,
+}
+
+impl<C: Cell> Memory<C> {
+    pub fn new(code: Vec<C>) -> Self {
+        Memory {
+            dense: code,
+            pages: HashMap::new(),
+            zero: C::from_i64(0),
+        }
+    }
+
+    /// Rebuilds memory from `dense()` and `pages()`. Panics if a page runs
+    /// past the end of the address space.
+    pub fn from_parts(dense: Vec<C>, pages: Vec<(usize, Vec<C>)>) -> Self {
+        let mut memory = Memory::new(dense);
+        for (start, cells) in pages {
+            for (offset, value) in cells.into_iter().enumerate() {
+                let address = start.checked_add(offset)
+                    .expect("page runs past the end of memory");
+                *memory.get_mut(address) = value;
+            }
+        }
+        memory
+    }
+
+    pub fn get(&self, address: usize) -> &C {
+        if address < self.dense.len() {
+            return &self.dense[address];
+        }
+        match self.pages.get(&(address / PAGE_SIZE)) {
+            Some(page) => &page[address % PAGE_SIZE],
+            None => &self.zero,
+        }
+    }
+
+    /// A cell to write to, allocating it if it isn't already.
+    pub fn get_mut(&mut self, address: usize) -> &mut C {
+        if address >= self.dense.len() && address < self.dense.len() + PAGE_SIZE {
+            self.grow_dense(address);
+        }
+        if address < self.dense.len() {
+            return &mut self.dense[address];
+        }
+
+        let zero = &self.zero;
+        let page = self.pages.entry(address / PAGE_SIZE)
+            .or_insert_with(|| vec![zero.clone(); PAGE_SIZE].into_boxed_slice());
+        &mut page[address % PAGE_SIZE]
+    }
+
+    /// Extends `dense` to the end of the page `address` is in, moving in any
+    /// pages that were already allocated there.
+    fn grow_dense(&mut self, address: usize) {
+        let old_len = self.dense.len();
+        let new_len = (address + 1).div_ceil(PAGE_SIZE) * PAGE_SIZE;
+        self.dense.resize(new_len, self.zero.clone());
+
+        for number in old_len / PAGE_SIZE .. new_len / PAGE_SIZE {
+            if let Some(page) = self.pages.remove(&number) {
+                let start = number * PAGE_SIZE;
+                self.dense[start .. start + PAGE_SIZE].clone_from_slice(&page);
+            }
+        }
+    }
+
+    /// Addresses below this are in the dense part.
+    pub fn dense_len(&self) -> usize {
+        self.dense.len()
+    }
+
+    pub fn dense(&self) -> &[C] {
+        &self.dense
+    }
+
+    /// The allocated pages past the dense part, as their first address and
+    /// their cells, in address order.
+    pub fn pages(&self) -> Vec<(usize, &[C])> {
+        let mut pages: Vec<_> = self.pages.iter()
+            .map(|(&number, page)| (number * PAGE_SIZE, &page[..]))
+            .collect();
+        pages.sort_by_key(|&(start, _)| start);
+        pages
+    }
+
+    /// How many cells are allocated, in the dense part and in pages.
+    pub fn allocated(&self) -> usize {
+        self.dense.len() + self.pages.len() * PAGE_SIZE
+    }
+
+    /// One past the highest allocated address.
+    pub fn len(&self) -> usize {
+        self.pages.keys()
+            .map(|&number| (number + 1) * PAGE_SIZE)
+            .max()
+            .unwrap_or(0)
+            .max(self.dense.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Every cell below `len()`, as one vector. Careful with programs that
+    /// write to far away addresses.
+    pub fn to_vec(&self) -> Vec<C> {
+        (0 .. self.len()).map(|address| self.get(address).clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn far_addresses_get_their_own_pages() {
+        let mut memory: Memory = Memory::new(vec![1, 2, 3]);
+        *memory.get_mut(1_000_000_000_000) = 7;
+
+        assert_eq!(*memory.get(1_000_000_000_000), 7);
+        assert_eq!(*memory.get(999_999_999_999), 0);
+        assert_eq!(*memory.get(5_000_000_000_000), 0);
+        assert_eq!(memory.dense_len(), 3);
+        assert_eq!(memory.pages().len(), 1);
+        assert_eq!(memory.pages()[0].0, 1_000_000_000_000 / PAGE_SIZE * PAGE_SIZE);
+    }
+
+    #[test]
+    fn nearby_pages_join_the_dense_part() {
+        let mut memory: Memory = Memory::new(vec![1, 2, 3]);
+        *memory.get_mut(PAGE_SIZE + 5) = 8;
+        assert_eq!(memory.pages().len(), 1);
+
+        // Writing just past the end grows the dense part over the first
+        // page, and then a page at a time, absorbing the page we wrote to.
+        *memory.get_mut(10) = 4;
+        assert_eq!(memory.dense_len(), PAGE_SIZE);
+        *memory.get_mut(PAGE_SIZE) = 5;
+        assert_eq!(memory.dense_len(), 2 * PAGE_SIZE);
+        assert!(memory.pages().is_empty());
+
+        assert_eq!(&memory.dense()[..4], &[1, 2, 3, 0]);
+        assert_eq!(*memory.get(10), 4);
+        assert_eq!(*memory.get(PAGE_SIZE), 5);
+        assert_eq!(*memory.get(PAGE_SIZE + 5), 8);
+    }
+
+    #[test]
+    fn rebuilds_from_parts() {
+        let mut memory: Memory = Memory::new(vec![1, 2, 3]);
+        *memory.get_mut(50_000) = 9;
+        *memory.get_mut(2) = 6;
+
+        let pages = memory.pages().into_iter()
+            .map(|(start, cells)| (start, cells.to_vec()))
+            .collect();
+        assert_eq!(Memory::from_parts(memory.dense().to_vec(), pages), memory);
+    }
+}
@@ -0,0 +1,371 @@
+//! Running lots of VMs on one thread, passing packets between them. Each
+//! machine gets a turn, round-robin, until it outputs or needs input it
+//! doesn't have, so a run always goes the same way.
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+
+use crate::error::VmError;
+use crate::vm::{ExecuteStatus, Program, VM};
+
+/// What to do with a packet a machine sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Route {
+    /// Send these values to the machine at this index.
+    To(usize, Vec<i64>),
+    /// Send it out of the network: `run()` returns it as
+    /// `NetworkStatus::Outgoing`.
+    Out(Vec<i64>),
+    Drop,
+    /// All of these, e.g. a copy of the packet for each of several machines.
+    Fork(Vec<Route>),
+}
+
+/// Decides where each machine's output goes.
+pub trait Router {
+    /// How many outputs make up one packet, e.g. 3 for an address and an X
+    /// and Y.
+    fn packet_len(&self) -> usize {
+        1
+    }
+
+    /// Where a packet from machine `from` goes.
+    fn route(&mut self, from: usize, packet: Vec<i64>) -> Route;
+
+    /// Input to give machine `machine` when it needs input and has none, or
+    /// `None` to leave it waiting.
+    fn idle_input(&mut self, _machine: usize) -> Option<i64> {
+        None
+    }
+}
+
+/// Any closure can route one-value packets.
+impl<F: FnMut(usize, Vec<i64>) -> Route> Router for F {
+    fn route(&mut self, from: usize, packet: Vec<i64>) -> Route {
+        self(from, packet)
+    }
+}
+
+/// Why `run()` returned. Calling it again carries on from there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetworkStatus {
+    /// Machine `from` sent a packet that the router sent out of the network.
+    /// If it sent more than one at once, each is returned in turn.
+    Outgoing { from: usize, packet: Vec<i64> },
+    /// This machine just halted. The others are still running.
+    Halted(usize),
+    /// Every machine has halted.
+    AllHalted,
+    /// Every machine still running is waiting for input and there's nothing
+    /// in flight, but the router gave some of them idle input to go on with.
+    /// Those idle inputs are still queued.
+    Idle,
+    /// Every machine still running is waiting for input that nobody is going
+    /// to send.
+    Deadlock,
+    /// This machine stopped at a breakpoint, watchpoint or budget.
+    Stopped(usize, ExecuteStatus),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum NetworkError {
+    /// A machine crashed.
+    Vm(usize, VmError),
+    /// A machine sent a packet to a machine that doesn't exist.
+    NoSuchMachine { from: usize, to: usize },
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkError::Vm(machine, err) => write!(f, "Machine {}: {}", machine, err),
+            NetworkError::NoSuchMachine { from, to } => {
+                write!(f, "Machine {} sent a packet to machine {}, which doesn't exist", from, to)
+            }
+        }
+    }
+}
+
+impl Error for NetworkError {}
+
+#[derive(Debug)]
+struct Machine {
+    vm: VM,
+    /// Outputs that don't make a whole packet yet.
+    packet: Vec<i64>,
+    halted: bool,
+}
+
+/// What happened in one machine's turn.
+enum Turn {
+    /// It sent a packet.
+    Sent,
+    /// It needs input, and the router gave it some idle input (or didn't).
+    Waiting { idle_input: bool },
+    Halted,
+    Stopped(ExecuteStatus),
+}
+
+/// A network of VMs, numbered from 0. A machine's input queue is its inbox:
+/// packets sent to it are queued there, and `send()` does the same.
+#[derive(Debug)]
+pub struct Network<R> {
+    machines: Vec<Machine>,
+    router: R,
+    /// Whose turn it is next.
+    next: usize,
+    /// Packets sent out of the network that `run()` hasn't returned yet.
+    outgoing: VecDeque<(usize, Vec<i64>)>,
+}
+
+impl<R: Router> Network<R> {
+    /// `count` machines, all running `program`.
+    pub fn new(program: &Program, count: usize, router: R) -> Self {
+        Network::from_vms((0..count).map(|_| VM::new(program)).collect(), router)
+    }
+
+    pub fn from_vms(vms: Vec<VM>, router: R) -> Self {
+        Network {
+            machines: vms.into_iter()
+                .map(|vm| Machine { vm, packet: Vec::new(), halted: false })
+                .collect(),
+            router,
+            next: 0,
+            outgoing: VecDeque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.machines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.machines.is_empty()
+    }
+
+    pub fn vm(&self, machine: usize) -> &VM {
+        &self.machines[machine].vm
+    }
+
+    pub fn vm_mut(&mut self, machine: usize) -> &mut VM {
+        &mut self.machines[machine].vm
+    }
+
+    pub fn router(&self) -> &R {
+        &self.router
+    }
+
+    pub fn router_mut(&mut self) -> &mut R {
+        &mut self.router
+    }
+
+    pub fn is_halted(&self, machine: usize) -> bool {
+        self.machines[machine].halted
+    }
+
+    /// Queues `value` as input for `machine`.
+    pub fn send(&mut self, machine: usize, value: i64) {
+        self.machines[machine].vm.send_input(value);
+    }
+
+    /// Gives each machine still running a turn in order, over and over,
+    /// until something happens that the caller needs to know about.
+    pub fn run(&mut self) -> Result<NetworkStatus, NetworkError> {
+        // Turns in a row where nothing was sent, and whether any of those
+        // machines got idle input.
+        let mut quiet = 0;
+        let mut idle_input = false;
+        loop {
+            if let Some((from, packet)) = self.outgoing.pop_front() {
+                return Ok(NetworkStatus::Outgoing { from, packet });
+            }
+            let running = self.machines.iter().filter(|machine| !machine.halted).count();
+            if running == 0 {
+                return Ok(NetworkStatus::AllHalted);
+            }
+            if quiet >= running {
+                return Ok(if idle_input { NetworkStatus::Idle } else { NetworkStatus::Deadlock });
+            }
+
+            let machine = self.next;
+            self.next = (machine + 1) % self.machines.len();
+            if self.machines[machine].halted {
+                continue;
+            }
+            match self.turn(machine)? {
+                Turn::Sent => {
+                    quiet = 0;
+                    idle_input = false;
+                }
+                Turn::Waiting { idle_input: fed } => {
+                    quiet += 1;
+                    idle_input |= fed;
+                }
+                Turn::Halted => return Ok(NetworkStatus::Halted(machine)),
+                Turn::Stopped(status) => return Ok(NetworkStatus::Stopped(machine, status)),
+            }
+        }
+    }
+
+    /// Runs `machine` until it sends a whole packet or needs input.
+    fn turn(&mut self, machine: usize) -> Result<Turn, NetworkError> {
+        let packet_len = self.router.packet_len();
+        loop {
+            let m = &mut self.machines[machine];
+            match m.vm.execute().map_err(|err| NetworkError::Vm(machine, err))? {
+                ExecuteStatus::Output => {
+                    let value = m.vm.recv_output().map_err(|err| NetworkError::Vm(machine, err))?;
+                    m.packet.push(value);
+                    if m.packet.len() < packet_len {
+                        continue;
+                    }
+                    let packet = std::mem::take(&mut m.packet);
+                    let route = self.router.route(machine, packet);
+                    self.deliver(machine, route)?;
+                    return Ok(Turn::Sent);
+                }
+                ExecuteStatus::NeedInput => {
+                    let value = self.router.idle_input(machine);
+                    if let Some(value) = value {
+                        m.vm.send_input(value);
+                    }
+                    return Ok(Turn::Waiting { idle_input: value.is_some() });
+                }
+                ExecuteStatus::Halted => {
+                    m.halted = true;
+                    return Ok(Turn::Halted);
+                }
+                status => return Ok(Turn::Stopped(status)),
+            }
+        }
+    }
+
+    fn deliver(&mut self, from: usize, route: Route) -> Result<(), NetworkError> {
+        match route {
+            Route::To(to, packet) => {
+                let target = self.machines.get_mut(to)
+                    .ok_or(NetworkError::NoSuchMachine { from, to })?;
+                for value in packet {
+                    target.vm.send_input(value);
+                }
+            }
+            Route::Out(packet) => self.outgoing.push_back((from, packet)),
+            Route::Drop => {}
+            Route::Fork(routes) => {
+                for route in routes {
+                    self.deliver(from, route)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    #[test]
+    fn runs_day7_feedback_loop() {
+        let program = Program::new(vec![
+            3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,
+            4,27,1001,28,-1,28,1005,28,6,99,0,0,5,
+        ]);
+        let mut last = None;
+        let mut network = Network::new(&program, 5, |from, packet: Vec<i64>| {
+            if from == 4 {
+                last = Some(packet[0]);
+            }
+            Route::To((from + 1) % 5, packet)
+        });
+        for (machine, phase) in [9, 8, 7, 6, 5].iter().enumerate() {
+            network.send(machine, *phase);
+        }
+        network.send(0, 0);
+
+        let mut halted = Vec::new();
+        while let NetworkStatus::Halted(machine) = network.run().unwrap() {
+            halted.push(machine);
+        }
+        assert_eq!(halted, vec![0, 1, 2, 3, 4]);
+        assert_eq!(network.run(), Ok(NetworkStatus::AllHalted));
+        assert_eq!(last, Some(139629729));
+    }
+
+    /// Packets are `[address, value]`, address 255 is outside the network,
+    /// and idle machines get -1.
+    struct Addressed;
+
+    impl Router for Addressed {
+        fn packet_len(&self) -> usize {
+            2
+        }
+
+        fn route(&mut self, _from: usize, packet: Vec<i64>) -> Route {
+            match packet[0] {
+                255 => Route::Out(vec![packet[1]]),
+                to => Route::To(to as usize, vec![packet[1]]),
+            }
+        }
+
+        fn idle_input(&mut self, _machine: usize) -> Option<i64> {
+            Some(-1)
+        }
+    }
+
+    #[test]
+    fn passes_a_token_around_fifty_machines() {
+        // Reads its address, then adds one to every value it gets and passes
+        // it on to the next address, or out of the network after 49. Machine
+        // 0 starts things off.
+        let program = assemble("
+                    in [addr]
+                    jt [addr], #loop
+                    out #1
+                    out #1
+            loop:   in [x]
+                    eq [x], #-1, [t]
+                    jt [t], #loop
+                    add [addr], #1, [next]
+                    eq [next], #50, [t]
+                    jf [t], #send
+                    add #255, #0, [next]
+            send:   out [next]
+                    add [x], #1, [x]
+                    out [x]
+                    jt #1, #loop
+            addr:   .data 0
+            x:      .data 0
+            t:      .data 0
+            next:   .data 0
+        ").unwrap();
+        let mut network = Network::new(&program, 50, Addressed);
+        for machine in 0..50 {
+            network.send(machine, machine as i64);
+        }
+
+        assert_eq!(network.run(), Ok(NetworkStatus::Outgoing { from: 49, packet: vec![50] }));
+        assert_eq!(network.run(), Ok(NetworkStatus::Idle));
+    }
+
+    #[test]
+    fn deadlock_and_crashes() {
+        let program = Program::new(vec![3,0,99]);
+        let mut network = Network::new(&program, 3, |_, packet| Route::To(0, packet));
+        assert_eq!(network.run(), Ok(NetworkStatus::Deadlock));
+
+        network.send(1, 7);
+        assert_eq!(network.run(), Ok(NetworkStatus::Halted(1)));
+        assert_eq!(network.run(), Ok(NetworkStatus::Deadlock));
+        assert!(network.is_halted(1));
+
+        network.send(2, 7);
+        network.vm_mut(2).breakpoints.insert(2);
+        assert_eq!(network.run(), Ok(NetworkStatus::Stopped(2, ExecuteStatus::Breakpoint)));
+
+        let mut network = Network::new(&Program::new(vec![104,1,99]), 1, |_, packet| Route::To(3, packet));
+        assert_eq!(network.run(), Err(NetworkError::NoSuchMachine { from: 0, to: 3 }));
+    }
+}
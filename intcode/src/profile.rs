@@ -0,0 +1,229 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use crate::cfg::ControlFlowGraph;
+use crate::event::{Jump, StepEvent};
+use crate::instruction::{Opcode, ParameterMode};
+use crate::vm::Program;
+
+/// Counts what a VM executes. Turn it on with
+/// `vm.profile = Some(Profile::new(&program))`, run the program, and then
+/// look at `vm.profile` (or print its `report()`).
+///
+/// Functions are the ones `ControlFlowGraph` finds in the original program.
+/// The profiler keeps its own call stack, pushing on a call it knows about
+/// and popping on a `jt #1, bp[0]`-style return.
+#[derive(Debug)]
+pub struct Profile {
+    cfg: ControlFlowGraph,
+    /// Total number of instructions executed.
+    pub total: u64,
+    pub ip_hits: HashMap<usize, u64>,
+    pub opcode_hits: HashMap<Opcode, u64>,
+    /// Instructions executed in each function itself (not counting the
+    /// functions it calls), keyed by entry address.
+    pub function_hits: HashMap<usize, u64>,
+    /// Function entries, innermost last.
+    stack: Vec<usize>,
+    stack_hits: HashMap<Vec<usize>, u64>,
+}
+
+impl Profile {
+    pub fn new(program: &Program) -> Self {
+        Profile {
+            cfg: ControlFlowGraph::new(program),
+            total: 0,
+            ip_hits: HashMap::new(),
+            opcode_hits: HashMap::new(),
+            function_hits: HashMap::new(),
+            stack: vec![0],
+            stack_hits: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn record(&mut self, event: &StepEvent) {
+        self.total += 1;
+        *self.ip_hits.entry(event.ip).or_insert(0) += 1;
+        *self.opcode_hits.entry(event.opcode).or_insert(0) += 1;
+
+        let function = *self.stack.last().unwrap();
+        *self.function_hits.entry(function).or_insert(0) += 1;
+        match self.stack_hits.get_mut(&self.stack[..]) {
+            Some(hits) => *hits += 1,
+            None => { self.stack_hits.insert(self.stack.clone(), 1); }
+        }
+
+        if let Some(Jump::Taken { target }) = event.jump {
+            if self.cfg.disassembly().callee(event.ip) == Some(target) {
+                self.stack.push(target);
+            } else if is_return(event) && self.stack.len() > 1 {
+                self.stack.pop();
+            }
+        }
+    }
+
+    /// The function `address` belongs to, if it's in a known one.
+    fn function_at(&self, address: usize) -> Option<usize> {
+        self.cfg.functions.values()
+            .find(|function| {
+                function.blocks.iter().any(|start| {
+                    (*start .. self.cfg.blocks[start].end).contains(&address)
+                })
+            })
+            .map(|function| function.entry)
+    }
+
+    fn percent(&self, hits: u64) -> f64 {
+        100.0 * hits as f64 / self.total.max(1) as f64
+    }
+
+    /// A human readable summary: the `top` hottest addresses (with their
+    /// disassembly) and loops, and totals per opcode and per function.
+    pub fn report(&self, top: usize) -> String {
+        let mut report = String::new();
+        writeln!(report, "{} instructions executed", self.total).unwrap();
+
+        let mut addresses: Vec<_> = self.ip_hits.iter().collect();
+        addresses.sort_by_key(|&(&address, &hits)| (std::cmp::Reverse(hits), address));
+
+        writeln!(report).unwrap();
+        writeln!(report, "Hottest addresses:").unwrap();
+        for &(&address, &hits) in addresses.iter().take(top) {
+            let function = self.function_at(address)
+                .map(|entry| self.cfg.function_name(entry))
+                .unwrap_or_else(|| "?".to_string());
+            let instruction = if self.cfg.disassembly().instructions().any(|start| start == address) {
+                self.cfg.disassembly().instruction(address)
+            } else {
+                "(not in the original program)".to_string()
+            };
+            writeln!(report, "{:>12} {:>6.2}%  {:>5}  {:<8} {}",
+                     hits, self.percent(hits), address, function, instruction).unwrap();
+        }
+
+        // A loop is a block that jumps back to an earlier block in the same
+        // function. The loop header's hit count is how many times it went
+        // around.
+        let mut loops: BTreeMap<usize, usize> = BTreeMap::new();
+        for block in self.cfg.blocks.values() {
+            for &successor in &block.successors {
+                if successor <= block.start
+                    && self.function_at(successor) == self.function_at(block.start)
+                {
+                    let end = loops.entry(successor).or_insert(block.end);
+                    *end = (*end).max(block.end);
+                }
+            }
+        }
+        let mut loops: Vec<_> = loops.into_iter()
+            .map(|(header, end)| {
+                let hits: u64 = (header .. end)
+                    .filter_map(|address| self.ip_hits.get(&address))
+                    .sum();
+                let iterations = self.ip_hits.get(&header).copied().unwrap_or(0);
+                (header, end, hits, iterations)
+            })
+            .filter(|&(_, _, hits, _)| hits > 0)
+            .collect();
+        loops.sort_by_key(|&(header, _, hits, _)| (std::cmp::Reverse(hits), header));
+
+        writeln!(report).unwrap();
+        writeln!(report, "Hottest loops:").unwrap();
+        for &(header, end, hits, iterations) in loops.iter().take(top) {
+            writeln!(report, "{:>12} {:>6.2}%  {:>5}..{:<5} {} iterations",
+                     hits, self.percent(hits), header, end, iterations).unwrap();
+        }
+
+        let mut opcodes: Vec<_> = self.opcode_hits.iter().collect();
+        opcodes.sort_by_key(|&(&opcode, &hits)| (std::cmp::Reverse(hits), opcode as u8));
+
+        writeln!(report).unwrap();
+        writeln!(report, "By opcode:").unwrap();
+        for (opcode, &hits) in opcodes {
+            writeln!(report, "{:>12} {:>6.2}%  {}", hits, self.percent(hits), opcode).unwrap();
+        }
+
+        let mut functions: Vec<_> = self.function_hits.iter().collect();
+        functions.sort_by_key(|&(&entry, &hits)| (std::cmp::Reverse(hits), entry));
+
+        writeln!(report).unwrap();
+        writeln!(report, "By function:").unwrap();
+        for (&entry, &hits) in functions {
+            writeln!(report, "{:>12} {:>6.2}%  {}",
+                     hits, self.percent(hits), self.cfg.function_name(entry)).unwrap();
+        }
+
+        report
+    }
+
+    /// One line per call stack, like `main;fn_393;fn_601 1234`, which is
+    /// what `flamegraph.pl` and friends expect.
+    pub fn collapsed_stacks(&self) -> String {
+        let mut lines: Vec<String> = self.stack_hits.iter()
+            .map(|(stack, hits)| {
+                let frames: Vec<String> = stack.iter()
+                    .map(|&entry| self.cfg.function_name(entry))
+                    .collect();
+                format!("{} {}\n", frames.join(";"), hits)
+            })
+            .collect();
+        lines.sort();
+        lines.concat()
+    }
+}
+
+/// `jt #1, bp[0]` and friends.
+fn is_return(event: &StepEvent) -> bool {
+    let unconditional = event.params[0].mode == ParameterMode::Immediate;
+    unconditional && matches!(
+        event.params.get(1),
+        Some(param) if param.mode == ParameterMode::Relative && param.raw == 0
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+    use crate::vm::{ExecuteStatus, VM};
+
+    #[test]
+    fn counts_hits_per_address_opcode_and_function() {
+        let program = assemble("
+                    arb #stack
+            loop:   add #0, #back, bp[0]    ; call inc
+                    jt #1, #inc
+            back:   lt [n], #3, [t]
+                    jt [t], #loop
+                    hlt
+            inc:    arb #1
+                    add [n], #1, [n]
+                    arb #-1
+                    jt #1, bp[0]
+            n:      .data 0
+            t:      .data 0
+            stack:  .data 0
+        ").unwrap();
+
+        let mut vm = VM::new(&program);
+        vm.profile = Some(Profile::new(&program));
+        assert_eq!(vm.execute(), Ok(ExecuteStatus::Halted));
+        let profile = vm.profile.unwrap();
+
+        // Set up the stack, three times round a 4 instruction loop and a 4
+        // instruction function, then halt.
+        assert_eq!(profile.total, 26);
+        assert_eq!(profile.ip_hits[&2], 3);
+        assert_eq!(profile.ip_hits[&16], 1);
+        assert_eq!(profile.opcode_hits[&Opcode::JmpT], 9);
+        assert_eq!(profile.opcode_hits[&Opcode::Base], 7);
+        assert_eq!(profile.function_hits[&0], 14);
+        assert_eq!(profile.function_hits[&17], 12);
+        assert_eq!(profile.collapsed_stacks(), "main 14\nmain;fn_17 12\n");
+
+        let report = profile.report(3);
+        assert!(report.starts_with("26 instructions executed\n"));
+        assert!(report.contains("           3  11.54%      2  main     add #0, #L9, bp[0]\n"));
+        assert!(report.contains("          12  46.15%  fn_17\n"));
+    }
+}
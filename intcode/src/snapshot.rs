@@ -0,0 +1,274 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::memory::PAGE_SIZE;
+
+/// Bump this whenever the text format changes, and keep parsing the old
+/// versions if at all possible.
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+/// Everything needed to pick up a VM exactly where it left off. Get one with
+/// `VM::snapshot()` and put it back with `VM::restore()`.
+///
+/// Snapshots are saved as plain text, one field per line:
+///
+/// ```text
+/// intcode-snapshot 2
+/// ip 4
+/// bp 0
+/// cycles 2
+/// input 5,8
+/// output
+/// memory 3,100,3,101,1,100,101,102,4,102,99
+/// page 1048576 0,0,42,...
+/// ```
+///
+/// Lists are comma separated like a program, and may be empty. `memory` is
+/// the dense part of memory, and each `page` line is one of the pages past
+/// it, starting at the given address. Pages start on a multiple of 1024 and
+/// are at most 1024 cells long. Version 1 snapshots, which don't have pages,
+/// still load.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub memory: Vec<i64>,
+    /// Sparse memory pages, by their first address. See `Memory::pages()`.
+    pub pages: Vec<(usize, Vec<i64>)>,
+    pub ip: usize,
+    pub bp: i64,
+    pub input: Vec<i64>,
+    pub output: Vec<i64>,
+    pub cycles: usize,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    /// The first line wasn't `intcode-snapshot <version>`.
+    NotASnapshot,
+    UnsupportedVersion(u32),
+    /// A line couldn't be parsed. Lines are numbered from 1.
+    InvalidLine(usize),
+    MissingField(&'static str),
+    /// `VM::restore()` was given a page past the VM's `max_address`. This is
+    /// the page's first address.
+    AddressOutOfRange(usize),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use SnapshotError::*;
+
+        match self {
+            Io(err) => write!(f, "{}", err),
+            NotASnapshot => write!(f, "Not an Intcode snapshot"),
+            UnsupportedVersion(version)
+                => write!(f, "Unsupported snapshot version {}", version),
+            InvalidLine(line) => write!(f, "Invalid snapshot line {}", line),
+            MissingField(field) => write!(f, "Snapshot is missing `{}`", field),
+            AddressOutOfRange(address)
+                => write!(f, "Snapshot has memory at {}, past the VM's max_address", address),
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(err: io::Error) -> Self {
+        SnapshotError::Io(err)
+    }
+}
+
+impl Snapshot {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        fs::read_to_string(path)?.parse()
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter<'_>, field: &str, values: &[i64]) -> fmt::Result {
+    write!(f, "{}", field)?;
+    if !values.is_empty() {
+        write!(f, " {}", values.iter()
+                              .map(i64::to_string)
+                              .collect::<Vec<_>>()
+                              .join(","))?;
+    }
+    writeln!(f)
+}
+
+fn parse_list(s: &str) -> Option<Vec<i64>> {
+    if s.is_empty() {
+        return Some(Vec::new());
+    }
+    s.split(',')
+        .map(|value| value.parse().ok())
+        .collect()
+}
+
+/// A page's first address and then its cells. It has to start and end
+/// within one page, the way `Memory::pages()` gives them, so that none of
+/// its addresses can overflow.
+fn parse_page(s: &str) -> Option<(usize, Vec<i64>)> {
+    let (start, cells) = match s.find(' ') {
+        Some(space) => (&s[..space], &s[space + 1..]),
+        None => (s, ""),
+    };
+    let start: usize = start.parse().ok()?;
+    let cells = parse_list(cells)?;
+    if start % PAGE_SIZE != 0 || cells.len() > PAGE_SIZE {
+        return None;
+    }
+    Some((start, cells))
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "intcode-snapshot {}", SNAPSHOT_VERSION)?;
+        writeln!(f, "ip {}", self.ip)?;
+        writeln!(f, "bp {}", self.bp)?;
+        writeln!(f, "cycles {}", self.cycles)?;
+        write_list(f, "input", &self.input)?;
+        write_list(f, "output", &self.output)?;
+        write_list(f, "memory", &self.memory)?;
+        for (start, cells) in &self.pages {
+            write_list(f, &format!("page {}", start), cells)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Snapshot {
+    type Err = SnapshotError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines();
+
+        let version = lines.next()
+            .and_then(|header| header.strip_prefix("intcode-snapshot "))
+            .and_then(|version| version.trim().parse::<u32>().ok())
+            .ok_or(SnapshotError::NotASnapshot)?;
+        if version != 1 && version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let mut ip = None;
+        let mut bp = None;
+        let mut cycles = None;
+        let mut input = None;
+        let mut output = None;
+        let mut memory = None;
+        let mut pages = Vec::new();
+
+        for (idx, line) in lines.enumerate() {
+            let line_number = idx + 2;
+            let line = line.trim_end();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (field, value) = match line.find(' ') {
+                Some(space) => (&line[..space], &line[space + 1..]),
+                None => (line, ""),
+            };
+
+            let parsed = match field {
+                "ip" => value.parse().ok().map(|v| ip = Some(v)),
+                "bp" => value.parse().ok().map(|v| bp = Some(v)),
+                "cycles" => value.parse().ok().map(|v| cycles = Some(v)),
+                "input" => parse_list(value).map(|v| input = Some(v)),
+                "output" => parse_list(value).map(|v| output = Some(v)),
+                "memory" => parse_list(value).map(|v| memory = Some(v)),
+                "page" if version >= 2 => parse_page(value).map(|page| pages.push(page)),
+                _ => None,
+            };
+            if parsed.is_none() {
+                return Err(SnapshotError::InvalidLine(line_number));
+            }
+        }
+
+        Ok(Snapshot {
+            ip: ip.ok_or(SnapshotError::MissingField("ip"))?,
+            bp: bp.ok_or(SnapshotError::MissingField("bp"))?,
+            cycles: cycles.ok_or(SnapshotError::MissingField("cycles"))?,
+            input: input.ok_or(SnapshotError::MissingField("input"))?,
+            output: output.ok_or(SnapshotError::MissingField("output"))?,
+            memory: memory.ok_or(SnapshotError::MissingField("memory"))?,
+            pages,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_text() {
+        let snapshot = Snapshot {
+            memory: vec![3,100,3,101,1,100,101,102,4,102,99],
+            pages: vec![(4096, vec![0, 7, 0]), (1 << 40, vec![-1])],
+            ip: 4,
+            bp: -3,
+            input: vec![5, 8],
+            output: vec![],
+            cycles: 2,
+        };
+
+        let text = snapshot.to_string();
+        assert_eq!(text, "\
+intcode-snapshot 2
+ip 4
+bp -3
+cycles 2
+input 5,8
+output
+memory 3,100,3,101,1,100,101,102,4,102,99
+page 4096 0,7,0
+page 1099511627776 -1
+");
+        assert_eq!(text.parse::<Snapshot>().unwrap(), snapshot);
+
+        let version_1 = "intcode-snapshot 1\nip 0\nbp 0\ncycles 0\ninput\noutput\nmemory 99\n";
+        assert_eq!(version_1.parse::<Snapshot>().unwrap().memory, vec![99]);
+    }
+
+    #[test]
+    fn rejects_bad_snapshots() {
+        assert!(matches!(
+            "3,0,4,0,99".parse::<Snapshot>(),
+            Err(SnapshotError::NotASnapshot)
+        ));
+        assert!(matches!(
+            "intcode-snapshot 3\n".parse::<Snapshot>(),
+            Err(SnapshotError::UnsupportedVersion(3))
+        ));
+        assert!(matches!(
+            "intcode-snapshot 1\nip 0\nbp zero\n".parse::<Snapshot>(),
+            Err(SnapshotError::InvalidLine(3))
+        ));
+        assert!(matches!(
+            "intcode-snapshot 1\nip 0\nbp 0\ncycles 0\ninput\noutput\n".parse::<Snapshot>(),
+            Err(SnapshotError::MissingField("memory"))
+        ));
+
+        // Pages can't start part way through a page, or run past the end of
+        // memory.
+        let with_page = |page: &str| format!(
+            "intcode-snapshot 2\nip 0\nbp 0\ncycles 0\ninput\noutput\nmemory 99\n{}\n", page);
+        for page in &["page 18446744073709551615 1,2", "page 1000 1"] {
+            assert!(matches!(
+                with_page(page).parse::<Snapshot>(),
+                Err(SnapshotError::InvalidLine(8))
+            ));
+        }
+        assert!(with_page("page 18446744073709550592 1").parse::<Snapshot>().is_ok());
+    }
+}
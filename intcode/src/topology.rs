@@ -0,0 +1,258 @@
+//! Describing how the machines in a `Network` are wired together, in code or
+//! in a little text format:
+//!
+//! ```text
+//! # Day 7, part 2: a feedback loop, where E's outputs also leave the network.
+//! A -> B -> C -> D -> E -> [A, out]
+//! ```
+//!
+//! Each line is a chain of nodes joined by `->`, and every node in one group
+//! sends its output to every node in the next. A group is a node name, or a
+//! list of them in square brackets, so `A -> [B, C]` sends a copy of each of
+//! A's outputs to both B and C, and `[B, C] -> D` merges B's and C's outputs
+//! into D's input. `out` is where outputs leave the network. A line with no
+//! `->` just declares its nodes, and `#` starts a comment.
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::network::{Network, Route, Router};
+use crate::vm::{Program, VM};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Node(usize),
+    Out,
+}
+
+/// A set of named nodes, numbered in the order they were added, and where
+/// each one's output goes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Topology {
+    names: Vec<String>,
+    targets: Vec<Vec<Target>>,
+}
+
+impl Topology {
+    pub fn new() -> Self {
+        Topology::default()
+    }
+
+    /// The index of the node called `name`, which is added if it isn't
+    /// there yet.
+    pub fn add_node(&mut self, name: &str) -> usize {
+        self.index(name).unwrap_or_else(|| {
+            self.names.push(name.to_string());
+            self.targets.push(Vec::new());
+            self.names.len() - 1
+        })
+    }
+
+    /// Sends `from`'s output to `to`, adding either node if needed.
+    pub fn connect(&mut self, from: &str, to: &str) {
+        let from = self.add_node(from);
+        let to = Target::Node(self.add_node(to));
+        self.add_target(from, to);
+    }
+
+    /// Sends `from`'s output out of the network.
+    pub fn connect_out(&mut self, from: &str) {
+        let from = self.add_node(from);
+        self.add_target(from, Target::Out);
+    }
+
+    fn add_target(&mut self, from: usize, to: Target) {
+        if !self.targets[from].contains(&to) {
+            self.targets[from].push(to);
+        }
+    }
+
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|node| node == name)
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// A network with a machine running `program` for each node, in order.
+    /// Each machine's input starts off with its entry in `inputs` (e.g. a
+    /// phase setting), if it has one.
+    pub fn network(&self, program: &Program, inputs: &[Vec<i64>]) -> Network<TopologyRouter> {
+        let vms = (0..self.len())
+            .map(|idx| {
+                let mut vm = VM::new(program);
+                for &value in inputs.get(idx).into_iter().flatten() {
+                    vm.send_input(value);
+                }
+                vm
+            })
+            .collect();
+        Network::from_vms(vms, TopologyRouter { targets: self.targets.clone() })
+    }
+}
+
+/// Routes each output along a `Topology`'s connections. An output from a
+/// node that isn't connected to anything is dropped.
+#[derive(Debug, Clone)]
+pub struct TopologyRouter {
+    targets: Vec<Vec<Target>>,
+}
+
+impl Router for TopologyRouter {
+    fn route(&mut self, from: usize, packet: Vec<i64>) -> Route {
+        Route::Fork(
+            self.targets[from].iter()
+                .map(|target| match *target {
+                    Target::Node(to) => Route::To(to, packet.clone()),
+                    Target::Out => Route::Out(packet.clone()),
+                })
+                .collect(),
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct TopologyError {
+    pub line: usize,
+    pub kind: TopologyErrorKind,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TopologyErrorKind {
+    /// A node name that's empty or has something other than letters,
+    /// digits, `_` or `-` in it.
+    InvalidName(String),
+    /// A `[` without a `]`, or the other way round.
+    UnmatchedBracket(String),
+    /// `out` sending output somewhere.
+    OutAsSource,
+}
+
+impl fmt::Display for TopologyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use TopologyErrorKind::*;
+
+        write!(f, "Line {}: ", self.line)?;
+        match &self.kind {
+            InvalidName(name) => write!(f, "Invalid node name `{}`", name),
+            UnmatchedBracket(group) => write!(f, "Unmatched bracket in `{}`", group),
+            OutAsSource => write!(f, "`out` can't send output anywhere"),
+        }
+    }
+}
+
+impl Error for TopologyError {}
+
+/// The node names in one group, e.g. `A` or `[B, C]`.
+fn parse_group(group: &str) -> Result<Vec<&str>, TopologyErrorKind> {
+    let group = group.trim();
+    let names = match (group.strip_prefix('['), group.strip_suffix(']')) {
+        (Some(_), Some(_)) => group[1..group.len() - 1].split(',').map(str::trim).collect(),
+        (None, None) => vec![group],
+        _ => return Err(TopologyErrorKind::UnmatchedBracket(group.to_string())),
+    };
+    for name in &names {
+        let valid = !name.is_empty()
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid {
+            return Err(TopologyErrorKind::InvalidName(name.to_string()));
+        }
+    }
+    Ok(names)
+}
+
+impl FromStr for Topology {
+    type Err = TopologyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut topology = Topology::new();
+        for (idx, line) in s.lines().enumerate() {
+            let err = |kind| TopologyError { line: idx + 1, kind };
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let groups = line.split("->")
+                .map(parse_group)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(err)?;
+            for name in groups.iter().flatten() {
+                if *name != "out" {
+                    topology.add_node(name);
+                }
+            }
+            for pair in groups.windows(2) {
+                for &from in &pair[0] {
+                    if from == "out" {
+                        return Err(err(TopologyErrorKind::OutAsSource));
+                    }
+                    for &to in &pair[1] {
+                        match to {
+                            "out" => topology.connect_out(from),
+                            _ => topology.connect(from, to),
+                        }
+                    }
+                }
+            }
+        }
+        Ok(topology)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::NetworkStatus;
+
+    #[test]
+    fn parses_chains_fan_out_and_merges() {
+        let topology: Topology = "
+            # comment
+            Z
+            A -> [B, C] # fan out
+            [B, C] -> D -> out
+        ".parse().unwrap();
+
+        let mut expected = Topology::new();
+        expected.add_node("Z");
+        expected.connect("A", "B");
+        expected.connect("A", "C");
+        expected.connect("B", "D");
+        expected.connect("C", "D");
+        expected.connect_out("D");
+        assert_eq!(topology, expected);
+        assert_eq!(topology.names(), ["Z", "A", "B", "C", "D"]);
+        assert_eq!(topology.index("D"), Some(4));
+
+        let error = |line, kind| Err(TopologyError { line, kind });
+        assert_eq!("A -> [B, C".parse::<Topology>(), error(1, TopologyErrorKind::UnmatchedBracket("[B, C".into())));
+        assert_eq!("A ->\n-> B".parse::<Topology>(), error(1, TopologyErrorKind::InvalidName("".into())));
+        assert_eq!("A -> B C".parse::<Topology>(), error(1, TopologyErrorKind::InvalidName("B C".into())));
+        assert_eq!("A\nout -> A".parse::<Topology>(), error(2, TopologyErrorKind::OutAsSource));
+    }
+
+    #[test]
+    fn runs_a_diamond() {
+        // Reads two numbers and outputs their sum.
+        let program = Program::new(vec![3,11, 3,12, 1,11,12,13, 4,13, 99, 0,0,0]);
+        let topology: Topology = "A -> [B, C] -> D -> out".parse().unwrap();
+        let mut network = topology.network(&program, &[vec![1, 2], vec![10], vec![20]]);
+
+        let mut status = network.run().unwrap();
+        while let NetworkStatus::Halted(_) = status {
+            status = network.run().unwrap();
+        }
+        assert_eq!(status, NetworkStatus::Outgoing { from: 3, packet: vec![36] });
+    }
+}
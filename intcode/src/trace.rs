@@ -0,0 +1,326 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::error::VmError;
+use crate::event::StepEvent;
+use crate::instruction::Opcode;
+use crate::vm::{Program, VM};
+
+/// Bump this whenever the text format changes.
+pub const TRACE_VERSION: u32 = 1;
+
+/// What one instruction did, as far as anyone outside the VM can tell.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub ip: usize,
+    pub opcode: Opcode,
+    /// The address written to, and the value written.
+    pub write: Option<(usize, i64)>,
+    pub input: Option<i64>,
+    pub output: Option<i64>,
+}
+
+impl From<&StepEvent> for TraceEntry {
+    fn from(event: &StepEvent) -> Self {
+        TraceEntry {
+            ip: event.ip,
+            opcode: event.opcode,
+            write: event.write.map(|write| (write.address, write.new)),
+            input: event.input,
+            output: event.output,
+        }
+    }
+}
+
+/// Every instruction a VM executed. Turn recording on with
+/// `vm.trace = Some(Trace::new())` and check a program against it later with
+/// `replay()`.
+///
+/// Traces are saved as plain text, one instruction per line: the `ip`, the
+/// opcode, then `w<address>=<value>` for a write, `i<value>` for input
+/// consumed and `o<value>` for output produced.
+///
+/// ```text
+/// intcode-trace 1
+/// 0 3 i8 w9=8
+/// 2 8 w9=1
+/// 6 4 o1
+/// 8 99
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace {
+    pub entries: Vec<TraceEntry>,
+}
+
+#[derive(Debug)]
+pub enum TraceError {
+    Io(io::Error),
+    /// The first line wasn't `intcode-trace <version>`.
+    NotATrace,
+    UnsupportedVersion(u32),
+    /// A line couldn't be parsed. Lines are numbered from 1.
+    InvalidLine(usize),
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use TraceError::*;
+
+        match self {
+            Io(err) => write!(f, "{}", err),
+            NotATrace => write!(f, "Not an Intcode trace"),
+            UnsupportedVersion(version)
+                => write!(f, "Unsupported trace version {}", version),
+            InvalidLine(line) => write!(f, "Invalid trace line {}", line),
+        }
+    }
+}
+
+impl Error for TraceError {}
+
+impl From<io::Error> for TraceError {
+    fn from(err: io::Error) -> Self {
+        TraceError::Io(err)
+    }
+}
+
+impl Trace {
+    pub fn new() -> Self {
+        Trace { entries: Vec::new() }
+    }
+
+    pub(crate) fn record(&mut self, event: &StepEvent) {
+        self.entries.push(TraceEntry::from(event));
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, TraceError> {
+        fs::read_to_string(path)?.parse()
+    }
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.ip, self.opcode as u8)?;
+        // Input comes first because `In` reads it before writing it.
+        if let Some(value) = self.input {
+            write!(f, " i{}", value)?;
+        }
+        if let Some((address, value)) = self.write {
+            write!(f, " w{}={}", address, value)?;
+        }
+        if let Some(value) = self.output {
+            write!(f, " o{}", value)?;
+        }
+        Ok(())
+    }
+}
+
+/// A line of a trace that isn't a `TraceEntry`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ParseTraceEntryError;
+
+impl fmt::Display for ParseTraceEntryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid trace entry")
+    }
+}
+
+impl Error for ParseTraceEntryError {}
+
+impl FromStr for TraceEntry {
+    type Err = ParseTraceEntryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        fn number<T: FromStr>(s: Option<&str>) -> Result<T, ParseTraceEntryError> {
+            s.ok_or(ParseTraceEntryError)?.parse().map_err(|_| ParseTraceEntryError)
+        }
+
+        let mut fields = s.split(' ');
+        let ip = number(fields.next())?;
+        let opcode = Opcode::try_from(number::<u8>(fields.next())?).map_err(|_| ParseTraceEntryError)?;
+
+        let mut entry = TraceEntry { ip, opcode, write: None, input: None, output: None };
+        for field in fields {
+            let mut chars = field.chars();
+            let kind = chars.next();
+            let value = chars.as_str();
+            match kind {
+                Some('w') => {
+                    let mut parts = value.splitn(2, '=');
+                    let address = number(parts.next())?;
+                    entry.write = Some((address, number(parts.next())?));
+                }
+                Some('i') => entry.input = Some(number(Some(value))?),
+                Some('o') => entry.output = Some(number(Some(value))?),
+                _ => return Err(ParseTraceEntryError),
+            }
+        }
+
+        Ok(entry)
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "intcode-trace {}", TRACE_VERSION)?;
+        for entry in &self.entries {
+            writeln!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Trace {
+    type Err = TraceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines();
+
+        let version = lines.next()
+            .and_then(|header| header.strip_prefix("intcode-trace "))
+            .and_then(|version| version.trim().parse::<u32>().ok())
+            .ok_or(TraceError::NotATrace)?;
+        if version != TRACE_VERSION {
+            return Err(TraceError::UnsupportedVersion(version));
+        }
+
+        let entries = lines.enumerate()
+            .map(|(idx, line)| (idx + 2, line.trim_end()))
+            .filter(|(_, line)| !line.is_empty())
+            .map(|(line_number, line)| {
+                line.parse().map_err(|_| TraceError::InvalidLine(line_number))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Trace { entries })
+    }
+}
+
+/// Where a replay stopped matching its trace. Steps are numbered from 0.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Divergence {
+    /// The instruction did something different.
+    Mismatch { step: usize, expected: TraceEntry, found: TraceEntry },
+    /// The program wanted input the trace doesn't have.
+    NeedInput { step: usize, expected: TraceEntry },
+    /// The program crashed.
+    Crashed { step: usize, expected: TraceEntry, error: VmError },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Divergence::*;
+
+        match self {
+            Mismatch { step, expected, found }
+                => write!(f, "Step {}: expected `{}`, found `{}`", step, expected, found),
+            NeedInput { step, expected }
+                => write!(f, "Step {}: expected `{}`, but the program wants input", step, expected),
+            Crashed { step, expected, error }
+                => write!(f, "Step {}: expected `{}`, but: {}", step, expected, error),
+        }
+    }
+}
+
+impl Error for Divergence {}
+
+/// Runs `program` from the start, feeding it the trace's input, and checks
+/// every instruction against the trace. Returns where they first differ, or
+/// `None` if they don't.
+///
+/// Any setup done before recording (like day 13 inserting quarters with
+/// `set_memory`) has to be done to the program too.
+pub fn replay(program: &Program, trace: &Trace) -> Option<Divergence> {
+    let mut vm = VM::new(program);
+
+    for (step, expected) in trace.entries.iter().enumerate() {
+        if let Some(value) = expected.input {
+            vm.send_input(value);
+        }
+
+        let found = match vm.step() {
+            Ok(Some(event)) => TraceEntry::from(&event),
+            Ok(None) => return Some(Divergence::NeedInput { step, expected: *expected }),
+            Err(error) => return Some(Divergence::Crashed { step, expected: *expected, error }),
+        };
+
+        if found != *expected {
+            return Some(Divergence::Mismatch { step, expected: *expected, found });
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::ExecuteStatus;
+
+    fn record(program: &Program, input: i64) -> Trace {
+        let mut vm = VM::new(program);
+        vm.trace = Some(Trace::new());
+        vm.send_input(input);
+        while vm.execute() != Ok(ExecuteStatus::Halted) {}
+        vm.trace.unwrap()
+    }
+
+    #[test]
+    fn round_trips_through_text() {
+        // Is the input equal to 8?
+        let program = Program::new(vec![3,9,8,9,10,9,4,9,99,-1,8]);
+        let trace = record(&program, 8);
+
+        let text = trace.to_string();
+        assert_eq!(text, "\
+intcode-trace 1
+0 3 i8 w9=8
+2 8 w9=1
+6 4 o1
+8 99
+");
+        assert_eq!(text.parse::<Trace>().unwrap(), trace);
+
+        assert!(matches!("0 3 i8".parse::<Trace>(), Err(TraceError::NotATrace)));
+        assert!(matches!(
+            "intcode-trace 1\n0 3 i8\n2 8 x9\n".parse::<Trace>(),
+            Err(TraceError::InvalidLine(3))
+        ));
+    }
+
+    #[test]
+    fn bad_lines_are_errors() {
+        for line in &["", "0", "0 1  w1=2", "0 1 é", "0 1 w1", "0 1 w=2", "0 1 i", "0 1 ox", "x 1", "0 42", "0 1 w1=2 "] {
+            assert_eq!(line.parse::<TraceEntry>(), Err(ParseTraceEntryError), "{:?}", line);
+        }
+        assert_eq!("0 1 w1=2".parse::<TraceEntry>().map(|entry| entry.write), Ok(Some((1, 2))));
+    }
+
+    #[test]
+    fn replay_finds_the_first_divergence() {
+        let program = Program::new(vec![3,9,8,9,10,9,4,9,99,-1,8]);
+        let trace = record(&program, 8);
+        assert_eq!(replay(&program, &trace), None);
+
+        // Now compare against 7 instead of 8.
+        let changed = Program::new(vec![3,9,8,9,10,9,4,9,99,-1,7]);
+        assert_eq!(replay(&changed, &trace), Some(Divergence::Mismatch {
+            step: 1,
+            expected: TraceEntry { ip: 2, opcode: Opcode::Eql, write: Some((9, 1)), input: None, output: None },
+            found: TraceEntry { ip: 2, opcode: Opcode::Eql, write: Some((9, 0)), input: None, output: None },
+        }));
+
+        // And one that crashes instead.
+        let broken = Program::new(vec![3,9,8,9,10,9,42,9,99,-1,8]);
+        assert!(matches!(replay(&broken, &trace), Some(Divergence::Crashed { step: 2, .. })));
+    }
+}
@@ -0,0 +1,1176 @@
+use smallvec::SmallVec;
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::budget::Budget;
+use crate::device::{IoDevice, Queue};
+use crate::error::VmError;
+use crate::event::{BaseChange, Jump, MemoryWrite, Param, StepEvent};
+use crate::history::{History, Undo};
+use crate::instruction::{Opcode, Instruction, ParameterMode};
+use crate::machine::Machine;
+use crate::memory::Memory;
+use crate::profile::Profile;
+use crate::snapshot::{Snapshot, SnapshotError};
+use crate::trace::Trace;
+
+mod threaded;
+
+/// An Intcode program. The cells are `i64` unless it's for a `GenericVM`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program<C = i64> {
+    code: Vec<C>,
+}
+
+impl<C> Program<C> {
+    pub fn new(code: Vec<C>) -> Self {
+        Program { code }
+    }
+
+    pub fn code(&self) -> &Vec<C> {
+        &self.code
+    }
+}
+
+impl<C: FromStr> FromStr for Program<C> {
+    type Err = C::Err;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let code: Vec<C> = s
+            .trim_end()
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Program { code })
+    }
+}
+
+impl<C: fmt::Display> fmt::Display for Program<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.code.iter()
+                            .map(C::to_string)
+                            .collect::<Vec<_>>()
+                            .join(","))
+    }
+}
+
+/// What `Add` and `Mul` do when the result doesn't fit in an `i64`. The
+/// default is `Wrap`, which is what the WebAssembly and C backends do too.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub enum Overflow {
+    /// Wrap around, two's complement style.
+    #[default]
+    Wrap,
+    /// Stop with `VmError::Overflow`.
+    Trap,
+    /// Clamp to `i64::MIN` or `i64::MAX`.
+    Saturate,
+}
+
+impl Overflow {
+    /// `a + b` under this policy, or `None` if it should trap.
+    pub fn add(self, a: i64, b: i64) -> Option<i64> {
+        match self {
+            Overflow::Wrap => Some(a.wrapping_add(b)),
+            Overflow::Trap => a.checked_add(b),
+            Overflow::Saturate => Some(a.saturating_add(b)),
+        }
+    }
+
+    /// `a * b` under this policy, or `None` if it should trap.
+    pub fn mul(self, a: i64, b: i64) -> Option<i64> {
+        match self {
+            Overflow::Wrap => Some(a.wrapping_mul(b)),
+            Overflow::Trap => a.checked_mul(b),
+            Overflow::Saturate => Some(a.saturating_mul(b)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum ExecuteStatus {
+    NeedInput,
+    Output,
+    Halted,
+    /// The VM is about to execute an instruction in `breakpoints`. Calling
+    /// `execute()` again carries on from there.
+    Breakpoint,
+    /// The instruction that just executed touched a watched address. If it
+    /// was an `Out`, the next `execute()` returns `Output` straight away.
+    Watchpoint { address: usize, access: Access },
+    /// Something in `vm.budget` ran out before the next instruction. Top it
+    /// up and call `execute()` again to carry on.
+    BudgetExhausted,
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// An Intcode VM, reading input from and writing output to `D`. The default,
+/// `Queue`, is what `send_input()` and `recv_output()` use, and the only
+/// device that stepping backwards and snapshots work with.
+#[derive(Debug)]
+pub struct VM<D = Queue> {
+    memory: Memory,
+    ip: usize,
+    bp: i64,
+    io: D,
+    pub cycles: usize,
+    pub debug: bool,
+    /// Whether `execute()` may take the threaded fast path. It's on by
+    /// default, and only ever taken while the debugging features are off.
+    /// Turn it off to compare the two.
+    pub threaded: bool,
+    /// Addresses of instructions to stop at.
+    pub breakpoints: HashSet<usize>,
+    pub read_watchpoints: HashSet<usize>,
+    pub write_watchpoints: HashSet<usize>,
+    /// Set this to start profiling. See `Profile`.
+    pub profile: Option<Profile>,
+    /// Set this to record every instruction executed. See `Trace`.
+    pub trace: Option<Trace>,
+    /// Set this to be able to step backwards. See `History`.
+    pub history: Option<History>,
+    /// What to do when `Add` or `Mul` overflows.
+    pub overflow: Overflow,
+    /// The highest address the program may use. Anything above it is an
+    /// `AddressOutOfRange` error.
+    pub max_address: Option<usize>,
+    /// Set this to limit how long `execute()` can run for. See `Budget`.
+    pub budget: Option<Budget>,
+    /// How many outputs the program has written, so undoing an `Out` can
+    /// tell whether its output is still in the queue.
+    outputs_written: usize,
+    /// Set when we stop at a breakpoint, so the next `execute()` doesn't stop
+    /// at the same one again before executing anything.
+    resuming_from_breakpoint: bool,
+    /// Set when an `Out` triggers a watchpoint, so the `Output` isn't lost.
+    pending_output: bool,
+    /// Pre-decoded instructions for the fast path, by address.
+    compiled: Vec<Option<threaded::Op<D>>>,
+}
+
+impl<D: IoDevice> VM<D> {
+    pub fn with_io(program: &Program, io: D) -> Self {
+        VM {
+            memory: Memory::new(program.code.clone()),
+            ip: 0,
+            bp: 0,
+            io,
+            cycles: 0,
+            debug: false,
+            threaded: true,
+            breakpoints: HashSet::new(),
+            read_watchpoints: HashSet::new(),
+            write_watchpoints: HashSet::new(),
+            profile: None,
+            trace: None,
+            history: None,
+            overflow: Overflow::default(),
+            max_address: None,
+            budget: None,
+            outputs_written: 0,
+            resuming_from_breakpoint: false,
+            pending_output: false,
+            compiled: Vec::new(),
+        }
+    }
+
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    pub fn bp(&self) -> i64 {
+        self.bp
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn io(&self) -> &D {
+        &self.io
+    }
+
+    pub fn io_mut(&mut self) -> &mut D {
+        &mut self.io
+    }
+
+    pub fn into_io(self) -> D {
+        self.io
+    }
+
+    /// Sets a memory cell, growing memory if needed, just like a write from
+    /// the program itself would.
+    pub fn set_memory(&mut self, address: usize, value: i64) {
+        *self.memory.get_mut(address) = value;
+        self.invalidate(address);
+    }
+
+    /// Runs until the program needs input that isn't there yet, produces an
+    /// output, or halts, or until it hits a breakpoint or watchpoint.
+    pub fn execute(&mut self) -> Result<ExecuteStatus, VmError> {
+        if self.pending_output {
+            self.pending_output = false;
+            return Ok(ExecuteStatus::Output);
+        }
+
+        if self.can_run_threaded() {
+            return self.run_threaded();
+        }
+
+        loop {
+            if self.breakpoints.contains(&self.ip) && !self.resuming_from_breakpoint {
+                self.resuming_from_breakpoint = true;
+                return Ok(ExecuteStatus::Breakpoint);
+            }
+            if self.out_of_budget() {
+                return Ok(ExecuteStatus::BudgetExhausted);
+            }
+
+            let event = match self.step()? {
+                Some(event) => event,
+                None => return Ok(ExecuteStatus::NeedInput),
+            };
+
+            if let Some((address, access)) = self.triggered_watchpoint(&event) {
+                self.pending_output = event.opcode == Opcode::Out;
+                return Ok(ExecuteStatus::Watchpoint { address, access });
+            }
+
+            match event.opcode {
+                Opcode::Out => return Ok(ExecuteStatus::Output),
+                Opcode::Halt => return Ok(ExecuteStatus::Halted),
+                _ => {}
+            }
+        }
+    }
+
+    /// Keeps calling `execute()` until it stops for anything but output, so
+    /// every output goes to the device: when the program halts, needs input
+    /// the device doesn't have, or hits a breakpoint, watchpoint or budget.
+    pub fn run(&mut self) -> Result<ExecuteStatus, VmError> {
+        loop {
+            match self.execute()? {
+                ExecuteStatus::Output => {}
+                status => return Ok(status),
+            }
+        }
+    }
+
+    /// Whether `budget` has run out before the next instruction.
+    fn out_of_budget(&self) -> bool {
+        self.budget.as_ref()
+            .is_some_and(|budget| budget.exhausted(self.cycles, self.memory.allocated()))
+    }
+
+    /// The first watched address that `event` read from or wrote to. Only
+    /// position and relative mode parameters count as reads.
+    fn triggered_watchpoint(&self, event: &StepEvent) -> Option<(usize, Access)> {
+        let num_reads = if event.write.is_some() {
+            event.params.len() - 1
+        } else {
+            event.params.len()
+        };
+
+        let read = event.params[..num_reads].iter()
+            .filter(|param| param.mode != ParameterMode::Immediate)
+            .find(|param| self.read_watchpoints.contains(&param.address))
+            .map(|param| (param.address, Access::Read));
+
+        let write = event.write
+            .filter(|write| self.write_watchpoints.contains(&write.address))
+            .map(|write| (write.address, Access::Write));
+
+        read.or(write)
+    }
+
+    /// Executes exactly one instruction and describes what it did. Returns
+    /// `None`, without doing anything, if the instruction is an `In` and
+    /// the device has no input for it. Halting doesn't move the `ip`, so stepping a
+    /// halted VM just executes the `Halt` again.
+    pub fn step(&mut self) -> Result<Option<StepEvent>, VmError> {
+        let instruction = self.read(self.ip);
+        let inst = self.decode()?;
+
+        let input = if inst.opcode() == Opcode::In {
+            // Work out where it's going first, so the input isn't lost if
+            // that fails.
+            self.write_address(&inst, 0)?;
+            match self.io.read() {
+                Some(value) => Some(value),
+                None => return Ok(None),
+            }
+        } else {
+            None
+        };
+
+        if self.debug {
+            let code: Vec<i64> = (self.ip .. self.ip + inst.length())
+                .map(|address| self.read(address))
+                .collect();
+            log(&format!("{:<4} | {}", self.ip, inst.disassemble(&code)));
+        }
+
+        let mut event = StepEvent {
+            ip: self.ip,
+            instruction,
+            opcode: inst.opcode(),
+            params: SmallVec::new(),
+            write: None,
+            jump: None,
+            bp: None,
+            input: None,
+            output: None,
+        };
+
+        let mut next_ip = self.ip + inst.length();
+
+        match inst.opcode() {
+            Opcode::Add => {
+                let a = self.param(&inst, 0, &mut event)?;
+                let b = self.param(&inst, 1, &mut event)?;
+                let sum = self.overflow.add(a, b)
+                    .ok_or_else(|| VmError::Overflow(self.fault()))?;
+                self.write_param(&inst, 2, sum, &mut event)?;
+            }
+            Opcode::Mul => {
+                let a = self.param(&inst, 0, &mut event)?;
+                let b = self.param(&inst, 1, &mut event)?;
+                let product = self.overflow.mul(a, b)
+                    .ok_or_else(|| VmError::Overflow(self.fault()))?;
+                self.write_param(&inst, 2, product, &mut event)?;
+            }
+            Opcode::In => {
+                let value = input.expect("read before executing");
+                event.input = Some(value);
+                self.write_param(&inst, 0, value, &mut event)?;
+            }
+            Opcode::Out => {
+                let value = self.param(&inst, 0, &mut event)?;
+                self.io.write(value);
+                self.outputs_written += 1;
+                event.output = Some(value);
+            }
+            Opcode::JmpT | Opcode::JmpF => {
+                let value = self.param(&inst, 0, &mut event)?;
+                let taken = (value != 0) == (inst.opcode() == Opcode::JmpT);
+                event.jump = if taken {
+                    let target = self.param(&inst, 1, &mut event)?;
+                    next_ip = self.address(&target)?;
+                    Some(Jump::Taken { target: next_ip })
+                } else {
+                    Some(Jump::NotTaken)
+                };
+            }
+            Opcode::Lt => {
+                let a = self.param(&inst, 0, &mut event)?;
+                let b = self.param(&inst, 1, &mut event)?;
+                self.write_param(&inst, 2, if a < b { 1 } else { 0 }, &mut event)?;
+            }
+            Opcode::Eql => {
+                let a = self.param(&inst, 0, &mut event)?;
+                let b = self.param(&inst, 1, &mut event)?;
+                self.write_param(&inst, 2, if a == b { 1 } else { 0 }, &mut event)?;
+            }
+            Opcode::Base => {
+                let old = self.bp;
+                self.bp = self.bp.wrapping_add(self.param(&inst, 0, &mut event)?);
+                event.bp = Some(BaseChange { old, new: self.bp });
+            }
+            Opcode::Halt => {
+                next_ip = self.ip;
+            }
+        };
+
+        self.ip = next_ip;
+        self.cycles += 1;
+        self.resuming_from_breakpoint = false;
+        if let Some(budget) = &mut self.budget {
+            budget.spend();
+        }
+
+        if let Some(profile) = &mut self.profile {
+            profile.record(&event);
+        }
+        if let Some(trace) = &mut self.trace {
+            trace.record(&event);
+        }
+        let output = event.output.map(|_| self.outputs_written - 1);
+        if let Some(history) = &mut self.history {
+            history.push(Undo::new(&event, output));
+        }
+
+        Ok(Some(event))
+    }
+
+    /// Reads a parameter, recording it in the event.
+    fn param(&mut self, inst: &Instruction, param: usize, event: &mut StepEvent) -> Result<i64, VmError> {
+        let address = self.param_address(inst, param)?;
+        let value = self.read(address);
+        event.params.push(Param {
+            mode: inst.param_mode(param),
+            raw: self.read(self.ip + param + 1),
+            address,
+            value,
+        });
+        Ok(value)
+    }
+
+    /// Writes to the address a parameter refers to, recording both the
+    /// parameter and the write in the event.
+    fn write_param(&mut self, inst: &Instruction, param: usize, value: i64, event: &mut StepEvent) -> Result<(), VmError> {
+        let address = self.write_address(inst, param)?;
+        let old = self.param(inst, param, event)?;
+        *self.memory.get_mut(address) = value;
+        self.invalidate(address);
+        event.write = Some(MemoryWrite { address, old, new: value });
+        Ok(())
+    }
+}
+
+impl<D: IoDevice> Machine<i64> for VM<D> {
+    fn ip(&self) -> usize {
+        self.ip
+    }
+
+    fn bp(&self) -> &i64 {
+        &self.bp
+    }
+
+    fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    fn max_address(&self) -> Option<usize> {
+        self.max_address
+    }
+}
+
+/// The parts that need to get at the queues.
+impl VM {
+    pub fn new(program: &Program) -> Self {
+        VM::with_io(program, Queue::default())
+    }
+
+    /// Input that's been sent but not read by the program yet.
+    pub fn queued_input(&self) -> &VecDeque<i64> {
+        &self.io.input
+    }
+
+    /// Output the program has produced that hasn't been received yet.
+    pub fn queued_output(&self) -> &VecDeque<i64> {
+        &self.io.output
+    }
+
+    /// Captures the VM's complete state (everything except `debug` and the
+    /// breakpoints and watchpoints), so it can be saved and resumed later.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            memory: self.memory.dense().to_vec(),
+            pages: self.memory.pages().into_iter()
+                .map(|(start, cells)| (start, cells.to_vec()))
+                .collect(),
+            ip: self.ip,
+            bp: self.bp,
+            input: self.io.input.iter().copied().collect(),
+            output: self.io.output.iter().copied().collect(),
+            cycles: self.cycles,
+        }
+    }
+
+    /// Puts the VM back into the state captured by `snapshot`. Fails, without
+    /// changing anything, if it has pages past `max_address`.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), SnapshotError> {
+        if let Some(max) = self.max_address {
+            if let Some(&(start, _)) = snapshot.pages.iter().find(|&&(start, _)| start > max) {
+                return Err(SnapshotError::AddressOutOfRange(start));
+            }
+        }
+
+        self.memory = Memory::from_parts(snapshot.memory.clone(), snapshot.pages.clone());
+        self.ip = snapshot.ip;
+        self.bp = snapshot.bp;
+        self.io.input = snapshot.input.iter().copied().collect();
+        self.io.output = snapshot.output.iter().copied().collect();
+        self.outputs_written = self.io.output.len();
+        self.cycles = snapshot.cycles;
+        self.resuming_from_breakpoint = false;
+        self.pending_output = false;
+        self.compiled.clear();
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+        Ok(())
+    }
+
+    pub fn send_input(&mut self, value: i64) {
+        self.io.input.push_back(value);
+    }
+
+    pub fn recv_output(&mut self) -> Result<i64, VmError> {
+        self.io.output.pop_front()
+            .ok_or_else(|| VmError::OutputUnderflow(self.fault()))
+    }
+
+    /// Undoes the last instruction executed, if there's any history left.
+    /// Returns false if there isn't (or `history` isn't turned on).
+    pub fn step_back(&mut self) -> bool {
+        self.undo().is_some()
+    }
+
+    /// Steps back until the instruction at `ip` is the next one to execute
+    /// (always stepping back at least once). Returns false, without moving,
+    /// if that instruction isn't in the history.
+    pub fn run_back_to(&mut self, ip: usize) -> bool {
+        let found = self.history.as_ref()
+            .is_some_and(|history| history.executed(ip));
+        if !found {
+            return false;
+        }
+
+        while self.step_back() {
+            if self.ip == ip {
+                break;
+            }
+        }
+        true
+    }
+
+    /// Steps back to just before the most recent write to `address`, so the
+    /// next instruction is the one that wrote it. Returns false, without
+    /// moving, if there's no such write in the history.
+    pub fn run_back_to_write(&mut self, address: usize) -> bool {
+        let found = self.history.as_ref()
+            .and_then(|history| history.last_write(address))
+            .is_some();
+        if !found {
+            return false;
+        }
+
+        while let Some(undo) = self.undo() {
+            if matches!(undo.write, Some((written, _)) if written == address) {
+                break;
+            }
+        }
+        true
+    }
+
+    fn undo(&mut self) -> Option<Undo> {
+        let undo = self.history.as_mut().and_then(History::pop)?;
+
+        if let Some((address, old)) = undo.write {
+            *self.memory.get_mut(address) = old;
+            self.invalidate(address);
+        }
+        if let Some(bp) = undo.bp {
+            self.bp = bp;
+        }
+        if let Some(value) = undo.input {
+            self.io.input.push_front(value);
+        }
+        if let Some(index) = undo.output {
+            let received = self.outputs_written.saturating_sub(self.io.output.len());
+            if index >= received {
+                self.io.output.truncate(index - received);
+                self.outputs_written = index;
+            }
+        }
+
+        self.ip = undo.ip;
+        self.cycles -= 1;
+        self.resuming_from_breakpoint = false;
+        self.pending_output = false;
+        Some(undo)
+    }
+}
+
+/// Prints a line of debug output. In the browser there's no stdout, so it goes
+/// to the JS console instead.
+#[cfg(not(target_arch = "wasm32"))]
+fn log(line: &str) {
+    println!("{}", line);
+}
+
+#[cfg(target_arch = "wasm32")]
+fn log(line: &str) {
+    web_sys::console::log_1(&line.into());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Fault;
+
+    fn test_program_memory(code: &[i64], expected: &[i64]) {
+        let mut vm = VM::new(&Program::new(code.to_vec()));
+        assert_eq!(vm.execute(), Ok(ExecuteStatus::Halted));
+        assert_eq!(vm.memory.to_vec(), expected);
+    }
+
+    fn test_program(code: &[i64], input: &[i64], output: &[i64]) {
+        let mut vm = VM::new(&Program::new(code.to_vec()));
+        for &value in input {
+            vm.send_input(value);
+        }
+        let mut actual_output = Vec::new();
+        loop {
+            match vm.execute().unwrap() {
+                ExecuteStatus::Output => actual_output.push(vm.recv_output().unwrap()),
+                ExecuteStatus::NeedInput => panic!("Not enough input"),
+                ExecuteStatus::Halted => break,
+                _ => unreachable!(),
+            }
+        }
+        assert_eq!(actual_output, output);
+    }
+
+    #[test]
+    fn pauses_execution_to_wait_for_input() {
+        let add = Program::new(vec![
+            3,100,
+            3,101,
+            1,100,101,102,
+            4,102,
+            99,
+        ]);
+
+        let mut vm = VM::new(&add);
+
+        assert_eq!(vm.execute(), Ok(ExecuteStatus::NeedInput));
+        assert_eq!(vm.ip, 0);
+        vm.send_input(5);
+
+        assert_eq!(vm.execute(), Ok(ExecuteStatus::NeedInput));
+        assert_eq!(vm.ip, 2);
+        vm.send_input(8);
+
+        assert_eq!(vm.execute(), Ok(ExecuteStatus::Output));
+        assert_eq!(vm.recv_output(), Ok(13));
+        assert_eq!(vm.ip, 10);
+
+        assert_eq!(vm.execute(), Ok(ExecuteStatus::Halted));
+        assert_eq!(vm.ip, 10);
+    }
+
+    fn test_program_error(code: &[i64], expected: VmError) {
+        let mut vm = VM::new(&Program::new(code.to_vec()));
+        assert_eq!(vm.execute(), Err(expected));
+    }
+
+    #[test]
+    fn overflow_policies() {
+        // i64::MAX + 1 and i64::MAX * 2, each stored at 13 and output.
+        let code = vec![
+            1101,9223372036854775807,1,13, 4,13,
+            1102,9223372036854775807,2,13, 4,13,
+            99,
+            0,
+        ];
+        let run = |overflow, stepped: bool| {
+            let mut vm = VM::new(&Program::new(code.clone()));
+            vm.overflow = overflow;
+            let mut output = Vec::new();
+            if stepped {
+                while let Some(event) = vm.step()? {
+                    output.extend(event.output);
+                    if event.opcode == Opcode::Halt {
+                        break;
+                    }
+                }
+            } else {
+                while vm.execute()? == ExecuteStatus::Output {
+                    output.push(vm.recv_output()?);
+                }
+            }
+            Ok(output)
+        };
+
+        for &stepped in &[false, true] {
+            assert_eq!(run(Overflow::Wrap, stepped), Ok(vec![i64::MIN, -2]));
+            assert_eq!(run(Overflow::Saturate, stepped), Ok(vec![i64::MAX, i64::MAX]));
+            assert_eq!(
+                run(Overflow::Trap, stepped),
+                Err(VmError::Overflow(Fault { ip: 0, instruction: 1101, cycles: 0 })),
+            );
+        }
+    }
+
+    #[test]
+    fn far_addresses_are_sparse_and_can_be_limited() {
+        // Write the input to 10^12, then output it back.
+        let code = vec![3,1000000000000, 4,1000000000000, 99];
+        let mut vm = VM::new(&Program::new(code.clone()));
+        vm.send_input(42);
+        assert_eq!(vm.execute(), Ok(ExecuteStatus::Output));
+        assert_eq!(vm.recv_output(), Ok(42));
+        assert_eq!(vm.memory().pages().len(), 1);
+
+        let mut restored = VM::new(&Program::new(vec![]));
+        restored.restore(&vm.snapshot().to_string().parse().unwrap()).unwrap();
+        assert_eq!(restored.memory(), vm.memory());
+
+        let mut limited = VM::new(&Program::new(vec![]));
+        limited.max_address = Some(1 << 20);
+        assert!(matches!(
+            limited.restore(&vm.snapshot()),
+            Err(SnapshotError::AddressOutOfRange(1_000_000_000_000))
+        ));
+
+        for &threaded in &[true, false] {
+            let mut vm = VM::new(&Program::new(code.clone()));
+            vm.max_address = Some(1 << 20);
+            vm.threaded = threaded;
+            vm.send_input(42);
+            assert_eq!(
+                vm.execute(),
+                Err(VmError::AddressOutOfRange(Fault { ip: 0, instruction: 3, cycles: 0 }, 1000000000000)),
+            );
+        }
+    }
+
+    #[test]
+    fn stops_when_the_budget_runs_out() {
+        use std::time::Instant;
+
+        // Counts up at address 20 forever, outputting every number.
+        let counter = Program::new(vec![1001,20,1,20, 4,20, 1105,1,0]);
+        for &threaded in &[true, false] {
+            let mut vm = VM::new(&counter);
+            vm.threaded = threaded;
+            vm.budget = Some(Budget { cycles: Some(7), ..Budget::default() });
+            let mut output = Vec::new();
+            loop {
+                match vm.execute() {
+                    Ok(ExecuteStatus::Output) => output.push(vm.recv_output().unwrap()),
+                    Ok(ExecuteStatus::BudgetExhausted) => break,
+                    status => panic!("Unexpected {:?}", status),
+                }
+            }
+            assert_eq!((output.as_slice(), vm.cycles), (&[1, 2][..], 7));
+
+            // Topping it up carries on from the same place.
+            vm.budget.as_mut().unwrap().cycles = Some(2);
+            assert_eq!(vm.execute(), Ok(ExecuteStatus::Output));
+            assert_eq!(vm.recv_output(), Ok(3));
+            assert_eq!(vm.execute(), Ok(ExecuteStatus::BudgetExhausted));
+            assert_eq!(vm.cycles, 9);
+        }
+
+        let mut vm = VM::new(&Program::new(vec![1101,1,1,1000000, 99]));
+        vm.budget = Some(Budget { memory: Some(100), ..Budget::default() });
+        assert_eq!(vm.execute(), Ok(ExecuteStatus::BudgetExhausted));
+        assert_eq!(vm.cycles, 1);
+        vm.budget = None;
+        assert_eq!(vm.execute(), Ok(ExecuteStatus::Halted));
+
+        let mut vm = VM::new(&counter);
+        vm.budget = Some(Budget { deadline: Some(Instant::now()), ..Budget::default() });
+        assert_eq!(vm.execute(), Ok(ExecuteStatus::BudgetExhausted));
+    }
+
+    #[test]
+    fn reports_errors_instead_of_panicking() {
+        test_program_error(
+            &[1101,1,1,5, 42,0],
+            VmError::InvalidOpcode(Fault { ip: 4, instruction: 42, cycles: 1 }),
+        );
+
+        test_program_error(
+            &[1101,1,1,5, 301,0,0,0],
+            VmError::InvalidParameterMode(Fault { ip: 4, instruction: 301, cycles: 1 }),
+        );
+
+        test_program_error(
+            &[1,-1,0,0, 99],
+            VmError::NegativeAddress(Fault { ip: 0, instruction: 1, cycles: 0 }, -1),
+        );
+
+        test_program_error(
+            &[109,-5, 2201,0,0,0, 99],
+            VmError::NegativeAddress(Fault { ip: 2, instruction: 2201, cycles: 1 }, -5),
+        );
+
+        test_program_error(
+            &[1105,1,-3, 99],
+            VmError::NegativeAddress(Fault { ip: 0, instruction: 1105, cycles: 0 }, -3),
+        );
+
+        test_program_error(
+            &[11101,1,1,0, 99],
+            VmError::ImmediateWrite(Fault { ip: 0, instruction: 11101, cycles: 0 }),
+        );
+
+        let mut vm = VM::new(&Program::new(vec![104,7, 99]));
+        assert_eq!(vm.execute(), Ok(ExecuteStatus::Output));
+        assert_eq!(vm.recv_output(), Ok(7));
+        assert_eq!(
+            vm.recv_output(),
+            Err(VmError::OutputUnderflow(Fault { ip: 2, instruction: 99, cycles: 1 })),
+        );
+    }
+
+    #[test]
+    fn step_describes_each_instruction() {
+        let program = Program::new(vec![
+            109,20,
+            21101,3,4,2,
+            1005,21,100,
+            3,22,
+            4,22,
+            99,
+        ]);
+        let mut vm = VM::new(&program);
+
+        let event = vm.step().unwrap().unwrap();
+        assert_eq!(event.opcode, Opcode::Base);
+        assert_eq!(event.bp, Some(BaseChange { old: 0, new: 20 }));
+        assert_eq!(vm.ip, 2);
+
+        // bp[2] is mem[22], which is past the end of the program.
+        let event = vm.step().unwrap().unwrap();
+        assert_eq!(event.ip, 2);
+        assert_eq!(event.instruction, 21101);
+        assert_eq!(
+            event.params.as_slice(),
+            &[
+                Param { mode: ParameterMode::Immediate, raw: 3, address: 3, value: 3 },
+                Param { mode: ParameterMode::Immediate, raw: 4, address: 4, value: 4 },
+                Param { mode: ParameterMode::Relative, raw: 2, address: 22, value: 0 },
+            ],
+        );
+        assert_eq!(event.write, Some(MemoryWrite { address: 22, old: 0, new: 7 }));
+
+        // mem[21] is 0, so the jump isn't taken and its target isn't resolved.
+        let event = vm.step().unwrap().unwrap();
+        assert_eq!(event.jump, Some(Jump::NotTaken));
+        assert_eq!(event.params.len(), 1);
+        assert_eq!(vm.ip, 9);
+
+        assert_eq!(vm.step(), Ok(None));
+        assert_eq!(vm.ip, 9);
+        vm.send_input(42);
+
+        let event = vm.step().unwrap().unwrap();
+        assert_eq!(event.input, Some(42));
+        assert_eq!(event.write, Some(MemoryWrite { address: 22, old: 7, new: 42 }));
+
+        let event = vm.step().unwrap().unwrap();
+        assert_eq!(event.output, Some(42));
+        assert_eq!(vm.recv_output(), Ok(42));
+
+        let event = vm.step().unwrap().unwrap();
+        assert_eq!(event.opcode, Opcode::Halt);
+        assert_eq!(vm.ip, 13);
+        assert_eq!(vm.cycles, 6);
+    }
+
+    #[test]
+    fn resumes_from_a_snapshot() {
+        let add = Program::new(vec![
+            3,100,
+            3,101,
+            1,100,101,102,
+            4,102,
+            99,
+        ]);
+
+        let mut vm = VM::new(&add);
+        vm.send_input(5);
+        assert_eq!(vm.execute(), Ok(ExecuteStatus::NeedInput));
+
+        let snapshot = vm.snapshot();
+        let snapshot = snapshot.to_string().parse::<Snapshot>().unwrap();
+
+        let mut resumed = VM::new(&Program::new(vec![99]));
+        resumed.restore(&snapshot).unwrap();
+        assert_eq!(resumed.ip, 2);
+        assert_eq!(resumed.cycles, 1);
+
+        resumed.send_input(8);
+        assert_eq!(resumed.execute(), Ok(ExecuteStatus::Output));
+        assert_eq!(resumed.recv_output(), Ok(13));
+        assert_eq!(resumed.execute(), Ok(ExecuteStatus::Halted));
+    }
+
+    #[test]
+    fn steps_backwards() {
+        // Reads x into mem[20], then outputs x+1, x+2, ... up to 3.
+        let program = Program::new(vec![
+            109,5,
+            3,20,
+            1001,20,1,20,
+            4,20,
+            1007,20,3,21,
+            1005,21,4,
+            99,
+        ]);
+
+        let mut vm = VM::new(&program);
+        vm.history = Some(History::new());
+        vm.send_input(0);
+        assert_eq!(vm.execute(), Ok(ExecuteStatus::Output));
+        assert_eq!(vm.recv_output(), Ok(1));
+        while vm.execute() != Ok(ExecuteStatus::Halted) {}
+        assert_eq!(vm.queued_output(), &[2, 3]);
+
+        // Who wrote the 3? The `add` at 4.
+        assert!(vm.run_back_to_write(20));
+        assert_eq!(vm.ip(), 4);
+        assert_eq!(*vm.memory().get(20), 2);
+        assert_eq!(vm.queued_output(), &[2]);
+
+        // Nothing at 3 was ever executed, so that doesn't go anywhere.
+        assert!(!vm.run_back_to(3));
+        assert_eq!(vm.ip(), 4);
+
+        // Back to before the input was read. The 1 was already received, so
+        // it stays gone.
+        assert!(vm.run_back_to(2));
+        assert_eq!(vm.cycles, 1);
+        assert_eq!(vm.bp(), 5);
+        assert_eq!(*vm.memory().get(20), 0);
+        assert_eq!(vm.queued_input(), &[0]);
+        assert!(vm.queued_output().is_empty());
+
+        assert!(vm.step_back());
+        assert_eq!((vm.ip(), vm.bp(), vm.cycles), (0, 0, 0));
+        assert!(!vm.step_back());
+        assert!(!vm.run_back_to_write(20));
+
+        // And forwards again.
+        assert_eq!(vm.execute(), Ok(ExecuteStatus::Output));
+        assert_eq!(vm.recv_output(), Ok(1));
+    }
+
+    #[test]
+    fn stops_at_breakpoints() {
+        let program = Program::new(vec![
+            1101,1,1,20,
+            3,21,
+            4,21,
+            99,
+        ]);
+
+        let mut vm = VM::new(&program);
+        vm.breakpoints.insert(0);
+        vm.breakpoints.insert(4);
+
+        assert_eq!(vm.execute(), Ok(ExecuteStatus::Breakpoint));
+        assert_eq!(vm.ip, 0);
+        assert_eq!(vm.execute(), Ok(ExecuteStatus::Breakpoint));
+        assert_eq!(vm.ip, 4);
+
+        // Waiting for input doesn't count as leaving the breakpoint.
+        assert_eq!(vm.execute(), Ok(ExecuteStatus::NeedInput));
+        vm.send_input(7);
+        assert_eq!(vm.execute(), Ok(ExecuteStatus::Output));
+        assert_eq!(vm.recv_output(), Ok(7));
+        assert_eq!(vm.execute(), Ok(ExecuteStatus::Halted));
+    }
+
+    #[test]
+    fn stops_at_watchpoints() {
+        let program = Program::new(vec![
+            1101,1,1,20,
+            1001,20,5,21,
+            4,21,
+            99,
+        ]);
+
+        let mut vm = VM::new(&program);
+        vm.write_watchpoints.insert(20);
+        vm.read_watchpoints.insert(21);
+
+        assert_eq!(
+            vm.execute(),
+            Ok(ExecuteStatus::Watchpoint { address: 20, access: Access::Write }),
+        );
+        assert_eq!(vm.ip, 4);
+
+        // Writing to 21 isn't a read.
+        assert_eq!(vm.execute(), Ok(ExecuteStatus::Watchpoint { address: 21, access: Access::Read }));
+        assert_eq!(vm.ip, 10);
+        assert_eq!(vm.execute(), Ok(ExecuteStatus::Output));
+        assert_eq!(vm.recv_output(), Ok(7));
+        assert_eq!(vm.execute(), Ok(ExecuteStatus::Halted));
+    }
+
+    #[test]
+    fn input_isnt_lost_when_it_cant_be_stored() {
+        let mut vm = VM::new(&Program::new(vec![103,0, 99]));
+        vm.send_input(5);
+        assert_eq!(
+            vm.execute(),
+            Err(VmError::ImmediateWrite(Fault { ip: 0, instruction: 103, cycles: 0 })),
+        );
+        assert_eq!(vm.io.input, vec![5]);
+
+        let mut vm = VM::new(&Program::new(vec![3,-1, 99]));
+        vm.send_input(5);
+        assert_eq!(
+            vm.execute(),
+            Err(VmError::NegativeAddress(Fault { ip: 0, instruction: 3, cycles: 0 }, -1)),
+        );
+        assert_eq!(vm.io.input, vec![5]);
+    }
+
+    #[test]
+    fn day2_test_cases() {
+        test_program_memory(
+            &[1,0,0,0,99],
+            &[2,0,0,0,99],
+        );
+
+        test_program_memory(
+            &[2,3,0,3,99],
+            &[2,3,0,6,99],
+        );
+
+        test_program_memory(
+            &[2,4,4,5,99,0],
+            &[2,4,4,5,99,9801],
+        );
+
+        test_program_memory(
+            &[1,1,1,4,99,5,6,0,99],
+            &[30,1,1,4,2,5,6,0,99],
+        );
+    }
+
+    #[test]
+    fn day5_test_cases() {
+        let echo = &[
+            3,0,
+            4,0,
+            99,
+        ];
+        test_program(echo, &[42], &[42]);
+        test_program(echo, &[123], &[123]);
+
+        let equal_to_8 = &[
+            3,9,
+            8,9,10,9,
+            4,9,
+            99,
+            -1,8,
+        ];
+        test_program(equal_to_8, &[8], &[1]);
+        test_program(equal_to_8, &[7], &[0]);
+        test_program(equal_to_8, &[9], &[0]);
+
+        let less_than_8 = &[
+            3,9,
+            7,9,10,9,
+            4,9,
+            99,
+            -1,8,
+        ];
+        test_program(less_than_8, &[7], &[1]);
+        test_program(less_than_8, &[8], &[0]);
+        test_program(less_than_8, &[9], &[0]);
+
+        let equal_to_8_imm = &[
+            3,3,
+            1108,-1,8,3,
+            4,3,
+            99,
+        ];
+        test_program(equal_to_8_imm, &[8], &[1]);
+        test_program(equal_to_8_imm, &[7], &[0]);
+        test_program(equal_to_8_imm, &[9], &[0]);
+
+        let less_than_8_imm = &[
+            3,3,
+            1107,-1,8,3,
+            4,3,
+            99,
+        ];
+        test_program(less_than_8_imm, &[7], &[1]);
+        test_program(less_than_8_imm, &[8], &[0]);
+        test_program(less_than_8_imm, &[9], &[0]);
+
+        let is_nonzero = &[
+            3,12,
+            6,12,15,
+            1,13,14,13,
+            4,13,
+            99,
+            -1,0,1,9,
+        ];
+        test_program(is_nonzero, &[0], &[0]);
+        test_program(is_nonzero, &[8], &[1]);
+
+        let is_nonzero_imm = &[
+            3,3,
+            1105,-1,9,
+            1101,0,0,12,
+            4,12,
+            99,
+            1,
+        ];
+        test_program(is_nonzero_imm, &[0], &[0]);
+        test_program(is_nonzero_imm, &[8], &[1]);
+
+        let spaceship_8 = &[
+            3,21,
+            1008,21,8,20,
+            1005,20,22,
+            107,8,21,20,
+            1006,20,31,
+            1106,0,36,
+            98,0,0,
+            1002,21,125,20,
+            4,20,
+            1105,1,46,
+            104,999,
+            1105,1,46,
+            1101,1000,1,20,
+            4,20,
+            1105,1,46,
+            98,
+            99,
+        ];
+        test_program(spaceship_8, &[10], &[1001]);
+        test_program(spaceship_8, &[9], &[1001]);
+        test_program(spaceship_8, &[8], &[1000]);
+        test_program(spaceship_8, &[7], &[999]);
+        test_program(spaceship_8, &[6], &[999]);
+    }
+
+    #[test]
+    fn day9_test_cases() {
+        let large_number = &[
+            104,1125899906842624,
+            99,
+        ];
+        test_program(large_number, &[], &[1125899906842624]);
+
+        let large_number = &[
+            1102,34915192,34915192,7,
+            4,7,
+            99,
+            0,
+        ];
+        test_program(large_number, &[], &[1219070632396864]);
+
+        let quine = &[
+            109,1,
+            204,-1,
+            1001,100,1,100,
+            1008,100,16,101,
+            1006,101,0,
+            99,
+        ];
+        test_program(quine, &[], quine);
+    }
+}
@@ -0,0 +1,346 @@
+//! The fast path for `VM::execute()`. Instead of decoding every instruction
+//! every time it runs, each one is decoded once into an `Op`: a handler for
+//! its opcode, plus its parameters with their modes already worked out. Ops
+//! are cached by address, and a write to any address an op was decoded from
+//! throws that op away, so self-modifying code still works.
+//!
+//! None of the debugging features (`debug`, breakpoints, watchpoints,
+//! profiling, tracing, history) work at this level, so `execute()` only
+//! takes this path when they're all off, and `vm.threaded` is set.
+
+use std::convert::TryFrom;
+
+use super::{ExecuteStatus, VM};
+use crate::device::IoDevice;
+use crate::error::VmError;
+use crate::instruction::{Instruction, Opcode, ParameterMode};
+use crate::machine::Machine;
+use crate::memory::Memory;
+
+/// A parameter with its mode resolved.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(super) enum Operand {
+    Position(i64),
+    Immediate(i64),
+    Relative(i64),
+}
+
+/// What the run loop should do after an op.
+enum Flow {
+    Continue,
+    NeedInput,
+    Output,
+    Halted,
+}
+
+type Handler<D> = fn(&mut VM<D>, &Op<D>) -> Result<Flow, VmError>;
+
+pub(super) struct Op<D> {
+    handler: Handler<D>,
+    length: usize,
+    args: [Operand; 3],
+}
+
+// Derived, these would only work for devices that are `Copy` themselves.
+impl<D> Clone for Op<D> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<D> Copy for Op<D> {}
+
+impl<D> std::fmt::Debug for Op<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Op")
+            .field("length", &self.length)
+            .field("args", &self.args)
+            .finish()
+    }
+}
+
+/// Decodes the instruction at `ip`. Returns `None` for anything the
+/// interpreter should handle instead: invalid instructions, writes to
+/// immediate parameters, and anything outside the dense part of memory. That
+/// way errors come out exactly the same.
+fn compile<D: IoDevice>(memory: &Memory, ip: usize) -> Option<Op<D>> {
+    let dense = memory.dense();
+    let inst = Instruction::try_from(*dense.get(ip)?).ok()?;
+    let length = inst.length();
+    if ip + length > dense.len() {
+        return None;
+    }
+    if let Some(param) = inst.opcode().write_param() {
+        if inst.param_mode(param) == ParameterMode::Immediate {
+            return None;
+        }
+    }
+
+    let mut args = [Operand::Immediate(0); 3];
+    for (param, arg) in args.iter_mut().enumerate().take(length - 1) {
+        let raw = dense[ip + param + 1];
+        *arg = match inst.param_mode(param) {
+            ParameterMode::Position => Operand::Position(raw),
+            ParameterMode::Immediate => Operand::Immediate(raw),
+            ParameterMode::Relative => Operand::Relative(raw),
+        };
+    }
+
+    let handler: Handler<D> = match inst.opcode() {
+        Opcode::Add => add,
+        Opcode::Mul => mul,
+        Opcode::In => input,
+        Opcode::Out => output,
+        Opcode::JmpT => jump_if_true,
+        Opcode::JmpF => jump_if_false,
+        Opcode::Lt => less_than,
+        Opcode::Eql => equals,
+        Opcode::Base => adjust_base,
+        Opcode::Halt => halt,
+    };
+
+    Some(Op { handler, length, args })
+}
+
+impl<D: IoDevice> VM<D> {
+    /// Whether `execute()` can take the fast path.
+    pub(super) fn can_run_threaded(&self) -> bool {
+        self.threaded
+            && !self.debug
+            && self.breakpoints.is_empty()
+            && self.read_watchpoints.is_empty()
+            && self.write_watchpoints.is_empty()
+            && self.profile.is_none()
+            && self.trace.is_none()
+            && self.history.is_none()
+    }
+
+    /// `execute()`, minus all the debugging features.
+    pub(super) fn run_threaded(&mut self) -> Result<ExecuteStatus, VmError> {
+        self.resuming_from_breakpoint = false;
+
+        loop {
+            if self.out_of_budget() {
+                return Ok(ExecuteStatus::BudgetExhausted);
+            }
+
+            let op = match self.compiled.get(self.ip).copied().flatten() {
+                Some(op) => op,
+                None => match compile(&self.memory, self.ip) {
+                    Some(op) => {
+                        if self.compiled.len() < self.memory.dense_len() {
+                            self.compiled.resize(self.memory.dense_len(), None);
+                        }
+                        self.compiled[self.ip] = Some(op);
+                        op
+                    }
+                    None => {
+                        let event = match self.step()? {
+                            Some(event) => event,
+                            None => return Ok(ExecuteStatus::NeedInput),
+                        };
+                        match event.opcode {
+                            Opcode::Out => return Ok(ExecuteStatus::Output),
+                            Opcode::Halt => return Ok(ExecuteStatus::Halted),
+                            _ => continue,
+                        }
+                    }
+                },
+            };
+
+            match (op.handler)(self, &op)? {
+                Flow::Continue => {}
+                Flow::NeedInput => return Ok(ExecuteStatus::NeedInput),
+                Flow::Output => return Ok(ExecuteStatus::Output),
+                Flow::Halted => return Ok(ExecuteStatus::Halted),
+            }
+        }
+    }
+
+    /// Throws away any compiled op that was decoded from `address`.
+    pub(super) fn invalidate(&mut self, address: usize) {
+        for start in address.saturating_sub(3) ..= address {
+            if let Some(Some(op)) = self.compiled.get(start) {
+                if start + op.length > address {
+                    self.compiled[start] = None;
+                }
+            }
+        }
+    }
+
+    fn load(&mut self, operand: Operand) -> Result<i64, VmError> {
+        let address = match operand {
+            Operand::Immediate(value) => return Ok(value),
+            Operand::Position(address) => address,
+            Operand::Relative(offset) => self.bp.wrapping_add(offset),
+        };
+        let address = self.address(&address)?;
+        Ok(*self.memory.get(address))
+    }
+
+    /// The address a write to `operand` goes to.
+    fn target(&self, operand: Operand) -> Result<usize, VmError> {
+        let address = match operand {
+            Operand::Position(address) => address,
+            Operand::Relative(offset) => self.bp.wrapping_add(offset),
+            Operand::Immediate(_) => unreachable!("compile() doesn't allow immediate writes"),
+        };
+        self.address(&address)
+    }
+
+    fn store(&mut self, operand: Operand, value: i64) -> Result<(), VmError> {
+        let address = self.target(operand)?;
+        *self.memory.get_mut(address) = value;
+        self.invalidate(address);
+        Ok(())
+    }
+
+    /// Moves on to `ip` after a successful op.
+    fn finish(&mut self, ip: usize) -> Result<Flow, VmError> {
+        self.ip = ip;
+        self.cycles += 1;
+        if let Some(budget) = &mut self.budget {
+            budget.spend();
+        }
+        Ok(Flow::Continue)
+    }
+}
+
+fn add<D: IoDevice>(vm: &mut VM<D>, op: &Op<D>) -> Result<Flow, VmError> {
+    let a = vm.load(op.args[0])?;
+    let b = vm.load(op.args[1])?;
+    let sum = vm.overflow.add(a, b).ok_or_else(|| VmError::Overflow(vm.fault()))?;
+    vm.store(op.args[2], sum)?;
+    vm.finish(vm.ip + 4)
+}
+
+fn mul<D: IoDevice>(vm: &mut VM<D>, op: &Op<D>) -> Result<Flow, VmError> {
+    let a = vm.load(op.args[0])?;
+    let b = vm.load(op.args[1])?;
+    let product = vm.overflow.mul(a, b).ok_or_else(|| VmError::Overflow(vm.fault()))?;
+    vm.store(op.args[2], product)?;
+    vm.finish(vm.ip + 4)
+}
+
+fn input<D: IoDevice>(vm: &mut VM<D>, op: &Op<D>) -> Result<Flow, VmError> {
+    // Check where it's going before reading it, so a bad address doesn't
+    // lose the input.
+    vm.target(op.args[0])?;
+    let value = match vm.io.read() {
+        Some(value) => value,
+        None => return Ok(Flow::NeedInput),
+    };
+    vm.store(op.args[0], value)?;
+    vm.finish(vm.ip + 2)
+}
+
+fn output<D: IoDevice>(vm: &mut VM<D>, op: &Op<D>) -> Result<Flow, VmError> {
+    let value = vm.load(op.args[0])?;
+    vm.io.write(value);
+    vm.outputs_written += 1;
+    vm.finish(vm.ip + 2)?;
+    Ok(Flow::Output)
+}
+
+fn jump<D: IoDevice>(vm: &mut VM<D>, op: &Op<D>, taken: bool) -> Result<Flow, VmError> {
+    if !taken {
+        return vm.finish(vm.ip + 3);
+    }
+    let target = vm.load(op.args[1])?;
+    let target = vm.address(&target)?;
+    vm.finish(target)
+}
+
+fn jump_if_true<D: IoDevice>(vm: &mut VM<D>, op: &Op<D>) -> Result<Flow, VmError> {
+    let value = vm.load(op.args[0])?;
+    jump(vm, op, value != 0)
+}
+
+fn jump_if_false<D: IoDevice>(vm: &mut VM<D>, op: &Op<D>) -> Result<Flow, VmError> {
+    let value = vm.load(op.args[0])?;
+    jump(vm, op, value == 0)
+}
+
+fn less_than<D: IoDevice>(vm: &mut VM<D>, op: &Op<D>) -> Result<Flow, VmError> {
+    let a = vm.load(op.args[0])?;
+    let b = vm.load(op.args[1])?;
+    vm.store(op.args[2], if a < b { 1 } else { 0 })?;
+    vm.finish(vm.ip + 4)
+}
+
+fn equals<D: IoDevice>(vm: &mut VM<D>, op: &Op<D>) -> Result<Flow, VmError> {
+    let a = vm.load(op.args[0])?;
+    let b = vm.load(op.args[1])?;
+    vm.store(op.args[2], if a == b { 1 } else { 0 })?;
+    vm.finish(vm.ip + 4)
+}
+
+fn adjust_base<D: IoDevice>(vm: &mut VM<D>, op: &Op<D>) -> Result<Flow, VmError> {
+    vm.bp = vm.bp.wrapping_add(vm.load(op.args[0])?);
+    vm.finish(vm.ip + 2)
+}
+
+fn halt<D: IoDevice>(vm: &mut VM<D>, _op: &Op<D>) -> Result<Flow, VmError> {
+    vm.finish(vm.ip)?;
+    Ok(Flow::Halted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::Program;
+
+    /// Runs to completion, returning the output, final memory and cycles.
+    fn run(program: &Program, threaded: bool) -> (Result<Vec<i64>, VmError>, Vec<i64>, usize) {
+        let mut vm = VM::new(program);
+        vm.threaded = threaded;
+        vm.send_input(5);
+
+        let mut output = Vec::new();
+        let result = loop {
+            match vm.execute() {
+                Ok(ExecuteStatus::Output) => output.push(vm.recv_output().unwrap()),
+                Ok(ExecuteStatus::Halted) => break Ok(output),
+                Ok(status) => panic!("Unexpected {:?}", status),
+                Err(err) => break Err(err),
+            }
+        };
+        (result, vm.memory().to_vec(), vm.cycles)
+    }
+
+    #[test]
+    fn recompiles_self_modified_code() {
+        // `out #1`, then bump that 1 and go round again until it's 4.
+        let program = Program::new(vec![
+            104,1,
+            1001,1,1,1,
+            1007,1,4,20,
+            1005,20,0,
+            99,
+        ]);
+
+        assert_eq!(run(&program, true).0, Ok(vec![1, 2, 3]));
+        assert_eq!(run(&program, true), run(&program, false));
+    }
+
+    #[test]
+    fn matches_the_interpreter() {
+        let programs = vec![
+            // day 9's quine, which grows memory through relative writes.
+            vec![109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99],
+            // Reads past the end of memory don't grow it.
+            vec![3,50,4,60,99],
+            // Negative addresses, an immediate write, and a bad opcode.
+            vec![3,0,4,-1,99],
+            vec![3,0,11101,1,1,7,99,0],
+            vec![3,0,42,99],
+            // An instruction that runs off the end of memory.
+            vec![3,10,1001,10,1],
+        ];
+
+        for code in programs {
+            let program = Program::new(code);
+            assert_eq!(run(&program, true), run(&program, false), "{}", program);
+        }
+    }
+}
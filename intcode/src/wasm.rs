@@ -0,0 +1,880 @@
+//! Compiles an Intcode program ahead of time into a standalone WebAssembly
+//! module, which runs under any WASI runtime:
+//!
+//! ```text
+//! $ cargo run --bin intcode-wasm ../input/input09 > boost.wat
+//! $ echo 1 | wasmtime boost.wat
+//! ```
+//!
+//! Intcode memory lives in linear memory, one `i64` per cell, initialized
+//! from the program. Each basic block the control-flow graph finds becomes a
+//! case of one big `br_table` switch inside a `$dispatch` loop, in address
+//! order, so falling through to the next block is free and jumps to a later
+//! block are a direct `br`. Everything else goes back through `$dispatch`
+//! with `$ip` set: jumps backwards, jumps through memory (like returns), and
+//! jumps to anything that isn't the start of a block.
+//!
+//! `$dispatch` hands any address it doesn't have a block for to a plain
+//! interpreter, which runs one instruction and goes back round. It also
+//! handles self-modifying code: there's a map of which cells hold compiled
+//! instructions, and once the program writes to one of those, the compiled
+//! code is out of date and everything from then on is interpreted.
+//!
+//! Input and output are numbers in text, one per line, through the same
+//! `wasi_unstable` `fd_read`/`fd_write` imports as `day02/intcode.wat`.
+//! Anything the VM would return an error for (a negative address, a bad
+//! opcode, running out of input) is a trap.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::cfg::ControlFlowGraph;
+use crate::instruction::{Instruction, Opcode, ParameterMode};
+use crate::vm::Program;
+
+mod module;
+
+use module::{Func, Import, Ins, Module, ValType};
+use Ins::*;
+use ValType::{I32, I64};
+
+/// Where the map of compiled cells starts. Everything before it is scratch
+/// space for I/O: an iovec at 0, a one character buffer at 8, the number of
+/// bytes read or written at 12, and a buffer for printing numbers at 16..48.
+const CODE_MAP: u32 = 64;
+
+/// How far past the end of the program to start with memory for, so that
+/// most programs never have to grow it.
+const HEADROOM: usize = 1024;
+
+/// An Intcode program compiled to WebAssembly. Displays as the text format
+/// (`.wat`), and `to_bytes()` gives the binary format (`.wasm`).
+#[derive(Debug, Clone)]
+pub struct WasmModule {
+    module: Module,
+}
+
+impl WasmModule {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.module.to_bytes()
+    }
+}
+
+impl fmt::Display for WasmModule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.module)
+    }
+}
+
+/// Where everything goes in linear memory.
+#[derive(Debug, Copy, Clone)]
+struct Layout {
+    /// Length of the program.
+    len: usize,
+    /// Where the Intcode cells start.
+    cells: u32,
+    pages: u32,
+    /// Cells that fit in the memory we start with. Those can be read and
+    /// written directly, without checking.
+    preallocated: usize,
+    /// Addresses from here on are too big for a 32-bit memory.
+    max_cells: i64,
+}
+
+impl Layout {
+    fn new(len: usize) -> Self {
+        let cells = (CODE_MAP + len as u32).div_ceil(8) * 8;
+        let bytes = cells as usize + 8 * (len + HEADROOM);
+        let pages = bytes.div_ceil(0x10000) as u32;
+        let preallocated = (pages as usize * 0x10000 - cells as usize) / 8;
+        let max_cells = (i64::from(i32::MAX) + 1 - i64::from(cells)) / 8;
+        Layout { len, cells, pages, preallocated, max_cells }
+    }
+
+    fn cell(&self, address: usize) -> i32 {
+        (self.cells as usize + address * 8) as i32
+    }
+}
+
+pub fn compile_wasm(program: &Program) -> WasmModule {
+    let code = program.code();
+    let cfg = ControlFlowGraph::new(program);
+    let layout = Layout::new(code.len());
+
+    let mut code_map = vec![0u8; code.len()];
+    for block in cfg.blocks.values() {
+        for compiled in &mut code_map[block.start .. block.end.min(code.len())] {
+            *compiled = 1;
+        }
+    }
+
+    let mut data = code_map.clone();
+    data.resize((layout.cells - CODE_MAP) as usize, 0);
+    for value in code {
+        data.extend(&value.to_le_bytes());
+    }
+
+    let mut compiler = Compiler { cfg: &cfg, layout, code_map, body: Vec::new() };
+    compiler.compile();
+
+    let module = Module {
+        imports: vec![
+            wasi_import("fd_read"),
+            wasi_import("fd_write"),
+        ],
+        memory_pages: layout.pages,
+        globals: vec!["modified"],
+        data_offset: CODE_MAP,
+        data,
+        funcs: vec![
+            Func {
+                name: "run",
+                params: vec![],
+                result: None,
+                locals: vec![("ip", I64), ("bp", I64), ("op", I64)],
+                body: compiler.body,
+            },
+            param_func(),
+            load_func(),
+            store_func(&layout),
+            cell_func(&layout),
+            input_func(),
+            get_char_func(),
+            output_func(),
+        ],
+        exports: vec![("_start", "run")],
+    };
+
+    WasmModule { module }
+}
+
+fn wasi_import(name: &'static str) -> Import {
+    Import {
+        module: "wasi_unstable",
+        name,
+        func: name,
+        params: vec![I32, I32, I32, I32],
+        result: Some(I32),
+    }
+}
+
+struct Compiler<'a> {
+    cfg: &'a ControlFlowGraph,
+    layout: Layout,
+    /// 1 for every cell that's part of a compiled instruction.
+    code_map: Vec<u8>,
+    body: Vec<Ins>,
+}
+
+impl Compiler<'_> {
+    fn compile(&mut self) {
+        let len = self.layout.len;
+        let targets: Vec<String> = (0 .. len)
+            .map(|address| match self.cfg.blocks.contains_key(&address) {
+                true => block_label(address),
+                false => "interpret".to_string(),
+            })
+            .collect();
+
+        self.body.extend(vec![
+            Loop("dispatch".to_string()),
+            Block("interpret".to_string()),
+            GlobalGet("modified"),
+            BrIf("interpret".to_string()),
+            LocalGet("ip"),
+            I64Const(len as i64),
+            Op("i64.ge_u"),
+            BrIf("interpret".to_string()),
+        ]);
+        for &start in self.cfg.blocks.keys().rev() {
+            self.body.push(Block(block_label(start)));
+        }
+        self.body.extend(vec![
+            LocalGet("ip"),
+            Op("i32.wrap_i64"),
+            BrTable(targets, "interpret".to_string()),
+        ]);
+
+        let starts: Vec<usize> = self.cfg.blocks.keys().copied().collect();
+        for (index, &start) in starts.iter().enumerate() {
+            self.body.push(End);
+            let block = &self.cfg.blocks[&start];
+            let mut finished = false;
+            for &address in &block.instructions {
+                finished = self.instruction(start, address);
+                if finished {
+                    break;
+                }
+            }
+            if !finished && starts.get(index + 1) != Some(&block.end) {
+                self.goto_dispatch(block.end);
+            }
+        }
+
+        self.body.push(End);
+        self.body.push(Comment("Anything else is interpreted, one instruction at a time.".to_string()));
+        self.body.extend(interpreter());
+        self.body.push(Br("dispatch".to_string()));
+        self.body.push(End);
+    }
+
+    /// Compiles the instruction at `address`, in the block starting at
+    /// `block`. Returns whether it always leaves the block.
+    fn instruction(&mut self, block: usize, address: usize) -> bool {
+        let code = self.cfg.disassembly().code();
+        let inst = Instruction::try_from(code[address]).unwrap();
+        let next = address + inst.length();
+        let arg = |param: usize| (inst.param_mode(param), code[address + param + 1]);
+
+        self.body.push(Comment(format!("{}: {}", address, self.cfg.disassembly().instruction(address))));
+
+        match inst.opcode() {
+            Opcode::Add | Opcode::Mul | Opcode::Lt | Opcode::Eql => {
+                let mut value = self.value(arg(0));
+                value.extend(self.value(arg(1)));
+                match inst.opcode() {
+                    Opcode::Add => value.push(Op("i64.add")),
+                    Opcode::Mul => value.push(Op("i64.mul")),
+                    Opcode::Lt => value.extend(vec![Op("i64.lt_s"), Op("i64.extend_i32_u")]),
+                    _ => value.extend(vec![Op("i64.eq"), Op("i64.extend_i32_u")]),
+                }
+                self.store(arg(2), value, next)
+            }
+            Opcode::In => self.store(arg(0), vec![Call("input")], next),
+            Opcode::Out => {
+                let value = self.value(arg(0));
+                self.body.extend(value);
+                self.body.push(Call("output"));
+                false
+            }
+            Opcode::JmpT | Opcode::JmpF => {
+                let jump_if_true = inst.opcode() == Opcode::JmpT;
+                match arg(0) {
+                    (ParameterMode::Immediate, value) if (value != 0) == jump_if_true => {
+                        self.jump(block, arg(1));
+                        true
+                    }
+                    (ParameterMode::Immediate, _) => false,
+                    condition => {
+                        let value = self.value(condition);
+                        self.body.extend(value);
+                        match jump_if_true {
+                            true => self.body.extend(vec![I64Const(0), Op("i64.ne")]),
+                            false => self.body.push(Op("i64.eqz")),
+                        }
+                        self.body.push(If);
+                        self.jump(block, arg(1));
+                        self.body.push(End);
+                        false
+                    }
+                }
+            }
+            Opcode::Base => {
+                let value = self.value(arg(0));
+                self.body.push(LocalGet("bp"));
+                self.body.extend(value);
+                self.body.extend(vec![Op("i64.add"), LocalSet("bp")]);
+                false
+            }
+            Opcode::Halt => {
+                self.body.push(Return);
+                true
+            }
+        }
+    }
+
+    /// Pushes a parameter's value.
+    fn value(&self, (mode, raw): (ParameterMode, i64)) -> Vec<Ins> {
+        match mode {
+            ParameterMode::Immediate => vec![I64Const(raw)],
+            ParameterMode::Position => match usize::try_from(raw) {
+                Ok(address) if address < self.layout.preallocated => {
+                    vec![I32Const(self.layout.cell(address)), Op("i64.load")]
+                }
+                _ => vec![I64Const(raw), Call("load")],
+            },
+            ParameterMode::Relative => {
+                vec![LocalGet("bp"), I64Const(raw), Op("i64.add"), Call("load")]
+            }
+        }
+    }
+
+    /// Writes `value` to a parameter. If that might have changed compiled
+    /// code, carries on at `next` in the interpreter. Returns whether it
+    /// always does.
+    fn store(&mut self, (mode, raw): (ParameterMode, i64), value: Vec<Ins>, next: usize) -> bool {
+        match mode {
+            ParameterMode::Immediate => {
+                self.body.push(Unreachable);
+                true
+            }
+            ParameterMode::Position => match usize::try_from(raw) {
+                Ok(address) if address < self.layout.preallocated => {
+                    self.body.push(I32Const(self.layout.cell(address)));
+                    self.body.extend(value);
+                    self.body.push(Op("i64.store"));
+                    if self.code_map.get(address) == Some(&1) {
+                        self.body.extend(vec![I32Const(1), GlobalSet("modified")]);
+                        self.goto_dispatch(next);
+                        return true;
+                    }
+                    false
+                }
+                _ => {
+                    self.body.push(I64Const(raw));
+                    self.body.extend(value);
+                    self.body.push(Call("store"));
+                    if raw >= 0 && (raw as usize) < self.layout.len {
+                        self.check_modified(next);
+                    }
+                    false
+                }
+            },
+            ParameterMode::Relative => {
+                self.body.extend(vec![LocalGet("bp"), I64Const(raw), Op("i64.add")]);
+                self.body.extend(value);
+                self.body.push(Call("store"));
+                self.check_modified(next);
+                false
+            }
+        }
+    }
+
+    fn check_modified(&mut self, next: usize) {
+        self.body.extend(vec![GlobalGet("modified"), If]);
+        self.goto_dispatch(next);
+        self.body.push(End);
+    }
+
+    /// Jumps to the target in a jump instruction's second parameter.
+    fn jump(&mut self, block: usize, target: (ParameterMode, i64)) {
+        match target {
+            (ParameterMode::Immediate, target) if target < 0 => self.body.push(Unreachable),
+            (ParameterMode::Immediate, target) => {
+                let target = target as usize;
+                if target > block && self.cfg.blocks.contains_key(&target) {
+                    self.body.push(Br(block_label(target)));
+                } else {
+                    self.goto_dispatch(target);
+                }
+            }
+            target => {
+                let value = self.value(target);
+                self.body.extend(value);
+                self.body.extend(vec![LocalSet("ip"), Br("dispatch".to_string())]);
+            }
+        }
+    }
+
+    fn goto_dispatch(&mut self, address: usize) {
+        self.body.extend(vec![
+            I64Const(address as i64),
+            LocalSet("ip"),
+            Br("dispatch".to_string()),
+        ]);
+    }
+}
+
+fn block_label(address: usize) -> String {
+    format!("L{}", address)
+}
+
+/// Runs the instruction at `$ip`, for the end of `$run`.
+fn interpreter() -> Vec<Ins> {
+    // The address of parameter `n`, for reading or writing.
+    let param = |n: i64, write: bool| vec![
+        LocalGet("ip"),
+        LocalGet("bp"),
+        I64Const(n),
+        I32Const(write as i32),
+        Call("param"),
+    ];
+    let read = |n: i64| {
+        let mut read = param(n, false);
+        read.push(Call("load"));
+        read
+    };
+    let advance = |length: i64| vec![
+        LocalGet("ip"),
+        I64Const(length),
+        Op("i64.add"),
+        LocalSet("ip"),
+    ];
+
+    let mut body = vec![
+        LocalGet("ip"),
+        Call("load"),
+        LocalTee("op"),
+        I64Const(0),
+        Op("i64.lt_s"),
+        If,
+        Unreachable,
+        End,
+        LocalGet("op"),
+        I64Const(100),
+        Op("i64.rem_u"),
+        LocalTee("op"),
+        I64Const(99),
+        Op("i64.eq"),
+        If,
+        Return,
+        End,
+    ];
+
+    let opcodes: Vec<Opcode> = (1 ..= 9).map(|op| Opcode::try_from(op).unwrap()).collect();
+    body.push(Block("bad".to_string()));
+    for opcode in opcodes.iter().rev() {
+        body.push(Block(opcode.mnemonic().to_string()));
+    }
+    body.extend(vec![
+        LocalGet("op"),
+        I64Const(1),
+        Op("i64.sub"),
+        Op("i32.wrap_i64"),
+        BrTable(opcodes.iter().map(|opcode| opcode.mnemonic().to_string()).collect(), "bad".to_string()),
+    ]);
+
+    for opcode in opcodes {
+        body.push(End);
+        match opcode {
+            Opcode::Add | Opcode::Mul | Opcode::Lt | Opcode::Eql => {
+                body.extend(param(3, true));
+                body.extend(read(1));
+                body.extend(read(2));
+                match opcode {
+                    Opcode::Add => body.push(Op("i64.add")),
+                    Opcode::Mul => body.push(Op("i64.mul")),
+                    Opcode::Lt => body.extend(vec![Op("i64.lt_s"), Op("i64.extend_i32_u")]),
+                    _ => body.extend(vec![Op("i64.eq"), Op("i64.extend_i32_u")]),
+                }
+                body.push(Call("store"));
+                body.extend(advance(4));
+            }
+            Opcode::In => {
+                body.extend(param(1, true));
+                body.extend(vec![Call("input"), Call("store")]);
+                body.extend(advance(2));
+            }
+            Opcode::Out => {
+                body.extend(read(1));
+                body.push(Call("output"));
+                body.extend(advance(2));
+            }
+            Opcode::JmpT | Opcode::JmpF => {
+                body.extend(read(1));
+                body.push(Op("i64.eqz"));
+                body.push(If);
+                let (falsy, truthy) = match opcode {
+                    Opcode::JmpT => (advance(3), read(2)),
+                    _ => (read(2), advance(3)),
+                };
+                body.extend(falsy);
+                if opcode == Opcode::JmpF {
+                    body.push(LocalSet("ip"));
+                }
+                body.push(Else);
+                body.extend(truthy);
+                if opcode == Opcode::JmpT {
+                    body.push(LocalSet("ip"));
+                }
+                body.push(End);
+            }
+            Opcode::Base => {
+                body.push(LocalGet("bp"));
+                body.extend(read(1));
+                body.extend(vec![Op("i64.add"), LocalSet("bp")]);
+                body.extend(advance(2));
+            }
+            Opcode::Halt => unreachable!(),
+        }
+        body.push(Br("dispatch".to_string()));
+    }
+
+    body.push(End);
+    body.push(Unreachable);
+    body
+}
+
+/// `$param(ip, bp, n, write)`: the address of the `n`th parameter of the
+/// instruction at `ip`. Traps on a bad mode, or an immediate one if it's
+/// going to be written to.
+fn param_func() -> Func {
+    Func {
+        name: "param",
+        params: vec![("ip", I64), ("bp", I64), ("n", I64), ("write", I32)],
+        result: Some(I64),
+        locals: vec![("mode", I64)],
+        body: vec![
+            LocalGet("ip"),
+            Call("load"),
+            // 100, 1000 or 10000 for the 1st, 2nd or 3rd parameter.
+            I64Const(100),
+            I64Const(1000),
+            I64Const(10000),
+            LocalGet("n"),
+            I64Const(2),
+            Op("i64.eq"),
+            Op("select"),
+            LocalGet("n"),
+            I64Const(1),
+            Op("i64.eq"),
+            Op("select"),
+            Op("i64.div_u"),
+            I64Const(10),
+            Op("i64.rem_u"),
+            LocalSet("mode"),
+            LocalGet("ip"),
+            LocalGet("n"),
+            Op("i64.add"),
+            LocalSet("ip"),
+            LocalGet("mode"),
+            Op("i64.eqz"),
+            If,
+            LocalGet("ip"),
+            Call("load"),
+            Return,
+            End,
+            LocalGet("mode"),
+            I64Const(1),
+            Op("i64.eq"),
+            If,
+            LocalGet("write"),
+            If,
+            Unreachable,
+            End,
+            LocalGet("ip"),
+            Return,
+            End,
+            LocalGet("mode"),
+            I64Const(2),
+            Op("i64.eq"),
+            If,
+            LocalGet("bp"),
+            LocalGet("ip"),
+            Call("load"),
+            Op("i64.add"),
+            Return,
+            End,
+            Unreachable,
+        ],
+    }
+}
+
+fn load_func() -> Func {
+    Func {
+        name: "load",
+        params: vec![("address", I64)],
+        result: Some(I64),
+        locals: vec![],
+        body: vec![
+            LocalGet("address"),
+            Call("cell"),
+            Op("i64.load"),
+        ],
+    }
+}
+
+/// Also sets `$modified` if the cell holds compiled code.
+fn store_func(layout: &Layout) -> Func {
+    Func {
+        name: "store",
+        params: vec![("address", I64), ("value", I64)],
+        result: None,
+        locals: vec![],
+        body: vec![
+            LocalGet("address"),
+            Call("cell"),
+            LocalGet("value"),
+            Op("i64.store"),
+            LocalGet("address"),
+            I64Const(layout.len as i64),
+            Op("i64.lt_u"),
+            If,
+            I32Const(CODE_MAP as i32),
+            LocalGet("address"),
+            Op("i32.wrap_i64"),
+            Op("i32.add"),
+            Op("i32.load8_u"),
+            If,
+            I32Const(1),
+            GlobalSet("modified"),
+            End,
+            End,
+        ],
+    }
+}
+
+/// `$cell(address)`: where in linear memory a cell is, growing memory to
+/// fit if need be, like the VM does. Traps on a negative address.
+fn cell_func(layout: &Layout) -> Func {
+    Func {
+        name: "cell",
+        params: vec![("address", I64)],
+        result: Some(I32),
+        locals: vec![("offset", I32)],
+        body: vec![
+            LocalGet("address"),
+            I64Const(layout.max_cells),
+            Op("i64.ge_u"),
+            If,
+            Unreachable,
+            End,
+            I32Const(layout.cells as i32),
+            LocalGet("address"),
+            Op("i32.wrap_i64"),
+            I32Const(3),
+            Op("i32.shl"),
+            Op("i32.add"),
+            LocalTee("offset"),
+            Op("memory.size"),
+            I32Const(16),
+            Op("i32.shl"),
+            Op("i32.ge_u"),
+            If,
+            LocalGet("offset"),
+            I32Const(16),
+            Op("i32.shr_u"),
+            I32Const(1),
+            Op("i32.add"),
+            Op("memory.size"),
+            Op("i32.sub"),
+            Op("memory.grow"),
+            I32Const(-1),
+            Op("i32.eq"),
+            If,
+            Unreachable,
+            End,
+            End,
+            LocalGet("offset"),
+        ],
+    }
+}
+
+/// Reads the next number from stdin, skipping anything that isn't part of
+/// one. Traps if there isn't one.
+fn input_func() -> Func {
+    Func {
+        name: "input",
+        params: vec![],
+        result: Some(I64),
+        locals: vec![("char", I32), ("number", I64), ("negative", I32), ("found", I32)],
+        body: vec![
+            Block("done".to_string()),
+            Loop("read".to_string()),
+            Call("getChar"),
+            LocalTee("char"),
+            I32Const(-1),
+            Op("i32.eq"),
+            If,
+            LocalGet("found"),
+            BrIf("done".to_string()),
+            Unreachable,
+            End,
+            LocalGet("char"),
+            I32Const(48),
+            Op("i32.sub"),
+            I32Const(9),
+            Op("i32.le_u"),
+            If,
+            I32Const(1),
+            LocalSet("found"),
+            LocalGet("number"),
+            I64Const(10),
+            Op("i64.mul"),
+            LocalGet("char"),
+            I32Const(48),
+            Op("i32.sub"),
+            Op("i64.extend_i32_u"),
+            Op("i64.add"),
+            LocalSet("number"),
+            Else,
+            LocalGet("found"),
+            BrIf("done".to_string()),
+            LocalGet("char"),
+            I32Const(45),
+            Op("i32.eq"),
+            LocalSet("negative"),
+            End,
+            Br("read".to_string()),
+            End,
+            End,
+            LocalGet("negative"),
+            If,
+            I64Const(0),
+            LocalGet("number"),
+            Op("i64.sub"),
+            Return,
+            End,
+            LocalGet("number"),
+        ],
+    }
+}
+
+/// One byte from stdin, or -1 at the end.
+fn get_char_func() -> Func {
+    Func {
+        name: "getChar",
+        params: vec![],
+        result: Some(I32),
+        locals: vec![],
+        body: vec![
+            I32Const(0),
+            I32Const(8),
+            Op("i32.store"),
+            I32Const(4),
+            I32Const(1),
+            Op("i32.store"),
+            I32Const(12),
+            I32Const(0),
+            Op("i32.store"),
+            I32Const(0),
+            I32Const(0),
+            I32Const(1),
+            I32Const(12),
+            Call("fd_read"),
+            Op("drop"),
+            I32Const(12),
+            Op("i32.load"),
+            Op("i32.eqz"),
+            If,
+            I32Const(-1),
+            Return,
+            End,
+            I32Const(8),
+            Op("i32.load8_u"),
+        ],
+    }
+}
+
+/// Prints a number and a newline to stdout.
+fn output_func() -> Func {
+    Func {
+        name: "output",
+        params: vec![("value", I64)],
+        result: None,
+        locals: vec![("start", I32), ("digit", I32), ("negative", I32)],
+        body: vec![
+            // The number is written backwards from the end of the buffer.
+            I32Const(47),
+            I32Const(10),
+            Op("i32.store8"),
+            I32Const(47),
+            LocalSet("start"),
+            LocalGet("value"),
+            I64Const(0),
+            Op("i64.lt_s"),
+            LocalSet("negative"),
+            Loop("digits".to_string()),
+            LocalGet("value"),
+            I64Const(10),
+            Op("i64.rem_s"),
+            Op("i32.wrap_i64"),
+            LocalSet("digit"),
+            LocalGet("negative"),
+            If,
+            I32Const(0),
+            LocalGet("digit"),
+            Op("i32.sub"),
+            LocalSet("digit"),
+            End,
+            LocalGet("start"),
+            I32Const(1),
+            Op("i32.sub"),
+            LocalTee("start"),
+            LocalGet("digit"),
+            I32Const(48),
+            Op("i32.add"),
+            Op("i32.store8"),
+            LocalGet("value"),
+            I64Const(10),
+            Op("i64.div_s"),
+            LocalTee("value"),
+            I64Const(0),
+            Op("i64.ne"),
+            BrIf("digits".to_string()),
+            End,
+            LocalGet("negative"),
+            If,
+            LocalGet("start"),
+            I32Const(1),
+            Op("i32.sub"),
+            LocalTee("start"),
+            I32Const(45),
+            Op("i32.store8"),
+            End,
+            I32Const(0),
+            LocalGet("start"),
+            Op("i32.store"),
+            I32Const(4),
+            I32Const(48),
+            LocalGet("start"),
+            Op("i32.sub"),
+            Op("i32.store"),
+            I32Const(1),
+            I32Const(0),
+            I32Const(1),
+            I32Const(12),
+            Call("fd_write"),
+            Op("drop"),
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    #[test]
+    fn jumps_forwards_with_br_and_backwards_through_dispatch() {
+        let program = assemble("
+                    in [n]
+            loop:   jf [n], #done
+                    out [n]
+                    add [n], #-1, [n]
+                    jt #1, #loop
+            done:   hlt
+            n:      .data 0
+        ").unwrap();
+        let text = compile_wasm(&program).to_string();
+
+        assert!(text.contains("br_table $L0 $interpret $L2 $interpret $interpret $L5 "));
+        assert!(text.contains("
+      ;; 2: jf [D15], #L14
+      i32.const 200
+      i64.load
+      i64.eqz
+      if
+        br $L14
+      end
+"));
+        assert!(text.contains("
+      ;; 11: jt #1, #L2
+      i64.const 2
+      local.set $ip
+      br $dispatch
+      end $L14
+"));
+    }
+
+    #[test]
+    fn hands_over_to_the_interpreter_after_writing_to_code() {
+        let program = assemble("
+                    add #7, #0, [patch+1]
+            patch:  out #1
+                    hlt
+        ").unwrap();
+        let module = compile_wasm(&program);
+
+        assert!(module.to_string().contains("
+      i64.store
+      i32.const 1
+      global.set $modified
+      i64.const 4
+      local.set $ip
+      br $dispatch
+      end $interpret
+"));
+        assert!(module.to_bytes().starts_with(b"\0asm\x01\0\0\0"));
+    }
+}
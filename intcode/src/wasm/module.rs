@@ -0,0 +1,550 @@
+//! Just enough of a WebAssembly module to hold what the compiler generates,
+//! and to write it out as either text (`.wat`) or binary (`.wasm`).
+//!
+//! Instructions are kept flat, the way they're laid out in the binary format,
+//! with `Block`/`Loop`/`If` ... `End` around nested code. Branches refer to
+//! labels by name, and get turned into depths when the binary is written.
+
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub(super) enum ValType {
+    I32,
+    I64,
+}
+
+impl ValType {
+    fn name(self) -> &'static str {
+        match self {
+            ValType::I32 => "i32",
+            ValType::I64 => "i64",
+        }
+    }
+
+    fn byte(self) -> u8 {
+        match self {
+            ValType::I32 => 0x7f,
+            ValType::I64 => 0x7e,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum Ins {
+    /// Only shows up in the text format.
+    Comment(String),
+    Block(String),
+    Loop(String),
+    If,
+    Else,
+    End,
+    Br(String),
+    BrIf(String),
+    BrTable(Vec<String>, String),
+    Return,
+    Unreachable,
+    Call(&'static str),
+    LocalGet(&'static str),
+    LocalSet(&'static str),
+    LocalTee(&'static str),
+    GlobalGet(&'static str),
+    GlobalSet(&'static str),
+    I32Const(i32),
+    I64Const(i64),
+    /// Anything that takes no immediates (apart from a default memarg), by
+    /// its text name, e.g. `Op("i64.add")` or `Op("i64.load")`.
+    Op(&'static str),
+}
+
+/// Binary encoding of everything `Ins::Op` can be.
+fn op_bytes(name: &str) -> &'static [u8] {
+    match name {
+        "drop" => &[0x1a],
+        "select" => &[0x1b],
+        "i32.load" => &[0x28, 2, 0],
+        "i64.load" => &[0x29, 3, 0],
+        "i32.load8_u" => &[0x2d, 0, 0],
+        "i32.store" => &[0x36, 2, 0],
+        "i64.store" => &[0x37, 3, 0],
+        "i32.store8" => &[0x3a, 0, 0],
+        "memory.size" => &[0x3f, 0],
+        "memory.grow" => &[0x40, 0],
+        "i32.eqz" => &[0x45],
+        "i32.eq" => &[0x46],
+        "i32.ge_u" => &[0x4f],
+        "i32.le_u" => &[0x4d],
+        "i64.eqz" => &[0x50],
+        "i64.eq" => &[0x51],
+        "i64.ne" => &[0x52],
+        "i64.lt_s" => &[0x53],
+        "i64.lt_u" => &[0x54],
+        "i64.ge_u" => &[0x5a],
+        "i32.add" => &[0x6a],
+        "i32.sub" => &[0x6b],
+        "i32.shl" => &[0x74],
+        "i32.shr_u" => &[0x76],
+        "i64.add" => &[0x7c],
+        "i64.sub" => &[0x7d],
+        "i64.mul" => &[0x7e],
+        "i64.div_s" => &[0x7f],
+        "i64.div_u" => &[0x80],
+        "i64.rem_s" => &[0x81],
+        "i64.rem_u" => &[0x82],
+        "i32.wrap_i64" => &[0xa7],
+        "i64.extend_i32_u" => &[0xad],
+        _ => panic!("No encoding for {}", name),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct Import {
+    pub module: &'static str,
+    pub name: &'static str,
+    pub func: &'static str,
+    pub params: Vec<ValType>,
+    pub result: Option<ValType>,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct Func {
+    pub name: &'static str,
+    pub params: Vec<(&'static str, ValType)>,
+    pub result: Option<ValType>,
+    pub locals: Vec<(&'static str, ValType)>,
+    pub body: Vec<Ins>,
+}
+
+/// A module with one memory (exported as `memory`) and one data segment.
+/// Globals are all mutable `i32`s starting at 0.
+#[derive(Debug, Clone)]
+pub(super) struct Module {
+    pub imports: Vec<Import>,
+    pub memory_pages: u32,
+    pub globals: Vec<&'static str>,
+    pub data_offset: u32,
+    pub data: Vec<u8>,
+    pub funcs: Vec<Func>,
+    /// Export name and function name.
+    pub exports: Vec<(&'static str, &'static str)>,
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "(module")?;
+
+        for import in &self.imports {
+            writeln!(f, "  (import \"{}\" \"{}\" (func ${}{}))",
+                     import.module, import.name, import.func,
+                     signature(&import.params, import.result))?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "  (memory (export \"memory\") {})", self.memory_pages)?;
+        for global in &self.globals {
+            writeln!(f, "  (global ${} (mut i32) (i32.const 0))", global)?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "  (data (i32.const {})", self.data_offset)?;
+        for chunk in self.data.chunks(32) {
+            let escaped: String = chunk.iter().map(|byte| format!("\\{:02x}", byte)).collect();
+            writeln!(f, "    \"{}\"", escaped)?;
+        }
+        writeln!(f, "  )")?;
+
+        for func in &self.funcs {
+            writeln!(f)?;
+            write!(f, "  (func ${}", func.name)?;
+            for (name, ty) in &func.params {
+                write!(f, " (param ${} {})", name, ty.name())?;
+            }
+            if let Some(ty) = func.result {
+                write!(f, " (result {})", ty.name())?;
+            }
+            writeln!(f)?;
+            for (name, ty) in &func.locals {
+                writeln!(f, "    (local ${} {})", name, ty.name())?;
+            }
+            write_body(f, &func.body)?;
+            writeln!(f, "  )")?;
+        }
+
+        writeln!(f)?;
+        for (name, func) in &self.exports {
+            writeln!(f, "  (export \"{}\" (func ${}))", name, func)?;
+        }
+        writeln!(f, ")")
+    }
+}
+
+fn signature(params: &[ValType], result: Option<ValType>) -> String {
+    let mut signature = String::new();
+    if !params.is_empty() {
+        let params: Vec<_> = params.iter().map(|ty| ty.name()).collect();
+        write!(signature, " (param {})", params.join(" ")).unwrap();
+    }
+    if let Some(ty) = result {
+        write!(signature, " (result {})", ty.name()).unwrap();
+    }
+    signature
+}
+
+/// Loops and ifs are indented, but blocks aren't: the compiled code is a
+/// few hundred blocks deep, and it reads better flat with labelled `end`s.
+fn write_body(f: &mut fmt::Formatter<'_>, body: &[Ins]) -> fmt::Result {
+    use Ins::*;
+
+    let mut indent = 4;
+    // The label of everything open (ifs don't have one), and whether its
+    // body is indented.
+    let mut open: Vec<(Option<&str>, bool)> = Vec::new();
+
+    for ins in body {
+        if let End = ins {
+            if open.last().unwrap().1 {
+                indent -= 2;
+            }
+        }
+        let pad = " ".repeat(indent);
+
+        match ins {
+            Comment(text) => writeln!(f, "{};; {}", pad, text)?,
+            Block(label) => {
+                writeln!(f, "{}block ${}", pad, label)?;
+                open.push((Some(label), false));
+            }
+            Loop(label) => {
+                writeln!(f, "{}loop ${}", pad, label)?;
+                open.push((Some(label), true));
+                indent += 2;
+            }
+            If => {
+                writeln!(f, "{}if", pad)?;
+                open.push((None, true));
+                indent += 2;
+            }
+            Else => writeln!(f, "{}else", &pad[2..])?,
+            End => match open.pop().unwrap() {
+                (Some(label), _) => writeln!(f, "{}end ${}", pad, label)?,
+                (None, _) => writeln!(f, "{}end", pad)?,
+            },
+            Br(label) => writeln!(f, "{}br ${}", pad, label)?,
+            BrIf(label) => writeln!(f, "{}br_if ${}", pad, label)?,
+            BrTable(labels, default) => {
+                write!(f, "{}br_table", pad)?;
+                for (index, label) in labels.iter().enumerate() {
+                    if index > 0 && index % 8 == 0 {
+                        write!(f, "\n{}  ", pad)?;
+                    }
+                    write!(f, " ${}", label)?;
+                }
+                writeln!(f, " ${}", default)?;
+            }
+            Return => writeln!(f, "{}return", pad)?,
+            Unreachable => writeln!(f, "{}unreachable", pad)?,
+            Call(func) => writeln!(f, "{}call ${}", pad, func)?,
+            LocalGet(name) => writeln!(f, "{}local.get ${}", pad, name)?,
+            LocalSet(name) => writeln!(f, "{}local.set ${}", pad, name)?,
+            LocalTee(name) => writeln!(f, "{}local.tee ${}", pad, name)?,
+            GlobalGet(name) => writeln!(f, "{}global.get ${}", pad, name)?,
+            GlobalSet(name) => writeln!(f, "{}global.set ${}", pad, name)?,
+            I32Const(value) => writeln!(f, "{}i32.const {}", pad, value)?,
+            I64Const(value) => writeln!(f, "{}i64.const {}", pad, value)?,
+            Op(name) => writeln!(f, "{}{}", pad, name)?,
+        }
+    }
+
+    Ok(())
+}
+
+type FuncType = (Vec<ValType>, Option<ValType>);
+
+impl Module {
+    /// The binary format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut types: Vec<FuncType> = Vec::new();
+        let mut type_of = |params: Vec<ValType>, result: Option<ValType>| {
+            let ty = (params, result);
+            match types.iter().position(|known| *known == ty) {
+                Some(index) => index,
+                None => {
+                    types.push(ty);
+                    types.len() - 1
+                }
+            }
+        };
+        let import_types: Vec<usize> = self.imports.iter()
+            .map(|import| type_of(import.params.clone(), import.result))
+            .collect();
+        let func_types: Vec<usize> = self.funcs.iter()
+            .map(|func| type_of(func.params.iter().map(|&(_, ty)| ty).collect(), func.result))
+            .collect();
+
+        let funcs: HashMap<&str, usize> = self.imports.iter()
+            .map(|import| import.func)
+            .chain(self.funcs.iter().map(|func| func.name))
+            .enumerate()
+            .map(|(index, name)| (name, index))
+            .collect();
+        let globals: HashMap<&str, usize> = self.globals.iter()
+            .enumerate()
+            .map(|(index, &name)| (name, index))
+            .collect();
+
+        let mut out = b"\0asm\x01\0\0\0".to_vec();
+
+        section(&mut out, 1, &types, |out, (params, result)| {
+            out.push(0x60);
+            vector(out, params, |out, ty| out.push(ty.byte()));
+            vector(out, &result.iter().collect::<Vec<_>>(), |out, ty| out.push(ty.byte()));
+        });
+
+        let imports: Vec<_> = self.imports.iter().zip(import_types).collect();
+        section(&mut out, 2, &imports, |out, (import, ty)| {
+            name(out, import.module);
+            name(out, import.name);
+            out.push(0x00);
+            unsigned(out, *ty as u64);
+        });
+
+        section(&mut out, 3, &func_types, |out, ty| unsigned(out, *ty as u64));
+
+        section(&mut out, 5, &[self.memory_pages], |out, pages| {
+            out.push(0x00);
+            unsigned(out, u64::from(*pages));
+        });
+
+        section(&mut out, 6, &self.globals, |out, _| {
+            out.extend(&[ValType::I32.byte(), 0x01, 0x41, 0x00, 0x0b]);
+        });
+
+        let mut exports = vec![("memory", 0x02, 0)];
+        exports.extend(self.exports.iter().map(|&(export, func)| (export, 0x00, funcs[func])));
+        section(&mut out, 7, &exports, |out, &(export, kind, index)| {
+            name(out, export);
+            out.push(kind);
+            unsigned(out, index as u64);
+        });
+
+        section(&mut out, 10, &self.funcs, |out, func| {
+            let code = encode_func(func, &funcs, &globals);
+            unsigned(out, code.len() as u64);
+            out.extend(code);
+        });
+
+        section(&mut out, 11, &[&self.data], |out, data| {
+            out.push(0x00);
+            out.push(0x41);
+            signed(out, i64::from(self.data_offset as i32));
+            out.push(0x0b);
+            unsigned(out, data.len() as u64);
+            out.extend(data.iter());
+        });
+
+        out
+    }
+}
+
+fn encode_func(func: &Func, funcs: &HashMap<&str, usize>, globals: &HashMap<&str, usize>) -> Vec<u8> {
+    use Ins::*;
+
+    let locals: HashMap<&str, usize> = func.params.iter()
+        .chain(&func.locals)
+        .enumerate()
+        .map(|(index, &(name, _))| (name, index))
+        .collect();
+
+    let mut out = Vec::new();
+
+    // Runs of locals with the same type.
+    let mut runs: Vec<(usize, ValType)> = Vec::new();
+    for &(_, ty) in &func.locals {
+        match runs.last_mut() {
+            Some((count, last)) if *last == ty => *count += 1,
+            _ => runs.push((1, ty)),
+        }
+    }
+    vector(&mut out, &runs, |out, &(count, ty)| {
+        unsigned(out, count as u64);
+        out.push(ty.byte());
+    });
+
+    // Labels of everything open, innermost last.
+    let mut open: Vec<Option<&str>> = Vec::new();
+    let depth = |open: &[Option<&str>], label: &str| {
+        open.iter().rev()
+            .position(|&open| open == Some(label))
+            .unwrap_or_else(|| panic!("No label ${} in ${}", label, func.name)) as u64
+    };
+
+    for ins in &func.body {
+        match ins {
+            Comment(_) => {}
+            Block(label) => {
+                out.extend(&[0x02, 0x40]);
+                open.push(Some(label));
+            }
+            Loop(label) => {
+                out.extend(&[0x03, 0x40]);
+                open.push(Some(label));
+            }
+            If => {
+                out.extend(&[0x04, 0x40]);
+                open.push(None);
+            }
+            Else => out.push(0x05),
+            End => {
+                out.push(0x0b);
+                open.pop();
+            }
+            Br(label) => {
+                out.push(0x0c);
+                unsigned(&mut out, depth(&open, label));
+            }
+            BrIf(label) => {
+                out.push(0x0d);
+                unsigned(&mut out, depth(&open, label));
+            }
+            BrTable(labels, default) => {
+                out.push(0x0e);
+                vector(&mut out, labels, |out, label| unsigned(out, depth(&open, label)));
+                unsigned(&mut out, depth(&open, default));
+            }
+            Return => out.push(0x0f),
+            Unreachable => out.push(0x00),
+            Call(name) => {
+                out.push(0x10);
+                unsigned(&mut out, funcs[name] as u64);
+            }
+            LocalGet(name) => {
+                out.push(0x20);
+                unsigned(&mut out, locals[name] as u64);
+            }
+            LocalSet(name) => {
+                out.push(0x21);
+                unsigned(&mut out, locals[name] as u64);
+            }
+            LocalTee(name) => {
+                out.push(0x22);
+                unsigned(&mut out, locals[name] as u64);
+            }
+            GlobalGet(name) => {
+                out.push(0x23);
+                unsigned(&mut out, globals[name] as u64);
+            }
+            GlobalSet(name) => {
+                out.push(0x24);
+                unsigned(&mut out, globals[name] as u64);
+            }
+            I32Const(value) => {
+                out.push(0x41);
+                signed(&mut out, i64::from(*value));
+            }
+            I64Const(value) => {
+                out.push(0x42);
+                signed(&mut out, *value);
+            }
+            Op(name) => out.extend(op_bytes(name)),
+        }
+    }
+    out.push(0x0b);
+
+    out
+}
+
+fn section<T>(out: &mut Vec<u8>, id: u8, items: &[T], encode: impl Fn(&mut Vec<u8>, &T)) {
+    if items.is_empty() {
+        return;
+    }
+    let mut contents = Vec::new();
+    vector(&mut contents, items, encode);
+    out.push(id);
+    unsigned(out, contents.len() as u64);
+    out.extend(contents);
+}
+
+fn vector<T>(out: &mut Vec<u8>, items: &[T], encode: impl Fn(&mut Vec<u8>, &T)) {
+    unsigned(out, items.len() as u64);
+    for item in items {
+        encode(out, item);
+    }
+}
+
+fn name(out: &mut Vec<u8>, name: &str) {
+    unsigned(out, name.len() as u64);
+    out.extend(name.as_bytes());
+}
+
+/// LEB128.
+fn unsigned(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Signed LEB128.
+fn signed(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let sign_bit = byte & 0x40 != 0;
+        if (value == 0 && !sign_bit) || (value == -1 && sign_bit) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_the_binary_format() {
+        use Ins::*;
+
+        let module = Module {
+            imports: vec![],
+            memory_pages: 1,
+            globals: vec!["g"],
+            data_offset: 64,
+            data: vec![1, 2],
+            funcs: vec![Func {
+                name: "f",
+                params: vec![],
+                result: Some(ValType::I64),
+                locals: vec![("a", ValType::I32), ("b", ValType::I32), ("c", ValType::I64)],
+                body: vec![
+                    Block("x".to_string()),
+                    I64Const(-129),
+                    Return,
+                    End,
+                    I64Const(300),
+                ],
+            }],
+            exports: vec![("f", "f")],
+        };
+
+        // What `wat` makes of the same module, minus the names.
+        assert_eq!(module.to_bytes(), vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+            0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7e,
+            0x03, 0x02, 0x01, 0x00,
+            0x05, 0x03, 0x01, 0x00, 0x01,
+            0x06, 0x06, 0x01, 0x7f, 0x01, 0x41, 0x00, 0x0b,
+            0x07, 0x0e, 0x02, 0x06, 0x6d, 0x65, 0x6d, 0x6f, 0x72, 0x79, 0x02, 0x00,
+                              0x01, 0x66, 0x00, 0x00,
+            0x0a, 0x12, 0x01, 0x10, 0x02, 0x02, 0x7f, 0x01, 0x7e,
+                  0x02, 0x40, 0x42, 0xff, 0x7e, 0x0f, 0x0b, 0x42, 0xac, 0x02, 0x0b,
+            0x0b, 0x09, 0x01, 0x00, 0x41, 0xc0, 0x00, 0x0b, 0x02, 0x01, 0x02,
+        ]);
+    }
+}